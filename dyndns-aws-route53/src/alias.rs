@@ -0,0 +1,205 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use serde::Deserialize;
+
+use anyhow::{Context, Result};
+use dyndns::config::ZoneConfig;
+use dyndns::name::DnsName;
+use dyndns::serde_yaml;
+
+use crate::model::{AliasTarget, Change, ChangeAction, RecordSet, RecordType};
+use crate::provider::AwsDomainName;
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub(crate) struct AliasConfig {
+    pub name: String,
+    pub target: String,
+    #[serde(default = "default_types")]
+    pub types: Vec<AliasType>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub(crate) enum AliasType {
+    A,
+    AAAA,
+}
+
+fn default_types() -> Vec<AliasType> {
+    vec![AliasType::A]
+}
+
+pub(crate) fn aliases(zone_config: &ZoneConfig) -> Result<Vec<AliasConfig>> {
+    match zone_config.options.get("aliases") {
+        Some(value) => {
+            serde_yaml::from_value(value.clone()).context("failed to read Route 53 aliases")
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+impl AliasConfig {
+    pub(crate) fn changes(&self, zone_id: &str) -> Vec<Change> {
+        self.types
+            .iter()
+            .map(|alias_type| Change {
+                action: ChangeAction::Upsert,
+                record_set: RecordSet {
+                    name: self.name.to_aws(),
+                    record_type: match alias_type {
+                        AliasType::A => RecordType::A,
+                        AliasType::AAAA => RecordType::Aaaa,
+                    },
+                    values: Vec::new(),
+                    ttl: None,
+                    alias_target: Some(AliasTarget {
+                        hosted_zone_id: zone_id.into(),
+                        dns_name: self.target.to_aws(),
+                    }),
+                },
+            })
+            .collect()
+    }
+}
+
+// Aliases already pointing at their target are left alone instead of being upserted in every run
+pub(crate) fn pending_changes(
+    aliases: &[AliasConfig],
+    zone_id: &str,
+    current: &[RecordSet],
+) -> Vec<Change> {
+    aliases
+        .iter()
+        .flat_map(|alias| alias.changes(zone_id))
+        .filter(|change| {
+            !current
+                .iter()
+                .any(|record_set| is_same_alias(record_set, &change.record_set))
+        })
+        .collect()
+}
+
+fn is_same_alias(current: &RecordSet, desired: &RecordSet) -> bool {
+    let same_target = match (&current.alias_target, &desired.alias_target) {
+        (Some(current), Some(desired)) => {
+            DnsName::from(&current.dns_name) == DnsName::from(&desired.dns_name)
+                && bare_zone_id(&current.hosted_zone_id) == bare_zone_id(&desired.hosted_zone_id)
+        }
+        _ => false,
+    };
+
+    same_target
+        && current.record_type == desired.record_type
+        && DnsName::from(&current.name) == DnsName::from(&desired.name)
+}
+
+pub(crate) fn describe(change: &Change) -> String {
+    format!(
+        "{} {} ALIAS {}",
+        change.record_set.name.as_internal(),
+        change.record_set.record_type.as_str(),
+        change
+            .record_set
+            .alias_target
+            .as_ref()
+            .map_or(String::new(), |target| target.dns_name.as_internal())
+    )
+}
+
+// e.g. /hostedzone/Z123 as configured
+fn bare_zone_id(zone_id: &str) -> &str {
+    zone_id.rsplit('/').next().unwrap_or(zone_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aliases() {
+        let zone_config: ZoneConfig = serde_yaml::from_str(
+            r#"
+records: []
+options:
+  aliases:
+    - name: example.com
+      target: home.example.com
+      types: [A, AAAA]
+    - name: www.example.com
+      target: home.example.com
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            aliases(&zone_config).unwrap(),
+            vec![
+                AliasConfig {
+                    name: "example.com".into(),
+                    target: "home.example.com".into(),
+                    types: vec![AliasType::A, AliasType::AAAA],
+                },
+                AliasConfig {
+                    name: "www.example.com".into(),
+                    target: "home.example.com".into(),
+                    types: vec![AliasType::A],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pending_changes() {
+        let alias = AliasConfig {
+            name: "example.com".into(),
+            target: "home.example.com".into(),
+            types: vec![AliasType::A, AliasType::AAAA],
+        };
+
+        let current = vec![RecordSet {
+            name: "Example.com.".into(),
+            record_type: RecordType::A,
+            values: Vec::new(),
+            ttl: None,
+            alias_target: Some(AliasTarget {
+                hosted_zone_id: "Z123".into(),
+                dns_name: "home.example.com.".into(),
+            }),
+        }];
+
+        let changes = pending_changes(&[alias], "/hostedzone/Z123", &current);
+
+        assert_eq!(1, changes.len());
+        assert_eq!(RecordType::Aaaa, changes[0].record_set.record_type);
+    }
+}
@@ -33,4 +33,9 @@
 
 pub use provider::AwsRoute53Provider;
 
+mod alias;
+mod model;
+mod pacing;
+mod partition;
 mod provider;
+mod sdk;
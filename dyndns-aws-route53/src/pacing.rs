@@ -0,0 +1,113 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::Duration;
+
+use dyndns::environment;
+use dyndns::log::warn;
+
+const DEFAULT_MAX_RETRIES: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+pub(crate) struct Pacing {
+    pub max_items: Option<u32>,
+    delay: Duration,
+    max_retries: u32,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Pacing {
+            max_items: env_var("ROUTE53_MAX_ITEMS"),
+            delay: Duration::from_millis(env_var("ROUTE53_REQUEST_DELAY").unwrap_or(0)),
+            max_retries: env_var("ROUTE53_MAX_RETRIES").unwrap_or(DEFAULT_MAX_RETRIES),
+        }
+    }
+}
+
+impl Pacing {
+    pub(crate) async fn send<T, E, F, Fut>(&self, request: F) -> Result<T, E>
+    where
+        E: Debug,
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut retries = 0;
+
+        loop {
+            if !self.delay.is_zero() {
+                tokio::time::sleep(self.delay).await;
+            }
+
+            match request().await {
+                Err(err) if retries < self.max_retries && is_throttled(&err) => {
+                    retries += 1;
+
+                    warn!(
+                        "Route 53 request throttled, retrying in {} seconds ({}/{})",
+                        backoff.as_secs(),
+                        retries,
+                        self.max_retries
+                    );
+
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_throttled<E: Debug>(err: &E) -> bool {
+    let err = format!("{:?}", err);
+    err.contains("Throttling") || err.contains("Rate exceeded")
+}
+
+fn env_var<T: std::str::FromStr>(name: &str) -> Option<T> {
+    environment::legacy_var(name).and_then(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_throttled() {
+        assert!(is_throttled(&"Throttling: Rate exceeded"));
+        assert!(!is_throttled(&"AccessDenied"));
+    }
+}
@@ -31,31 +31,32 @@
  *
  */
 
-use std::collections::HashMap;
-use std::error::Error;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::env;
 use std::ops::Add;
 use std::rc::Rc;
 use std::str::FromStr;
 
-use aws_config::meta::region::RegionProviderChain;
-use aws_sdk_route53::model::{
-    Change, ChangeAction, ChangeBatch, HostedZone, ResourceRecord, ResourceRecordSet, RrType,
-};
-use aws_sdk_route53::{Client, Region};
+use http::Uri;
 use lazy_static::lazy_static;
 use lexical::NumberFormatBuilder;
 use regex::{Captures, Regex};
 use tokio::runtime::Runtime;
 
-use dyndns::config::Config;
-use dyndns::provider::{DnsProvider, DnsRecords, DnsZones, Record, Zone};
-use dyndns::result::DynResult;
+use crate::alias::{aliases, describe, pending_changes};
+use crate::model::{Change, ChangeAction, RecordSet, RecordType};
+use crate::partition::Partition;
+use crate::sdk::Route53Client;
+
+use dyndns::environment;
+use dyndns::log::{debug, error, info, warn};
+use dyndns::prelude::{
+    Config, DnsName, DnsProvider, ProviderErrorKind, Record, Result, Zone, ZoneConfig, ZoneResults,
+};
+use dyndns::provider::DnsRecords;
 
 pub struct AwsRoute53Provider {
     runtime: Rc<Runtime>,
-    client: Client,
-    _config: aws_config::Config,
+    client: Route53Client,
 }
 
 impl Default for AwsRoute53Provider {
@@ -66,198 +67,237 @@ impl Default for AwsRoute53Provider {
 
 impl AwsRoute53Provider {
     pub fn with_runtime(runtime: Rc<Runtime>) -> Self {
-        let build_instance = async {
-            let region_provider =
-                RegionProviderChain::default_provider().or_else(Region::new("us-east-1"));
+        let client = runtime.block_on(Route53Client::new(partition(), endpoint()));
 
-            let config = aws_config::from_env().region(region_provider).load().await;
-            let client = Client::new(&config);
+        AwsRoute53Provider { runtime, client }
+    }
+}
 
-            AwsRoute53Provider {
-                runtime: Rc::clone(&runtime),
-                client,
-                _config: config,
-            }
-        };
+fn endpoint() -> Option<Uri> {
+    let endpoint = env::var("AWS_ENDPOINT_URL_ROUTE_53")
+        .or_else(|_| env::var("AWS_ENDPOINT_URL"))
+        .ok()?;
 
-        runtime.block_on(build_instance)
+    match endpoint.parse() {
+        Ok(endpoint) => Some(endpoint),
+        Err(err) => {
+            error!("Invalid Route 53 endpoint URL {}: {}", endpoint, err);
+            None
+        }
+    }
+}
+
+fn partition() -> Option<Partition> {
+    let partition = environment::legacy_var("ROUTE53_PARTITION")?;
+
+    match Partition::from_str(&partition) {
+        Ok(partition) => Some(partition),
+        Err(err) => {
+            error!("{:?}", err);
+            None
+        }
     }
 }
 
 impl DnsProvider for AwsRoute53Provider {
-    fn current(&self, config: &Config) -> DynResult<DnsZones> {
-        self.runtime.block_on(current(self, config))
+    fn name(&self) -> &str {
+        "aws-route53"
     }
 
-    fn update(&self, zone: &Zone, record: Record) -> DynResult<()> {
-        self.runtime.block_on(update(self, zone, record))
+    fn current(&self, config: &Config) -> Result<ZoneResults> {
+        self.runtime.block_on(current(self, config))
     }
-}
 
-async fn current(provider: &AwsRoute53Provider, config: &Config) -> DynResult<DnsZones> {
-    let mut aws_zones: Vec<HostedZone> = Vec::new();
-
-    let mut last_aws_zone_marker = None;
-    loop {
-        let aws_list_zones_request = provider
-            .client
-            .list_hosted_zones()
-            .set_marker(last_aws_zone_marker.clone());
-
-        match aws_list_zones_request.send().await {
-            Ok(aws_output) => {
-                aws_zones.append(
-                    &mut aws_output
-                        .hosted_zones
-                        .unwrap_or_default()
-                        .into_iter()
-                        .filter(|hosted_zone| {
-                            if let Some(hz_name) = &hosted_zone.name {
-                                config.zones.contains_key(&hz_name.as_internal())
-                            } else {
-                                false
-                            }
-                        })
-                        .collect(),
-                );
+    fn update(&self, zone: &Zone, record: Record) -> Result<()> {
+        self.runtime
+            .block_on(change(self, zone, record, ChangeAction::Upsert))
+    }
 
-                // aws_zones.append(&mut aws_output.hosted_zones.unwrap_or_default());
-                if aws_output.is_truncated {
-                    last_aws_zone_marker = aws_output.marker
-                } else {
-                    break;
-                }
-            }
-            Err(err) => return Err(dyndns::anyhow::Error::from(err)),
-        }
+    fn delete(&self, zone: &Zone, record: Record) -> Result<()> {
+        self.runtime
+            .block_on(change(self, zone, record, ChangeAction::Delete))
     }
 
-    let mut result = HashMap::new();
-    for aws_zone in aws_zones {
-        let mut dns_records: DnsRecords = Vec::new();
+    fn option_changes(&self, zone: &Zone, zone_config: &ZoneConfig) -> Result<Vec<String>> {
+        let changes = self
+            .runtime
+            .block_on(alias_changes(self, zone, zone_config))?;
 
-        let aws_zone_id = aws_zone.zone_id();
-        let aws_zone_name = aws_zone.name.unwrap();
+        Ok(changes.iter().map(describe).collect())
+    }
 
-        if !config.zones.contains_key(&aws_zone_name.as_internal()) {
-            continue;
+    fn apply_options(&self, zone: &Zone, zone_config: &ZoneConfig) -> Result<()> {
+        self.runtime
+            .block_on(apply_aliases(self, zone, zone_config))
+    }
+}
+
+async fn current(provider: &AwsRoute53Provider, config: &Config) -> Result<ZoneResults> {
+    let handled_zones = config
+        .zones
+        .iter()
+        .filter(|(_, zone_config)| zone_config.is_handled_by(provider.name()))
+        .collect::<Vec<_>>();
+
+    for (zone_name, zone_config) in &handled_zones {
+        if !zone_config.credentials.is_empty() {
+            warn!(
+                "Per-zone credentials are not supported by {}, ignoring them for zone {}",
+                provider.name(),
+                zone_name
+            );
         }
+    }
 
-        let mut last_aws_record_identifier = None;
-        loop {
-            let aws_list_records_request = provider
+    let mut aws_zones: Vec<(String, String)> = handled_zones
+        .iter()
+        .filter_map(|(zone_name, zone_config)| {
+            zone_config
+                .id
+                .as_ref()
+                .map(|zone_id| (zone_name.to_string(), zone_id.clone()))
+        })
+        .collect();
+
+    let unknown_zone_ids = handled_zones
+        .iter()
+        .filter(|(_, zone_config)| zone_config.id.is_none())
+        .map(|(zone_name, _)| zone_name.as_str())
+        .collect::<Vec<_>>();
+
+    if !unknown_zone_ids.is_empty() {
+        aws_zones.extend(
+            provider
                 .client
-                .list_resource_record_sets()
-                .hosted_zone_id(aws_zone_id.clone())
-                .set_start_record_identifier(last_aws_record_identifier.clone());
-
-            let aws_response = aws_list_records_request.send().await;
-
-            match aws_response {
-                Ok(aws_output) => {
-                    dns_records.append(
-                        &mut aws_output
-                            .resource_record_sets
-                            .unwrap_or_default()
-                            .into_iter()
-                            .filter_map(|record_set| {
-                                let record_set_name = record_set.name.unwrap().as_internal();
-
-                                let record_set_type = record_set.r#type.unwrap();
-
-                                let records = record_set.resource_records.unwrap_or_default();
-                                if !records.is_empty() {
-                                    match record_set_type {
-                                        RrType::A => Some(Record::A {
-                                            name: record_set_name,
-                                            value: Ipv4Addr::from_str(
-                                                if let Some(Some(value)) =
-                                                    records.first().map(|it| &it.value)
-                                                {
-                                                    value
-                                                } else {
-                                                    return None;
-                                                },
-                                            )
-                                            .unwrap(),
-                                            ttl: record_set.ttl.unwrap().try_into().unwrap(),
-                                        }),
-                                        RrType::Aaaa => Some(Record::AAAA {
-                                            name: record_set_name,
-                                            value: Ipv6Addr::from_str(
-                                                if let Some(Some(value)) =
-                                                    records.first().map(|it| &it.value)
-                                                {
-                                                    value
-                                                } else {
-                                                    return None;
-                                                },
-                                            )
-                                            .unwrap(),
-                                            ttl: record_set.ttl.unwrap().try_into().unwrap(),
-                                        }),
-                                        _ => None,
-                                    }
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect(),
-                    );
-
-                    if aws_output.is_truncated {
-                        last_aws_record_identifier = aws_output.next_record_identifier
+                .list_hosted_zones()
+                .await?
+                .into_iter()
+                .filter_map(|hosted_zone| {
+                    let hz_name = hosted_zone.name.as_internal();
+                    if unknown_zone_ids
+                        .iter()
+                        .any(|zone| DnsName::from(*zone) == DnsName::from(&hz_name))
+                    {
+                        Some((hz_name, hosted_zone.id))
                     } else {
-                        break;
+                        None
                     }
-                }
-                Err(err) => {
-                    eprintln!("{:?}", err);
-                    eprintln!("{:?}", err.source());
-
-                    return Err(dyndns::anyhow::Error::from(err));
-                }
-            }
-        }
-
-        result.insert(
-            Zone::with_id(aws_zone_name.as_internal(), aws_zone_id),
-            dns_records,
+                }),
         );
     }
 
+    let mut result: ZoneResults = unknown_zone_ids
+        .iter()
+        .filter(|zone_name| {
+            !aws_zones.iter().any(|(aws_zone_name, _)| {
+                DnsName::from(aws_zone_name) == DnsName::from(**zone_name)
+            })
+        })
+        .map(|zone_name| {
+            (
+                Zone::new(zone_name.to_string()),
+                Err(dyndns::Error::provider(
+                    ProviderErrorKind::NotFound,
+                    format!("No such hosted zone: {}", zone_name),
+                )),
+            )
+        })
+        .collect();
+
+    for (zone_name, aws_zone_id) in aws_zones {
+        let dns_records = list_records(provider, &aws_zone_id).await;
+        result.insert(Zone::with_id(zone_name, aws_zone_id), dns_records);
+    }
+
     Ok(result)
 }
 
-async fn update(provider: &AwsRoute53Provider, zone: &Zone, record: Record) -> DynResult<()> {
+async fn list_records(provider: &AwsRoute53Provider, aws_zone_id: &str) -> Result<DnsRecords> {
+    Ok(provider
+        .client
+        .list_resource_record_sets(aws_zone_id)
+        .await?
+        .into_iter()
+        .filter_map(|record_set| record_set.to_record())
+        .collect())
+}
+
+async fn change(
+    provider: &AwsRoute53Provider,
+    zone: &Zone,
+    record: Record,
+    action: ChangeAction,
+) -> Result<()> {
     let zone_id = if let Some(zone_id) = &zone.id {
         zone_id.clone()
     } else {
-        eprintln!("No such hosted zone: {}", zone.name);
+        error!("No such hosted zone: {}", zone.name);
         return Ok(());
     };
 
+    let record_set = match record.to_record_set() {
+        Some(record_set) => record_set,
+        None => {
+            return Err(dyndns::Error::provider(
+                ProviderErrorKind::Unsupported,
+                format!("Route 53 doesn't support the record {}", record),
+            ))
+        }
+    };
+
     provider
         .client
-        .change_resource_record_sets()
-        .hosted_zone_id(zone_id)
-        .change_batch(
-            ChangeBatch::builder()
-                .changes(
-                    Change::builder()
-                        .action(ChangeAction::Upsert)
-                        .resource_record_set(record.to_resource_record_set())
-                        .build(),
-                )
-                .build(),
-        )
-        .send()
-        .await?;
+        .change_resource_record_sets(&zone_id, vec![Change { action, record_set }])
+        .await
+}
+
+async fn alias_changes(
+    provider: &AwsRoute53Provider,
+    zone: &Zone,
+    zone_config: &ZoneConfig,
+) -> Result<Vec<Change>> {
+    let aliases = aliases(zone_config).map_err(|err| dyndns::Error::Config(err.into()))?;
+    if aliases.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    Ok(())
+    let zone_id = if let Some(zone_id) = &zone.id {
+        zone_id.clone()
+    } else {
+        error!("No such hosted zone: {}", zone.name);
+        return Ok(Vec::new());
+    };
+
+    let current = provider.client.list_resource_record_sets(&zone_id).await?;
+    Ok(pending_changes(&aliases, &zone_id, &current))
 }
 
-trait AwsDomainName {
+pub(crate) async fn apply_aliases(
+    provider: &AwsRoute53Provider,
+    zone: &Zone,
+    zone_config: &ZoneConfig,
+) -> Result<()> {
+    let changes = alias_changes(provider, zone, zone_config).await?;
+
+    let zone_id = match &zone.id {
+        Some(zone_id) if !changes.is_empty() => zone_id,
+        _ => {
+            debug!("Aliases of zone {} are up-to-date", zone.name);
+            return Ok(());
+        }
+    };
+
+    for change in &changes {
+        info!("Updating alias {}", describe(change));
+    }
+
+    provider
+        .client
+        .change_resource_record_sets(zone_id, changes)
+        .await
+}
+
+pub(crate) trait AwsDomainName {
     fn as_internal(&self) -> String;
 
     fn to_aws(&self) -> String;
@@ -285,7 +325,7 @@ impl AwsDomainName for String {
                     Ok(Some(char)) => char.to_string(),
                     err => {
                         let capture = captures.get(0).unwrap().as_str();
-                        eprintln!("Failed to convert {} into character ({:?})", capture, err);
+                        error!("Failed to convert {} into character ({:?})", capture, err);
 
                         capture.to_string()
                     }
@@ -299,43 +339,161 @@ impl AwsDomainName for String {
     }
 }
 
-trait AwsHostedZone {
-    fn zone_id(&self) -> String;
+fn parse_values<T: FromStr>(values: &[String]) -> Option<Vec<T>> {
+    let values = values
+        .iter()
+        .filter_map(|value| T::from_str(value).ok())
+        .collect::<Vec<_>>();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values)
+    }
 }
 
-impl AwsHostedZone for HostedZone {
-    fn zone_id(&self) -> String {
-        self.id
-            .as_ref()
-            .map(|zone| {
-                if let Some(i) = zone.rfind('/') {
-                    zone[i + 1..].to_string()
-                } else {
-                    zone.to_string()
-                }
-            })
-            .unwrap()
+impl RecordSet {
+    fn to_record(&self) -> Option<Record> {
+        if self.values.is_empty() {
+            return None;
+        }
+
+        let name = self.name.as_internal();
+        let ttl = self.ttl?;
+
+        match self.record_type {
+            RecordType::A => Some(Record::A {
+                name,
+                values: parse_values(&self.values)?,
+                ttl,
+            }),
+            RecordType::Aaaa => Some(Record::AAAA {
+                name,
+                values: parse_values(&self.values)?,
+                ttl,
+            }),
+            RecordType::Ptr => Some(Record::PTR {
+                name,
+                target: self.values.first()?.as_internal(),
+                ttl,
+            }),
+            RecordType::Cname => Some(Record::CNAME {
+                name,
+                target: self.values.first()?.as_internal(),
+                ttl,
+            }),
+            RecordType::Srv => Record::parse_service("SRV", &name, self.values.first()?, ttl),
+        }
     }
 }
 
 trait AwsRecord {
-    fn to_resource_record_set(&self) -> ResourceRecordSet;
+    // None for records Route 53 (or the SDK) doesn't support
+    fn to_record_set(&self) -> Option<RecordSet>;
 }
 
 impl AwsRecord for Record {
-    fn to_resource_record_set(&self) -> ResourceRecordSet {
-        let (name, r#type, value, ttl) = match self {
-            Record::A { name, value, ttl } => (name.to_aws(), RrType::A, value.to_string(), *ttl),
-            Record::AAAA { name, value, ttl } => {
-                (name.to_aws(), RrType::Aaaa, value.to_string(), *ttl)
+    fn to_record_set(&self) -> Option<RecordSet> {
+        let (name, record_type, values, ttl) = match self {
+            Record::A { name, values, ttl } => (
+                name.to_aws(),
+                RecordType::A,
+                values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>(),
+                *ttl,
+            ),
+            Record::AAAA { name, values, ttl } => (
+                name.to_aws(),
+                RecordType::Aaaa,
+                values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>(),
+                *ttl,
+            ),
+            Record::PTR { name, target, ttl } => {
+                (name.to_aws(), RecordType::Ptr, vec![target.to_aws()], *ttl)
             }
+            Record::CNAME { name, target, ttl } => (
+                name.to_aws(),
+                RecordType::Cname,
+                vec![target.to_aws()],
+                *ttl,
+            ),
+            Record::SRV { name, ttl, .. } => (name.to_aws(), RecordType::Srv, self.values(), *ttl),
+            Record::HTTPS { .. } => return None,
         };
 
-        ResourceRecordSet::builder()
-            .name(name)
-            .r#type(r#type)
-            .resource_records(ResourceRecord::builder().value(value).build())
-            .ttl(ttl.into())
-            .build()
+        Some(RecordSet {
+            name,
+            record_type,
+            values,
+            ttl: Some(ttl),
+            alias_target: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    const DOMAIN: &str = "[a-z0-9]{1,10}(\\.[a-z0-9-]{1,10}){0,3}";
+
+    proptest! {
+        #[test]
+        fn prop_as_internal_strips_trailing_dot(name in DOMAIN) {
+            prop_assert_eq!(&name, &format!("{}.", name).as_internal());
+            prop_assert_eq!(&name, &name.as_internal());
+        }
+
+        #[test]
+        fn prop_to_aws_round_trip(name in DOMAIN) {
+            prop_assert_eq!(format!("{}.", name), name.to_aws());
+            prop_assert_eq!(&name, &name.to_aws().as_internal());
+        }
+
+        #[test]
+        fn prop_as_internal_unescapes_wildcard(zone in DOMAIN) {
+            prop_assert_eq!(
+                format!("*.{}", zone),
+                format!("\\052.{}.", zone).as_internal()
+            );
+        }
+
+        #[test]
+        fn prop_record_set_round_trip(name in DOMAIN, target in DOMAIN, ttl: u32) {
+            let records = vec![
+                Record::A {
+                    name: name.clone(),
+                    values: vec!["203.0.113.10".parse().unwrap()],
+                    ttl,
+                },
+                Record::CNAME {
+                    name: name.clone(),
+                    target: target.clone(),
+                    ttl,
+                },
+                Record::SRV {
+                    name: format!("_sip._udp.{}", name),
+                    priority: 10,
+                    weight: 5,
+                    port: 5060,
+                    target,
+                    ttl,
+                },
+            ];
+
+            for record in records {
+                prop_assert_eq!(
+                    Some(record.clone()),
+                    record.to_record_set().and_then(|record_set| record_set.to_record())
+                );
+            }
+        }
     }
 }
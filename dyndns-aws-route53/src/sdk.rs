@@ -0,0 +1,301 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::error::Error;
+
+use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_route53::model;
+use aws_sdk_route53::{Client, Endpoint, Region};
+use aws_types::app_name::AppName;
+use http::Uri;
+
+use dyndns::http::configured_user_agent;
+use dyndns::log::{error, warn};
+use dyndns::Result;
+
+use crate::model::{AliasTarget, Change, ChangeAction, HostedZone, RecordSet, RecordType};
+use crate::pacing::Pacing;
+use crate::partition::Partition;
+
+pub(crate) struct Route53Client {
+    client: Client,
+    pacing: Pacing,
+    _config: aws_config::Config,
+}
+
+impl Route53Client {
+    pub(crate) async fn new(partition: Option<Partition>, endpoint: Option<Uri>) -> Self {
+        let region_provider = match partition {
+            Some(partition) => RegionProviderChain::first_try(Region::new(partition.region())),
+            None => RegionProviderChain::default_provider().or_else(Region::new("us-east-1")),
+        };
+
+        let mut loader = aws_config::from_env().region(region_provider);
+        if let Some(app_name) = app_name(&configured_user_agent()) {
+            loader = loader.app_name(app_name);
+        }
+
+        let config = loader.load().await;
+
+        let mut client_config = aws_sdk_route53::config::Builder::from(&config);
+        if let Some(endpoint) = endpoint {
+            client_config = client_config.endpoint_resolver(Endpoint::immutable(endpoint));
+        }
+
+        Route53Client {
+            client: Client::from_conf(client_config.build()),
+            pacing: Pacing::default(),
+            _config: config,
+        }
+    }
+
+    pub(crate) async fn list_hosted_zones(&self) -> Result<Vec<HostedZone>> {
+        let mut hosted_zones = Vec::new();
+
+        let mut marker = None;
+        loop {
+            let output = self
+                .pacing
+                .send(|| {
+                    self.client
+                        .list_hosted_zones()
+                        .set_marker(marker.clone())
+                        .set_max_items(self.max_items())
+                        .send()
+                })
+                .await
+                .map_err(dyndns::Error::from_provider)?;
+
+            hosted_zones.extend(
+                output
+                    .hosted_zones
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(hosted_zone),
+            );
+
+            if output.is_truncated {
+                marker = output.next_marker
+            } else {
+                break;
+            }
+        }
+
+        Ok(hosted_zones)
+    }
+
+    pub(crate) async fn list_resource_record_sets(&self, zone_id: &str) -> Result<Vec<RecordSet>> {
+        let mut record_sets = Vec::new();
+
+        let mut next_record = (None, None, None);
+        loop {
+            let response = self
+                .pacing
+                .send(|| {
+                    self.client
+                        .list_resource_record_sets()
+                        .hosted_zone_id(zone_id)
+                        .set_start_record_name(next_record.0.clone())
+                        .set_start_record_type(next_record.1.clone())
+                        .set_start_record_identifier(next_record.2.clone())
+                        .set_max_items(self.max_items())
+                        .send()
+                })
+                .await;
+
+            let output = match response {
+                Ok(output) => output,
+                Err(err) => {
+                    error!("{:?}", err);
+                    error!("{:?}", err.source());
+
+                    return Err(dyndns::Error::from_provider(err));
+                }
+            };
+
+            record_sets.extend(
+                output
+                    .resource_record_sets
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(record_set),
+            );
+
+            if output.is_truncated {
+                next_record = (
+                    output.next_record_name,
+                    output.next_record_type,
+                    output.next_record_identifier,
+                )
+            } else {
+                break;
+            }
+        }
+
+        Ok(record_sets)
+    }
+
+    pub(crate) async fn change_resource_record_sets(
+        &self,
+        zone_id: &str,
+        changes: Vec<Change>,
+    ) -> Result<()> {
+        let change_batch = model::ChangeBatch::builder()
+            .set_changes(Some(changes.into_iter().map(to_aws_change).collect()))
+            .build();
+
+        self.pacing
+            .send(|| {
+                self.client
+                    .change_resource_record_sets()
+                    .hosted_zone_id(zone_id)
+                    .change_batch(change_batch.clone())
+                    .send()
+            })
+            .await
+            .map_err(dyndns::Error::from_provider)?;
+
+        Ok(())
+    }
+
+    fn max_items(&self) -> Option<String> {
+        self.pacing.max_items.map(|max_items| max_items.to_string())
+    }
+}
+
+// The SDK sends its own User-Agent, the configured one is added as the app name, which only allows a
+// restricted set of characters and at most 50 of them
+fn app_name(user_agent: &str) -> Option<AppName> {
+    let name = user_agent
+        .split_whitespace()
+        .next()?
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(50)
+        .collect::<String>();
+
+    AppName::new(name)
+        .map_err(|err| warn!("Not sending the User-Agent to Route 53: {}", err))
+        .ok()
+}
+
+fn hosted_zone(hosted_zone: model::HostedZone) -> Option<HostedZone> {
+    let id = hosted_zone.id?;
+
+    Some(HostedZone {
+        id: match id.rfind('/') {
+            Some(i) => id[i + 1..].to_string(),
+            None => id,
+        },
+        name: hosted_zone.name?,
+    })
+}
+
+fn record_set(record_set: model::ResourceRecordSet) -> Option<RecordSet> {
+    let record_type = match record_set.r#type? {
+        model::RrType::A => RecordType::A,
+        model::RrType::Aaaa => RecordType::Aaaa,
+        model::RrType::Cname => RecordType::Cname,
+        model::RrType::Ptr => RecordType::Ptr,
+        model::RrType::Srv => RecordType::Srv,
+        _ => return None,
+    };
+
+    Some(RecordSet {
+        name: record_set.name?,
+        record_type,
+        values: record_set
+            .resource_records
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|record| record.value)
+            .collect(),
+        ttl: record_set.ttl.and_then(|ttl| ttl.try_into().ok()),
+        alias_target: record_set.alias_target.and_then(|alias_target| {
+            Some(AliasTarget {
+                hosted_zone_id: alias_target.hosted_zone_id?,
+                dns_name: alias_target.dns_name?,
+            })
+        }),
+    })
+}
+
+fn to_aws_change(change: Change) -> model::Change {
+    model::Change::builder()
+        .action(match change.action {
+            ChangeAction::Upsert => model::ChangeAction::Upsert,
+            ChangeAction::Delete => model::ChangeAction::Delete,
+        })
+        .resource_record_set(to_aws_record_set(change.record_set))
+        .build()
+}
+
+fn to_aws_record_set(record_set: RecordSet) -> model::ResourceRecordSet {
+    let resource_records = if record_set.values.is_empty() {
+        None
+    } else {
+        Some(
+            record_set
+                .values
+                .into_iter()
+                .map(|value| model::ResourceRecord::builder().value(value).build())
+                .collect(),
+        )
+    };
+
+    model::ResourceRecordSet::builder()
+        .name(record_set.name)
+        .r#type(match record_set.record_type {
+            RecordType::A => model::RrType::A,
+            RecordType::Aaaa => model::RrType::Aaaa,
+            RecordType::Cname => model::RrType::Cname,
+            RecordType::Ptr => model::RrType::Ptr,
+            RecordType::Srv => model::RrType::Srv,
+        })
+        .set_resource_records(resource_records)
+        .set_ttl(record_set.ttl.map(i64::from))
+        .set_alias_target(record_set.alias_target.map(|alias_target| {
+            model::AliasTarget::builder()
+                .hosted_zone_id(alias_target.hosted_zone_id)
+                .dns_name(alias_target.dns_name)
+                .evaluate_target_health(false)
+                .build()
+        }))
+        .build()
+}
@@ -0,0 +1,116 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+#![cfg(feature = "localstack")]
+
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_sdk_route53::{Client, Endpoint};
+use tokio::runtime::Runtime;
+
+use dyndns::config::Config;
+use dyndns::provider::{DnsProvider, Record, Zone};
+use dyndns::{serde_yaml, Updater};
+use dyndns_aws_route53::AwsRoute53Provider;
+
+const DEFAULT_ENDPOINT: &str = "http://localhost:4566";
+
+fn setup_env() -> String {
+    let endpoint = env::var("AWS_ENDPOINT_URL").unwrap_or_else(|_| DEFAULT_ENDPOINT.into());
+
+    env::set_var("AWS_ENDPOINT_URL", &endpoint);
+    env::set_var("AWS_ACCESS_KEY_ID", "test");
+    env::set_var("AWS_SECRET_ACCESS_KEY", "test");
+    env::set_var("AWS_REGION", "us-east-1");
+
+    endpoint
+}
+
+fn create_hosted_zone(endpoint: &str, name: &str) {
+    Runtime::new().unwrap().block_on(async {
+        let config = aws_config::from_env().load().await;
+        let client = Client::from_conf(
+            aws_sdk_route53::config::Builder::from(&config)
+                .endpoint_resolver(Endpoint::immutable(endpoint.parse().unwrap()))
+                .build(),
+        );
+
+        let caller_reference = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+            .to_string();
+
+        client
+            .create_hosted_zone()
+            .name(name)
+            .caller_reference(caller_reference)
+            .send()
+            .await
+            .unwrap();
+    })
+}
+
+#[test]
+#[ignore]
+fn test_run_once() {
+    let endpoint = setup_env();
+    create_hosted_zone(&endpoint, "localstack.example.com");
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+override_ip:
+  ipv4: 203.0.113.10
+zones:
+  localstack.example.com:
+    - a: home.localstack.example.com
+"#,
+    )
+    .unwrap();
+
+    let provider = AwsRoute53Provider::default();
+
+    Updater::new(&config, &provider).run_once().unwrap();
+
+    let current = provider.current(&config).unwrap();
+    let records = current[&Zone::new("localstack.example.com".into())]
+        .as_ref()
+        .unwrap();
+
+    assert!(records.contains(&Record::A {
+        name: "home.localstack.example.com".into(),
+        values: vec!["203.0.113.10".parse().unwrap()],
+        ttl: 300,
+    }));
+}
@@ -0,0 +1,143 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use dyndns::log::{error, info};
+use dyndns::Error;
+use tiny_http::{Header, Request, Response, Server};
+
+pub struct EchoServer {
+    server: Server,
+    trust_proxy: bool,
+}
+
+impl EchoServer {
+    pub fn bind(address: SocketAddr, trust_proxy: bool) -> dyndns::Result<EchoServer> {
+        let server = Server::http(address).map_err(|err| {
+            Error::Io(io::Error::other(format!(
+                "failed to listen on {}: {}",
+                address, err
+            )))
+        })?;
+
+        info!("Listening on {}", address);
+
+        Ok(EchoServer {
+            server,
+            trust_proxy,
+        })
+    }
+
+    pub fn run(&self) {
+        for request in self.server.incoming_requests() {
+            if let Err(err) = self.handle(request) {
+                error!("Failed to respond: {}", err);
+            }
+        }
+    }
+
+    fn handle(&self, request: Request) -> std::io::Result<()> {
+        let forwarded_for = if self.trust_proxy {
+            request
+                .headers()
+                .iter()
+                .find(|header| header.field.equiv("X-Forwarded-For"))
+                .map(|header| header.value.as_str().to_string())
+        } else {
+            None
+        };
+
+        let ip = client_ip(request.remote_addr(), forwarded_for.as_deref());
+
+        let path = request.url().split('?').next().unwrap_or_default();
+
+        let response = match (path, ip) {
+            ("/", Some(ip)) => Response::from_string(format!("{}\n", ip))
+                .with_header(content_type("text/plain; charset=utf-8")),
+            ("/json", Some(ip)) => Response::from_string(format!("{{\"ip\":\"{}\"}}\n", ip))
+                .with_header(content_type("application/json")),
+            (_, None) => Response::from_string("client address unknown\n").with_status_code(500),
+            _ => Response::from_string("not found\n").with_status_code(404),
+        };
+
+        request.respond(response)
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes("Content-Type", value).unwrap()
+}
+
+fn client_ip(remote_addr: Option<&SocketAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+    // Only the right-most entry is added by the proxy, clients can send any others
+    let forwarded = forwarded_for
+        .and_then(|forwarded_for| forwarded_for.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+
+    let ip = forwarded.or_else(|| remote_addr.map(SocketAddr::ip))?;
+
+    match ip {
+        IpAddr::V6(ipv6) => Some(
+            ipv6.to_ipv4_mapped()
+                .map(IpAddr::V4)
+                .unwrap_or(IpAddr::V6(ipv6)),
+        ),
+        ip => Some(ip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ip() {
+        let remote_addr: SocketAddr = "[::ffff:203.0.113.10]:4711".parse().unwrap();
+
+        assert_eq!(
+            Some("203.0.113.10".parse().unwrap()),
+            client_ip(Some(&remote_addr), None)
+        );
+        assert_eq!(
+            Some("2001:db8::10".parse().unwrap()),
+            client_ip(Some(&remote_addr), Some("192.0.2.1, 2001:db8::10"))
+        );
+        assert_eq!(
+            Some("203.0.113.10".parse().unwrap()),
+            client_ip(Some(&remote_addr), Some("garbage"))
+        );
+        assert_eq!(None, client_ip(None, None));
+    }
+}
@@ -31,128 +31,338 @@
  *
  */
 
-use dyndns::anyhow::{Context, Error};
+use dyndns::credentials::get_credential;
+use dyndns::environment;
+use dyndns::http::{self, handle_response, with_retry};
+use dyndns::log::error;
+use dyndns::name::DnsName;
 use dyndns::provider::Record;
-use dyndns::result::DynResult;
-use dyndns::ureq;
+use dyndns::{ureq, Error, ProviderErrorKind, Result};
 use std::cmp::max;
-use std::env;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::client::model::*;
 
+static KEYRING_API_KEY: &str = "gandi-livedns/api_key";
 static BASE_URL: &str = "https://api.gandi.net/v5/livedns";
 static PER_PAGE_VALUE: &str = "2147483647";
 
 pub(crate) struct LDClient {
+    base_url: String,
     api_key: Option<String>,
+    sharing_id: Option<String>,
+}
+
+#[derive(Clone, Copy, Default)]
+pub(crate) struct LDCredentials<'a> {
+    pub api_key: Option<&'a str>,
+    pub sharing_id: Option<&'a str>,
 }
 
 impl Default for LDClient {
     fn default() -> Self {
-        LDClient {
-            api_key: env::var("LIVEDNS_API_KEY").ok(),
-        }
+        LDClient::new(BASE_URL)
     }
 }
 
 impl LDClient {
-    pub(crate) fn get_domains(&self) -> DynResult<Vec<LDDomain>> {
-        ureq::get(&format!("{}/domains", BASE_URL))
-            .query("per_page", PER_PAGE_VALUE)
-            .set("Authorization", &format!("Apikey {}", self.api_key()?))
-            .call()
-            .context("failed to call LiveDNS")?
-            .into_json()
-            .context("failed to read domains response")
+    pub(crate) fn new(base_url: &str) -> Self {
+        LDClient {
+            base_url: base_url.trim_end_matches('/').into(),
+            api_key: environment::legacy_var("LIVEDNS_API_KEY").or_else(|| {
+                match get_credential(KEYRING_API_KEY) {
+                    Ok(api_key) => api_key,
+                    Err(err) => {
+                        error!("{:?}", anyhow::Error::from(err));
+                        None
+                    }
+                }
+            }),
+            sharing_id: environment::legacy_var("LIVEDNS_SHARING_ID"),
+        }
     }
 
-    pub(crate) fn get_records(&self, domain: &str) -> DynResult<Vec<LDRecord>> {
-        let mut a_records: Vec<LDRecord> = self.get_records_for_type(domain, LDRecordType::A)?;
-        let mut aaaa_records: Vec<LDRecord> =
-            self.get_records_for_type(domain, LDRecordType::Aaaa)?;
-
-        a_records.append(&mut aaaa_records);
+    pub(crate) fn get_domains(&self, credentials: LDCredentials) -> Result<Vec<LDDomain>> {
+        let request = self.authorize(
+            http::get(&format!("{}/domains", self.base_url)).query("per_page", PER_PAGE_VALUE),
+            credentials,
+        )?;
 
-        Ok(a_records)
+        with_retry(|| ld_response(request.clone().call()))?
+            .into_json()
+            .map_err(|err| invalid_response(err, "failed to read domains response".into()))
     }
 
-    fn get_records_for_type(
+    pub(crate) fn get_records_for_type(
         &self,
         domain: &str,
         record_type: LDRecordType,
-    ) -> DynResult<Vec<LDRecord>> {
+        credentials: LDCredentials,
+    ) -> Result<Vec<LDRecord>> {
         let record_type_str: &str = record_type.into();
 
-        ureq::get(&format!("{}/domains/{}/records", BASE_URL, domain))
-            .query("rrset_type", record_type_str)
-            .query("per_page", PER_PAGE_VALUE)
-            .set("Authorization", &format!("Apikey {}", self.api_key()?))
-            .call()
-            .context("failed to call LiveDNS")?
+        let request = self.authorize(
+            http::get(&format!("{}/domains/{}/records", self.base_url, domain))
+                .query("rrset_type", record_type_str)
+                .query("per_page", PER_PAGE_VALUE),
+            credentials,
+        )?;
+
+        with_retry(|| ld_response(request.clone().call()))?
             .into_json()
-            .context(format!(
-                "failed to read domain {} records response",
-                record_type_str
-            ))
+            .map_err(|err| {
+                invalid_response(
+                    err,
+                    format!("failed to read domain {} records response", record_type_str),
+                )
+            })
     }
 
-    pub(crate) fn put_record(&self, zone: &str, record: Record) -> DynResult<()> {
-        let (name, r#type, value, ttl) = match &record {
-            Record::A { name, value, ttl } => (
+    pub(crate) fn put_record(
+        &self,
+        zone: &str,
+        record: Record,
+        credentials: LDCredentials,
+    ) -> Result<()> {
+        let (name, r#type, values, ttl) = match &record {
+            Record::A { name, values, ttl } => (
                 name.gandi_record_name(zone),
                 LDRecordType::A,
-                value.to_string(),
+                values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>(),
                 *ttl,
             ),
-            Record::AAAA { name, value, ttl } => (
+            Record::AAAA { name, values, ttl } => (
                 name.gandi_record_name(zone),
                 LDRecordType::Aaaa,
-                value.to_string(),
+                values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>(),
                 *ttl,
             ),
+            Record::CNAME { name, target, ttl } => (
+                name.gandi_record_name(zone),
+                LDRecordType::Cname,
+                vec![format!("{}.", target)],
+                *ttl,
+            ),
+            Record::SRV { name, ttl, .. } => (
+                name.gandi_record_name(zone),
+                LDRecordType::Srv,
+                record.values(),
+                *ttl,
+            ),
+            Record::HTTPS { name, ttl, .. } => (
+                name.gandi_record_name(zone),
+                LDRecordType::Https,
+                record.values(),
+                *ttl,
+            ),
+            Record::PTR { .. } => return Err(unsupported_record(&record)),
         };
 
-        let response = ureq::put(&format!(
-            "{}/domains/{}/records/{}/{}",
-            BASE_URL, zone, name, r#type
-        ))
-        .set("Authorization", &format!("Apikey {}", self.api_key()?))
-        .send_json(dyndns::ureq::json!({
-            "rrset_values": [value],
+        let request = self.authorize(
+            http::put(&format!(
+                "{}/domains/{}/records/{}/{}",
+                self.base_url, zone, name, r#type
+            )),
+            credentials,
+        )?;
+
+        let body = dyndns::ureq::json!({
+            "rrset_values": values,
             "rrset_ttl": max(300, ttl)
-        }))
-        .context("failed to call LiveDNS")?;
+        });
+
+        let response = with_retry(|| ld_response(request.clone().send_json(body.clone())))?;
 
         if response.status() == 201 {
             Ok(())
         } else {
-            Err(Error::msg(format!(
-                "Unexpected response status: {}",
-                response.status()
-            )))
+            Err(Error::provider(
+                ProviderErrorKind::Other,
+                format!(
+                    "Unexpected response status: {} ({})",
+                    response.status(),
+                    response.into_string().unwrap_or_default().trim()
+                ),
+            ))
+        }
+    }
+
+    pub(crate) fn delete_record(
+        &self,
+        zone: &str,
+        record: Record,
+        credentials: LDCredentials,
+    ) -> Result<()> {
+        let (name, r#type) = match &record {
+            Record::A { name, .. } => (name.gandi_record_name(zone), LDRecordType::A),
+            Record::AAAA { name, .. } => (name.gandi_record_name(zone), LDRecordType::Aaaa),
+            Record::CNAME { name, .. } => (name.gandi_record_name(zone), LDRecordType::Cname),
+            Record::SRV { name, .. } => (name.gandi_record_name(zone), LDRecordType::Srv),
+            Record::HTTPS { name, .. } => (name.gandi_record_name(zone), LDRecordType::Https),
+            Record::PTR { .. } => return Err(unsupported_record(&record)),
+        };
+
+        let request = self.authorize(
+            http::delete(&format!(
+                "{}/domains/{}/records/{}/{}",
+                self.base_url, zone, name, r#type
+            )),
+            credentials,
+        )?;
+
+        let response = with_retry(|| ld_response(request.clone().call()))?;
+
+        if response.status() == 204 {
+            Ok(())
+        } else {
+            Err(Error::provider(
+                ProviderErrorKind::Other,
+                format!(
+                    "Unexpected response status: {} ({})",
+                    response.status(),
+                    response.into_string().unwrap_or_default().trim()
+                ),
+            ))
         }
     }
 
-    fn api_key(&self) -> DynResult<&str> {
-        match &self.api_key {
-            Some(api_key) => Ok(api_key.as_str()),
-            _ => Err(Error::msg("Gandi LiveDNS API Key not configured")),
+    pub(crate) fn create_snapshot(&self, zone: &str, credentials: LDCredentials) -> Result<String> {
+        let request = self.authorize(
+            http::post(&format!("{}/domains/{}/snapshots", self.base_url, zone)),
+            credentials,
+        )?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+
+        let body = dyndns::ureq::json!({
+            "name": format!("dyndns-gandi-livedns-{}", timestamp)
+        });
+
+        with_retry(|| ld_response(request.clone().send_json(body.clone())))?
+            .into_json::<LDSnapshot>()
+            .map(|snapshot| snapshot.id)
+            .map_err(|err| invalid_response(err, "failed to read snapshot response".into()))
+    }
+
+    pub(crate) fn restore_snapshot(
+        &self,
+        zone: &str,
+        snapshot: &str,
+        credentials: LDCredentials,
+    ) -> Result<()> {
+        let request = self.authorize(
+            http::get(&format!(
+                "{}/domains/{}/snapshots/{}",
+                self.base_url, zone, snapshot
+            )),
+            credentials,
+        )?;
+
+        let zone_data = with_retry(|| ld_response(request.clone().call()))?
+            .into_json::<LDSnapshotData>()
+            .map(|snapshot| snapshot.zone_data)
+            .map_err(|err| invalid_response(err, "failed to read snapshot response".into()))?;
+
+        let request = self.authorize(
+            http::put(&format!("{}/domains/{}/records", self.base_url, zone)),
+            credentials,
+        )?;
+
+        let body = dyndns::ureq::json!({ "items": zone_data });
+
+        let response = with_retry(|| ld_response(request.clone().send_json(body.clone())))?;
+
+        if response.status() == 201 {
+            Ok(())
+        } else {
+            Err(Error::provider(
+                ProviderErrorKind::Other,
+                format!(
+                    "Unexpected response status: {} ({})",
+                    response.status(),
+                    response.into_string().unwrap_or_default().trim()
+                ),
+            ))
         }
     }
+
+    fn authorize(
+        &self,
+        request: ureq::Request,
+        credentials: LDCredentials,
+    ) -> Result<ureq::Request> {
+        let request = request.set("Authorization", &self.authorization(credentials.api_key)?);
+
+        match credentials.sharing_id.or(self.sharing_id.as_deref()) {
+            Some(sharing_id) => Ok(request.query("sharing_id", sharing_id)),
+            None => Ok(request),
+        }
+    }
+
+    fn authorization(&self, zone_key: Option<&str>) -> Result<String> {
+        match (zone_key, &self.api_key) {
+            (Some(api_key), _) => Ok(format!("Apikey {}", api_key)),
+            (None, Some(api_key)) => Ok(format!("Apikey {}", api_key)),
+            _ => Err(Error::Config("Gandi LiveDNS API Key not configured".into())),
+        }
+    }
+}
+
+fn ld_response(result: std::result::Result<ureq::Response, ureq::Error>) -> Result<ureq::Response> {
+    handle_response(result).map_err(|err| {
+        let kind = err.provider_kind().unwrap_or(ProviderErrorKind::Other);
+
+        let ld_error = match &err {
+            Error::Http(http_error) => serde_json::from_str::<LDError>(&http_error.body).ok(),
+            _ => None,
+        };
+
+        let err = match ld_error {
+            Some(ld_error) => {
+                anyhow::Error::from(err).context(format!("LiveDNS request failed: {}", ld_error))
+            }
+            None => anyhow::Error::from(err).context("failed to call LiveDNS"),
+        };
+
+        Error::provider(kind, err)
+    })
+}
+
+fn invalid_response(err: io::Error, context: String) -> Error {
+    Error::provider(
+        ProviderErrorKind::Other,
+        anyhow::Error::from(err).context(context),
+    )
+}
+
+fn unsupported_record(record: &Record) -> Error {
+    Error::provider(
+        ProviderErrorKind::Unsupported,
+        format!("Gandi LiveDNS does not support PTR records ({})", record),
+    )
 }
 
 trait GandiRecord {
-    fn gandi_record_name(&self, zone: &str) -> &str;
+    fn gandi_record_name(&self, zone: &str) -> String;
 }
 
 impl GandiRecord for String {
-    fn gandi_record_name(&self, zone: &str) -> &str {
-        let stripped = self.strip_suffix(zone).unwrap_or_else(|| self.as_str());
+    fn gandi_record_name(&self, zone: &str) -> String {
+        let name = DnsName::from(self);
 
-        if let Some(i) = stripped.rfind('.') {
-            &stripped[..i]
-        } else {
-            stripped
+        match name.strip_zone(&zone.into()) {
+            Some("") => "@".into(),
+            Some(stripped) => stripped.into(),
+            None => name.to_string(),
         }
     }
 }
@@ -178,11 +388,70 @@ pub(crate) mod model {
         pub values: Vec<String>,
     }
 
-    #[derive(Deserialize, Serialize)]
+    #[derive(Deserialize)]
+    pub struct LDSnapshot {
+        pub id: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct LDSnapshotData {
+        pub zone_data: serde_json::Value,
+    }
+
+    #[derive(Deserialize)]
+    pub struct LDError {
+        pub message: Option<String>,
+        pub object: Option<String>,
+        pub cause: Option<String>,
+        #[serde(default)]
+        pub errors: Vec<LDErrorDetail>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct LDErrorDetail {
+        #[serde(default)]
+        pub location: String,
+        #[serde(default)]
+        pub name: String,
+        #[serde(default)]
+        pub description: String,
+    }
+
+    impl Display for LDError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.message.as_deref().unwrap_or("unknown error"))?;
+
+            if let Some(cause) = &self.cause {
+                write!(f, " [cause: {}]", cause)?;
+            }
+
+            if let Some(object) = &self.object {
+                write!(f, " [object: {}]", object)?;
+            }
+
+            for error in &self.errors {
+                write!(
+                    f,
+                    "; {} {}: {}",
+                    error.location, error.name, error.description
+                )?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Copy, Deserialize, Serialize)]
     pub enum LDRecordType {
         A,
         #[serde(rename = "AAAA")]
         Aaaa,
+        #[serde(rename = "CNAME")]
+        Cname,
+        #[serde(rename = "SRV")]
+        Srv,
+        #[serde(rename = "HTTPS")]
+        Https,
     }
 
     impl Display for LDRecordType {
@@ -193,6 +462,9 @@ pub(crate) mod model {
                 match self {
                     LDRecordType::A => "A",
                     LDRecordType::Aaaa => "AAAA",
+                    LDRecordType::Cname => "CNAME",
+                    LDRecordType::Srv => "SRV",
+                    LDRecordType::Https => "HTTPS",
                 }
             )
         }
@@ -203,7 +475,36 @@ pub(crate) mod model {
             match t {
                 LDRecordType::A => "A",
                 LDRecordType::Aaaa => "AAAA",
+                LDRecordType::Cname => "CNAME",
+                LDRecordType::Srv => "SRV",
+                LDRecordType::Https => "HTTPS",
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    const DOMAIN: &str = "[a-z0-9]{1,10}(\\.[a-z0-9]{1,10}){1,3}";
+
+    proptest! {
+        #[test]
+        fn prop_gandi_record_name(label in "(\\*|[a-z0-9]{1,10})(\\.[a-z0-9]{1,10}){0,2}", zone in DOMAIN) {
+            prop_assert_eq!(&label, &format!("{}.{}", label, zone).gandi_record_name(&zone));
+            prop_assert_eq!(
+                &label,
+                &format!("{}.{}.", label, zone.to_uppercase()).gandi_record_name(&zone)
+            );
+        }
+
+        #[test]
+        fn prop_gandi_record_name_apex(zone in DOMAIN) {
+            prop_assert_eq!("@", zone.gandi_record_name(&zone));
+            prop_assert_eq!("@", format!("{}.", zone).gandi_record_name(&zone));
+        }
+    }
+}
@@ -34,4 +34,5 @@
 pub use crate::provider::GandiLivednsProvider;
 
 mod client;
+mod parallel;
 mod provider;
@@ -31,67 +31,314 @@
  *
  */
 
-use dyndns::config::Config;
-use dyndns::provider::{DnsProvider, DnsZones, Record, Zone};
-use dyndns::result::DynResult;
-use std::collections::HashMap;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use dyndns::environment;
+use dyndns::log::warn;
+use dyndns::prelude::{
+    describe, Config, DnsName, DnsProvider, Error, ProviderErrorKind, Record, Result, Ttl, Zone,
+    ZoneConfig, ZoneResults,
+};
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
 use std::str::FromStr;
+use std::sync::Mutex;
 
 use crate::client::model::*;
-use crate::client::LDClient;
+use crate::client::{LDClient, LDCredentials};
+use crate::parallel::map_bounded;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+// LiveDNS rejects TTLs outside of 5 minutes to 30 days
+const MIN_TTL: Ttl = Ttl::from_secs(300);
+const MAX_TTL: Ttl = Ttl::from_secs(2_592_000);
 
-#[derive(Default)]
 pub struct GandiLivednsProvider {
     client: LDClient,
+    concurrency: usize,
+    // SRV and HTTPS RRsets with several values, a PUT would replace all of them with a single one
+    shared_rrsets: Mutex<HashSet<(DnsName, &'static str)>>,
+}
+
+impl Default for GandiLivednsProvider {
+    fn default() -> Self {
+        GandiLivednsProvider::with_client(LDClient::default())
+    }
+}
+
+impl GandiLivednsProvider {
+    pub fn with_base_url(base_url: &str) -> Self {
+        GandiLivednsProvider::with_client(LDClient::new(base_url))
+    }
+
+    fn with_client(client: LDClient) -> Self {
+        GandiLivednsProvider {
+            client,
+            concurrency: environment::legacy_var("LIVEDNS_CONCURRENCY")
+                .and_then(|concurrency| concurrency.parse().ok())
+                .unwrap_or(DEFAULT_CONCURRENCY),
+            shared_rrsets: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn service_records(&self, name: String, record: &LDRecord) -> Vec<Record> {
+        let record_type = record.r#type.into();
+
+        if record.values.len() > 1 {
+            warn!(
+                "Not changing the {} records of {}: It has {} values, only single values are managed",
+                record_type,
+                name,
+                record.values.len()
+            );
+            self.shared_rrsets
+                .lock()
+                .unwrap()
+                .insert((DnsName::from(name.as_str()), record_type));
+        }
+
+        record
+            .values
+            .iter()
+            .filter_map(|value| Record::parse_service(record_type, &name, value, record.ttl))
+            .collect()
+    }
+
+    fn check_shared(&self, record: &Record) -> Result<()> {
+        let key = (DnsName::from(record.name()), record.record_type());
+
+        if self.shared_rrsets.lock().unwrap().contains(&key) {
+            Err(Error::provider(
+                ProviderErrorKind::Unsupported,
+                format!(
+                    "{} records of {} have several values, refusing to replace them",
+                    record.record_type(),
+                    record.name()
+                ),
+            ))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl DnsProvider for GandiLivednsProvider {
-    fn current(&self, config: &Config) -> DynResult<DnsZones> {
-        let mut zones = HashMap::new();
+    fn name(&self) -> &str {
+        "gandi-livedns"
+    }
 
-        let domains = self.client.get_domains()?;
-        for domain in domains {
-            if !config.zones.contains_key(&domain.fqdn) {
-                continue;
-            }
+    fn current(&self, config: &Config) -> Result<ZoneResults> {
+        let mut zones: ZoneResults = HashMap::new();
+        self.shared_rrsets.lock().unwrap().clear();
+
+        let handled_zones = config
+            .zones
+            .iter()
+            .filter(|(_, zone_config)| zone_config.is_handled_by(self.name()))
+            .collect::<Vec<_>>();
+
+        let domains = if handled_zones
+            .iter()
+            .any(|(_, zone_config)| !zone_config.has_own_credentials())
+        {
+            self.client
+                .get_domains(LDCredentials::default())
+                .map(|domains| {
+                    domains
+                        .into_iter()
+                        .map(|domain| domain.fqdn)
+                        .collect::<Vec<_>>()
+                })
+        } else {
+            Ok(Vec::new())
+        };
+
+        let fetch_jobs = handled_zones
+            .into_iter()
+            .filter(|(fqdn, zone_config)| {
+                if zone_config.has_own_credentials() {
+                    return true;
+                }
+
+                let error = match &domains {
+                    Ok(domains)
+                        if domains
+                            .iter()
+                            .any(|domain| DnsName::from(domain) == DnsName::from(*fqdn)) =>
+                    {
+                        return true;
+                    }
+                    Ok(_) => Error::provider(
+                        ProviderErrorKind::NotFound,
+                        format!("domain {} not found in Gandi LiveDNS account", fqdn),
+                    ),
+                    Err(err) => Error::provider(
+                        err.provider_kind().unwrap_or(ProviderErrorKind::Other),
+                        describe(err),
+                    ),
+                };
 
-            let records = self.client.get_records(&domain.fqdn)?;
+                zones.insert(Zone::new(fqdn.to_string()), Err(error));
+                false
+            })
+            .flat_map(|(fqdn, zone_config)| {
+                let credentials = zone_config.ld_credentials();
 
-            zones.insert(
-                Zone::new(domain.fqdn.clone()),
-                records
+                let has_cname = zone_config
+                    .records
+                    .iter()
+                    .any(|record| record.cname.is_some());
+                let has_srv = zone_config
+                    .records
+                    .iter()
+                    .any(|record| !record.srv.is_empty());
+                let has_https = zone_config
+                    .records
+                    .iter()
+                    .any(|record| !record.https.is_empty());
+
+                [LDRecordType::A, LDRecordType::Aaaa]
                     .into_iter()
-                    .filter_map(|record| {
-                        let record_name = record.proper_name(&domain.fqdn);
+                    .chain(has_cname.then_some(LDRecordType::Cname))
+                    .chain(has_srv.then_some(LDRecordType::Srv))
+                    .chain(has_https.then_some(LDRecordType::Https))
+                    .map(move |record_type| (fqdn, record_type, credentials))
+            })
+            .collect::<Vec<_>>();
+
+        let fetch_results = map_bounded(
+            &fetch_jobs,
+            self.concurrency,
+            |(fqdn, record_type, credentials)| {
+                self.client
+                    .get_records_for_type(fqdn, *record_type, *credentials)
+            },
+        );
+
+        for ((fqdn, _, _), records) in fetch_jobs.iter().zip(fetch_results) {
+            let zone_records = zones
+                .entry(Zone::new(fqdn.to_string()))
+                .or_insert_with(|| Ok(Vec::new()));
+
+            let records = match records {
+                Ok(records) => records,
+                Err(err) => {
+                    *zone_records = Err(err);
+                    continue;
+                }
+            };
 
-                        match record.r#type {
-                            LDRecordType::A => Some(Record::A {
+            if let Ok(zone_records) = zone_records {
+                zone_records.extend(records.into_iter().flat_map(|record| {
+                    let record_name = record.proper_name(fqdn);
+
+                    let record = match record.r#type {
+                        LDRecordType::A => parse_values(&record.values).map(|values| Record::A {
+                            name: record_name,
+                            values,
+                            ttl: record.ttl,
+                        }),
+                        LDRecordType::Aaaa => {
+                            parse_values(&record.values).map(|values| Record::AAAA {
                                 name: record_name,
-                                value: Ipv4Addr::from_str(record.values.first()?).unwrap(),
+                                values,
                                 ttl: record.ttl,
-                            }),
-                            LDRecordType::Aaaa => Some(Record::AAAA {
+                            })
+                        }
+                        LDRecordType::Cname => {
+                            record.proper_target(fqdn).map(|target| Record::CNAME {
                                 name: record_name,
-                                value: Ipv6Addr::from_str(record.values.first()?).unwrap(),
+                                target,
                                 ttl: record.ttl,
-                            }),
+                            })
                         }
-                    })
-                    .collect(),
-            );
+                        LDRecordType::Srv | LDRecordType::Https => {
+                            return self.service_records(record_name, &record)
+                        }
+                    };
+
+                    record.into_iter().collect()
+                }));
+            }
         }
 
         Ok(zones)
     }
 
-    fn update(&self, zone: &Zone, record: Record) -> DynResult<()> {
-        self.client.put_record(zone.name.as_str(), record)
+    fn ttl_range(&self) -> RangeInclusive<Ttl> {
+        MIN_TTL..=MAX_TTL
+    }
+
+    fn update(&self, zone: &Zone, record: Record) -> Result<()> {
+        self.check_shared(&record)?;
+        self.client
+            .put_record(zone.name.as_str(), record, zone.ld_credentials())
+    }
+
+    fn delete(&self, zone: &Zone, record: Record) -> Result<()> {
+        self.check_shared(&record)?;
+        self.client
+            .delete_record(zone.name.as_str(), record, zone.ld_credentials())
+    }
+
+    fn snapshot(&self, zone: &Zone) -> Result<String> {
+        self.client
+            .create_snapshot(zone.name.as_str(), zone.ld_credentials())
+    }
+
+    fn rollback(&self, zone: &Zone, snapshot: &str) -> Result<()> {
+        self.client
+            .restore_snapshot(zone.name.as_str(), snapshot, zone.ld_credentials())
+    }
+}
+
+fn parse_values<T: FromStr>(values: &[String]) -> Option<Vec<T>> {
+    let values = values
+        .iter()
+        .filter_map(|value| T::from_str(value).ok())
+        .collect::<Vec<_>>();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values)
+    }
+}
+
+trait ZoneCredentials {
+    fn credential(&self, name: &str) -> Option<&str>;
+
+    fn api_key(&self) -> Option<&str> {
+        self.credential("api_key")
+    }
+
+    fn has_own_credentials(&self) -> bool {
+        self.api_key().is_some() || self.credential("sharing_id").is_some()
+    }
+
+    fn ld_credentials(&self) -> LDCredentials<'_> {
+        LDCredentials {
+            api_key: self.api_key(),
+            sharing_id: self.credential("sharing_id"),
+        }
+    }
+}
+
+impl ZoneCredentials for ZoneConfig {
+    fn credential(&self, name: &str) -> Option<&str> {
+        self.credentials.get(name).map(String::as_str)
+    }
+}
+
+impl ZoneCredentials for Zone {
+    fn credential(&self, name: &str) -> Option<&str> {
+        self.credentials.get(name).map(String::as_str)
     }
 }
 
 trait ProperRecord {
     fn proper_name(&self, domain_name: &str) -> String;
+
+    fn proper_target(&self, domain_name: &str) -> Option<String>;
 }
 
 impl ProperRecord for LDRecord {
@@ -101,4 +348,14 @@ impl ProperRecord for LDRecord {
             name => name.to_owned() + "." + domain_name,
         }
     }
+
+    fn proper_target(&self, domain_name: &str) -> Option<String> {
+        let target = self.values.first()?;
+
+        match target.strip_suffix('.') {
+            Some(target) => Some(target.into()),
+            None if target == "@" => Some(domain_name.into()),
+            None => Some(target.to_owned() + "." + domain_name),
+        }
+    }
 }
@@ -0,0 +1,328 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use dyndns::config::Config;
+use dyndns::provider::{DnsProvider, Record, Zone};
+use dyndns::{serde_yaml, ProviderErrorKind, Updater};
+use dyndns_gandi_livedns::GandiLivednsProvider;
+use httpmock::prelude::*;
+use serde_json::json;
+use std::error::Error as StdError;
+
+fn config() -> Config {
+    serde_yaml::from_str(
+        r#"
+override_ip:
+  ipv4: 203.0.113.10
+zones:
+  example.com:
+    credentials:
+      api_key: secret
+    records:
+      - a: home.example.com
+"#,
+    )
+    .unwrap()
+}
+
+fn mock_records(server: &MockServer, rrset_type: &str, records: serde_json::Value) {
+    server.mock(|when, then| {
+        when.method(GET)
+            .path("/domains/example.com/records")
+            .query_param("rrset_type", rrset_type)
+            .header("Authorization", "Apikey secret");
+        then.status(200).json_body(records);
+    });
+}
+
+#[test]
+fn test_current() {
+    let server = MockServer::start();
+
+    mock_records(
+        &server,
+        "A",
+        json!([{
+            "rrset_type": "A",
+            "rrset_ttl": 300,
+            "rrset_name": "home",
+            "rrset_values": ["198.51.100.1"]
+        }]),
+    );
+    mock_records(&server, "AAAA", json!([]));
+
+    let provider = GandiLivednsProvider::with_base_url(&server.base_url());
+    let current = provider.current(&config()).unwrap();
+
+    assert_eq!(
+        &vec![Record::A {
+            name: "home.example.com".into(),
+            values: vec!["198.51.100.1".parse().unwrap()],
+            ttl: 300,
+        }],
+        current[&Zone::new("example.com".into())].as_ref().unwrap()
+    );
+}
+
+fn chain<'a>(
+    err: &'a (dyn StdError + 'static),
+) -> impl Iterator<Item = &'a (dyn StdError + 'static)> {
+    std::iter::successors(Some(err), |err| (*err).source())
+}
+
+#[test]
+fn test_current_zone_error() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(GET).path("/domains/example.com/records");
+        then.status(403)
+            .json_body(json!({ "message": "Access was denied" }));
+    });
+
+    let provider = GandiLivednsProvider::with_base_url(&server.base_url());
+    let current = provider.current(&config()).unwrap();
+
+    let err = current[&Zone::new("example.com".into())]
+        .as_ref()
+        .unwrap_err();
+
+    assert_eq!(Some(ProviderErrorKind::Authentication), err.provider_kind());
+    assert!(chain(err).any(|err| err.to_string().contains("Access was denied")));
+}
+
+#[test]
+fn test_run_once_updates_changed_record() {
+    let server = MockServer::start();
+
+    mock_records(
+        &server,
+        "A",
+        json!([{
+            "rrset_type": "A",
+            "rrset_ttl": 300,
+            "rrset_name": "home",
+            "rrset_values": ["198.51.100.1"]
+        }]),
+    );
+    mock_records(&server, "AAAA", json!([]));
+
+    let update_mock = server.mock(|when, then| {
+        when.method(PUT)
+            .path("/domains/example.com/records/home/A")
+            .header("Authorization", "Apikey secret")
+            .json_body(json!({
+                "rrset_values": ["203.0.113.10"],
+                "rrset_ttl": 300
+            }));
+        then.status(201);
+    });
+
+    let config = config();
+    let provider = GandiLivednsProvider::with_base_url(&server.base_url());
+
+    Updater::new(&config, &provider).run_once().unwrap();
+
+    update_mock.assert();
+}
+
+#[test]
+fn test_current_with_sharing_id() {
+    let server = MockServer::start();
+
+    let records_mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/domains/example.com/records")
+            .query_param("sharing_id", "organization")
+            .header("Authorization", "Apikey secret");
+        then.status(200).json_body(json!([]));
+    });
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+zones:
+  example.com:
+    credentials:
+      api_key: secret
+      sharing_id: organization
+    records:
+      - a: home.example.com
+"#,
+    )
+    .unwrap();
+
+    let provider = GandiLivednsProvider::with_base_url(&server.base_url());
+    let current = provider.current(&config).unwrap();
+
+    assert!(current[&Zone::new("example.com".into())].is_ok());
+    records_mock.assert_hits(2);
+}
+
+#[test]
+fn test_run_once_snapshots_zone_before_first_change() {
+    let server = MockServer::start();
+
+    mock_records(&server, "A", json!([]));
+    mock_records(&server, "AAAA", json!([]));
+
+    let snapshot_mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/domains/example.com/snapshots")
+            .header("Authorization", "Apikey secret");
+        then.status(201)
+            .json_body(json!({ "id": "snapshot", "message": "Snapshot Created" }));
+    });
+
+    let update_mock = server.mock(|when, then| {
+        when.method(PUT)
+            .path_contains("/domains/example.com/records/");
+        then.status(201);
+    });
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+override_ip:
+  ipv4: 203.0.113.10
+zones:
+  example.com:
+    credentials:
+      api_key: secret
+    records:
+      - a: home.example.com
+      - a: nas.example.com
+    snapshot: true
+"#,
+    )
+    .unwrap();
+
+    let provider = GandiLivednsProvider::with_base_url(&server.base_url());
+
+    Updater::new(&config, &provider).run_once().unwrap();
+
+    snapshot_mock.assert_hits(1);
+    update_mock.assert_hits(2);
+}
+
+#[test]
+fn test_rollback_restores_snapshot_zone_data() {
+    let server = MockServer::start();
+
+    let zone_data = json!([{
+        "rrset_name": "home",
+        "rrset_type": "A",
+        "rrset_ttl": 300,
+        "rrset_values": ["198.51.100.1"]
+    }]);
+
+    let snapshot_mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/domains/example.com/snapshots/snapshot")
+            .header("Authorization", "Apikey secret");
+        then.status(200)
+            .json_body(json!({ "id": "snapshot", "zone_data": zone_data }));
+    });
+
+    let restore_mock = server.mock(|when, then| {
+        when.method(PUT)
+            .path("/domains/example.com/records")
+            .header("Authorization", "Apikey secret")
+            .json_body(json!({ "items": zone_data }));
+        then.status(201);
+    });
+
+    let mut zone = Zone::new("example.com".into());
+    zone.credentials.insert("api_key".into(), "secret".into());
+
+    GandiLivednsProvider::with_base_url(&server.base_url())
+        .rollback(&zone, "snapshot")
+        .unwrap();
+
+    snapshot_mock.assert();
+    restore_mock.assert();
+}
+
+#[test]
+fn test_multi_valued_srv_untouched() {
+    let server = MockServer::start();
+
+    mock_records(&server, "A", json!([]));
+    mock_records(&server, "AAAA", json!([]));
+    mock_records(
+        &server,
+        "SRV",
+        json!([{
+            "rrset_type": "SRV",
+            "rrset_ttl": 300,
+            "rrset_name": "_sip._tcp",
+            "rrset_values": ["0 0 5060 home.example.com.", "10 0 5060 backup.example.com."]
+        }]),
+    );
+
+    let update_mock = server.mock(|when, then| {
+        when.method(PUT)
+            .path("/domains/example.com/records/_sip._tcp/SRV");
+        then.status(201);
+    });
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+zones:
+  example.com:
+    credentials:
+      api_key: secret
+    records:
+      - a: home.example.com
+        srv:
+          - name: _sip._tcp.example.com
+            port: 5061
+"#,
+    )
+    .unwrap();
+    let provider = GandiLivednsProvider::with_base_url(&server.base_url());
+    let zone = Zone::new("example.com".into());
+
+    let current = provider.current(&config).unwrap();
+    assert_eq!(2, current[&zone].as_ref().unwrap().len());
+
+    let record = Record::parse_service(
+        "SRV",
+        "_sip._tcp.example.com",
+        "0 0 5061 home.example.com.",
+        300,
+    )
+    .unwrap();
+    let err = provider.update(&zone, record).unwrap_err();
+
+    assert_eq!(Some(ProviderErrorKind::Unsupported), err.provider_kind());
+    update_mock.assert_hits(0);
+}
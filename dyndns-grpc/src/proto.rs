@@ -0,0 +1,105 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+//! Messages of `proto/provider.proto`, written out instead of generated so building doesn't require `protoc`.
+
+use std::collections::HashMap;
+
+pub const SERVICE: &str = "ezdyndns.provider.v1.DnsProvider";
+
+pub const CURRENT: &str = "/ezdyndns.provider.v1.DnsProvider/Current";
+pub const UPDATE: &str = "/ezdyndns.provider.v1.DnsProvider/Update";
+pub const DELETE: &str = "/ezdyndns.provider.v1.DnsProvider/Delete";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Zone {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, optional, tag = "2")]
+    pub id: Option<String>,
+    #[prost(map = "string, string", tag = "3")]
+    pub credentials: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Record {
+    #[prost(string, tag = "1")]
+    pub r#type: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+    #[prost(string, repeated, tag = "3")]
+    pub values: Vec<String>,
+    #[prost(uint32, tag = "4")]
+    pub ttl: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Status {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CurrentRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub zones: Vec<Zone>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ZoneRecords {
+    #[prost(message, optional, tag = "1")]
+    pub zone: Option<Zone>,
+    #[prost(message, repeated, tag = "2")]
+    pub records: Vec<Record>,
+    #[prost(message, optional, tag = "3")]
+    pub error: Option<Status>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CurrentResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub zones: Vec<ZoneRecords>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChangeRequest {
+    #[prost(message, optional, tag = "1")]
+    pub zone: Option<Zone>,
+    #[prost(message, optional, tag = "2")]
+    pub record: Option<Record>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChangeResponse {}
@@ -0,0 +1,265 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use dyndns::credentials::get_credential;
+use dyndns::environment;
+use dyndns::http::configured_user_agent;
+use dyndns::log::{error, warn};
+use dyndns::prelude::{
+    Config, DnsProvider, Error, ProviderErrorKind, Record, Result, Zone, ZoneResults,
+};
+use dyndns::provider::add_missing_zones;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, Endpoint};
+use tonic::Code;
+
+use crate::proto;
+
+static KEYRING_TOKEN: &str = "grpc/token";
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct GrpcProvider {
+    runtime: Runtime,
+    channel: std::result::Result<Channel, String>,
+    token: Option<String>,
+}
+
+impl Default for GrpcProvider {
+    fn default() -> Self {
+        GrpcProvider::new(environment::legacy_var("GRPC_PROVIDER_URL"))
+    }
+}
+
+impl GrpcProvider {
+    pub fn with_url(url: &str) -> Self {
+        GrpcProvider::new(Some(url.into()))
+    }
+
+    fn new(url: Option<String>) -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the gRPC client runtime");
+
+        // The channel spawns its connection task on the runtime, even if it connects lazily
+        let channel = {
+            let _guard = runtime.enter();
+
+            match url {
+                Some(url) => Endpoint::from_shared(url.clone())
+                    .map_err(|err| format!("invalid gRPC provider URL {}: {}", url, err))
+                    .and_then(|endpoint| {
+                        endpoint
+                            .user_agent(configured_user_agent())
+                            .map_err(|err| format!("invalid User-Agent: {}", err))
+                    })
+                    .map(|endpoint| endpoint.timeout(TIMEOUT).connect_lazy()),
+                None => Err("gRPC provider URL not configured".into()),
+            }
+        };
+
+        GrpcProvider {
+            runtime,
+            channel,
+            token: environment::legacy_var("GRPC_PROVIDER_TOKEN").or_else(|| match get_credential(
+                KEYRING_TOKEN,
+            ) {
+                Ok(token) => token,
+                Err(err) => {
+                    error!("{:?}", anyhow::Error::from(err));
+                    None
+                }
+            }),
+        }
+    }
+
+    fn call<Req, Res>(&self, path: &'static str, message: Req) -> Result<Res>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Res: prost::Message + Default + Send + Sync + 'static,
+    {
+        let channel = self
+            .channel
+            .clone()
+            .map_err(|err| Error::Config(err.into()))?;
+
+        let mut request = tonic::Request::new(message);
+        if let Some(token) = &self.token {
+            let authorization = format!("Bearer {}", token)
+                .parse()
+                .map_err(|_| Error::Config("invalid gRPC provider token".into()))?;
+            request
+                .metadata_mut()
+                .insert("authorization", authorization);
+        }
+
+        self.runtime.block_on(async move {
+            let mut client = tonic::client::Grpc::new(channel);
+            client
+                .ready()
+                .await
+                .map_err(|err| Error::provider(ProviderErrorKind::Unavailable, err))?;
+
+            client
+                .unary(
+                    request,
+                    PathAndQuery::from_static(path),
+                    ProstCodec::default(),
+                )
+                .await
+                .map(tonic::Response::into_inner)
+                .map_err(|status| Error::provider(error_kind(status.code()), status))
+        })
+    }
+
+    fn change(&self, path: &'static str, zone: &Zone, record: &Record) -> Result<()> {
+        self.call::<_, proto::ChangeResponse>(
+            path,
+            proto::ChangeRequest {
+                zone: Some(proto_zone(zone)),
+                record: Some(proto_record(record)),
+            },
+        )
+        .map(|_| ())
+    }
+}
+
+impl DnsProvider for GrpcProvider {
+    fn name(&self) -> &str {
+        "grpc"
+    }
+
+    fn current(&self, config: &Config) -> Result<ZoneResults> {
+        let zones = config
+            .zones
+            .iter()
+            .filter(|(_, zone_config)| zone_config.is_handled_by(self.name()))
+            .map(|(fqdn, zone_config)| Zone {
+                name: fqdn.to_string(),
+                id: zone_config.id.clone(),
+                credentials: zone_config.credentials.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let response: proto::CurrentResponse = self.call(
+            proto::CURRENT,
+            proto::CurrentRequest {
+                zones: zones.iter().map(proto_zone).collect(),
+            },
+        )?;
+
+        let mut results = response
+            .zones
+            .into_iter()
+            .filter_map(|zone_records| {
+                let zone = zone_records.zone?;
+                let zone = Zone {
+                    name: zone.name,
+                    id: zone.id,
+                    credentials: HashMap::new(),
+                };
+
+                let records = match zone_records.error {
+                    Some(status) => Err(Error::provider(
+                        error_kind(Code::from(status.code)),
+                        status.message,
+                    )),
+                    None => Ok(zone_records
+                        .records
+                        .into_iter()
+                        .filter_map(|record| parse_record(record, &zone.name))
+                        .collect()),
+                };
+
+                Some((zone, records))
+            })
+            .collect::<ZoneResults>();
+
+        add_missing_zones(&mut results, zones, "gRPC provider");
+
+        Ok(results)
+    }
+
+    fn update(&self, zone: &Zone, record: Record) -> Result<()> {
+        self.change(proto::UPDATE, zone, &record)
+    }
+
+    fn delete(&self, zone: &Zone, record: Record) -> Result<()> {
+        self.change(proto::DELETE, zone, &record)
+    }
+}
+
+fn error_kind(code: Code) -> ProviderErrorKind {
+    match code {
+        Code::Unauthenticated | Code::PermissionDenied => ProviderErrorKind::Authentication,
+        Code::NotFound => ProviderErrorKind::NotFound,
+        Code::ResourceExhausted => ProviderErrorKind::RateLimited,
+        Code::Unavailable | Code::DeadlineExceeded => ProviderErrorKind::Unavailable,
+        Code::Unimplemented => ProviderErrorKind::Unsupported,
+        _ => ProviderErrorKind::Other,
+    }
+}
+
+fn proto_zone(zone: &Zone) -> proto::Zone {
+    proto::Zone {
+        name: zone.name.clone(),
+        id: zone.id.clone(),
+        credentials: zone.credentials.clone(),
+    }
+}
+
+fn proto_record(record: &Record) -> proto::Record {
+    proto::Record {
+        r#type: record.record_type().into(),
+        name: record.name().into(),
+        values: record.values(),
+        ttl: record.ttl(),
+    }
+}
+
+fn parse_record(record: proto::Record, zone: &str) -> Option<Record> {
+    let parsed = Record::parse(&record.r#type, &record.name, &record.values, record.ttl);
+
+    if parsed.is_none() {
+        warn!(
+            "Ignoring invalid {} record {} of zone {} returned by the gRPC provider",
+            record.r#type, record.name, zone
+        );
+    }
+
+    parsed
+}
@@ -0,0 +1,226 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use dyndns::config::Config;
+use dyndns::provider::{DnsProvider, Zone};
+use dyndns::{serde_yaml, ProviderErrorKind, Updater};
+use dyndns_grpc::{proto, GrpcProvider};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http;
+use tonic::server::NamedService;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::Status;
+use tower::{service_fn, Service};
+
+fn config() -> Config {
+    serde_yaml::from_str(
+        r#"
+override_ip:
+  ipv4: 203.0.113.10
+zones:
+  example.com:
+    credentials:
+      api_key: secret
+    records:
+      - a: home.example.com
+"#,
+    )
+    .unwrap()
+}
+
+// Stands in for a provider service written in another language
+#[derive(Clone, Default)]
+struct FakeProvider {
+    zones: Arc<Mutex<Vec<proto::ZoneRecords>>>,
+    updates: Arc<Mutex<Vec<proto::ChangeRequest>>>,
+}
+
+impl Service<http::Request<BoxBody>> for FakeProvider {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let provider = self.clone();
+
+        Box::pin(async move {
+            Ok(match request.uri().path() {
+                proto::CURRENT => {
+                    unary(request, move |_: proto::CurrentRequest| {
+                        proto::CurrentResponse {
+                            zones: provider.zones.lock().unwrap().clone(),
+                        }
+                    })
+                    .await
+                }
+                proto::UPDATE => {
+                    unary(request, move |change: proto::ChangeRequest| {
+                        provider.updates.lock().unwrap().push(change);
+                        proto::ChangeResponse {}
+                    })
+                    .await
+                }
+                _ => Status::unimplemented("not implemented").into_http(),
+            })
+        })
+    }
+}
+
+impl NamedService for FakeProvider {
+    const NAME: &'static str = proto::SERVICE;
+}
+
+async fn unary<Req, Res, F>(request: http::Request<BoxBody>, handler: F) -> http::Response<BoxBody>
+where
+    Req: prost::Message + Default + Send + 'static,
+    Res: prost::Message + Send + 'static,
+    F: FnOnce(Req) -> Res + Clone + Send + 'static,
+{
+    let service = service_fn(move |request: tonic::Request<Req>| {
+        let handler = handler.clone();
+        async move { Ok::<_, Status>(tonic::Response::new(handler(request.into_inner()))) }
+    });
+
+    tonic::server::Grpc::new(ProstCodec::<Res, Req>::default())
+        .unary(service, request)
+        .await
+}
+
+fn serve(provider: FakeProvider) -> (Runtime, String) {
+    let runtime = Runtime::new().unwrap();
+
+    let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+
+    runtime.spawn(
+        Server::builder()
+            .add_service(provider)
+            .serve_with_incoming(incoming),
+    );
+
+    (runtime, url)
+}
+
+fn zone(name: &str) -> proto::Zone {
+    proto::Zone {
+        name: name.into(),
+        ..proto::Zone::default()
+    }
+}
+
+#[test]
+fn test_run_once_updates_changed_record() {
+    let provider = FakeProvider::default();
+    provider.zones.lock().unwrap().push(proto::ZoneRecords {
+        zone: Some(zone("example.com")),
+        records: vec![proto::Record {
+            r#type: "A".into(),
+            name: "home.example.com".into(),
+            values: vec!["198.51.100.1".into()],
+            ttl: 300,
+        }],
+        error: None,
+    });
+
+    let (_runtime, url) = serve(provider.clone());
+
+    let config = config();
+    Updater::new(&config, &GrpcProvider::with_url(&url))
+        .run_once()
+        .unwrap();
+
+    let updates = provider.updates.lock().unwrap();
+    assert_eq!(1, updates.len());
+
+    let update = &updates[0];
+    assert_eq!(
+        "secret",
+        update.zone.as_ref().unwrap().credentials["api_key"]
+    );
+
+    let record = update.record.as_ref().unwrap();
+    assert_eq!("A", record.r#type);
+    assert_eq!("home.example.com", record.name);
+    assert_eq!(vec!["203.0.113.10".to_string()], record.values);
+}
+
+#[test]
+fn test_current_zone_errors() {
+    let provider = FakeProvider::default();
+    provider.zones.lock().unwrap().push(proto::ZoneRecords {
+        zone: Some(zone("example.com")),
+        records: Vec::new(),
+        error: Some(proto::Status {
+            code: tonic::Code::PermissionDenied as i32,
+            message: "access denied".into(),
+        }),
+    });
+
+    let (_runtime, url) = serve(provider);
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+zones:
+  example.com:
+    - a: home.example.com
+  example.net:
+    - a: home.example.net
+"#,
+    )
+    .unwrap();
+
+    let current = GrpcProvider::with_url(&url).current(&config).unwrap();
+
+    let kind = |zone: &str| {
+        current[&Zone::new(zone.into())]
+            .as_ref()
+            .unwrap_err()
+            .provider_kind()
+    };
+    assert_eq!(Some(ProviderErrorKind::Authentication), kind("example.com"));
+    assert_eq!(Some(ProviderErrorKind::NotFound), kind("example.net"));
+}
@@ -0,0 +1,215 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use dyndns::credentials::get_credential;
+use dyndns::environment;
+use dyndns::http::{self, handle_response, with_retry};
+use dyndns::log::{debug, error};
+use dyndns::prelude::{
+    Config, DnsName, DnsProvider, Error, ProviderErrorKind, Record, Result, Zone, ZoneResults,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+static KEYRING_PASSWORD: &str = "relay/password";
+static DEFAULT_USERNAME: &str = "ez-dyndns";
+
+pub struct RelayProvider {
+    url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    // DynDNS2 can't list records, so the addresses pushed since the start stand in for them
+    pushed: Mutex<HashMap<Zone, Vec<Record>>>,
+}
+
+impl Default for RelayProvider {
+    fn default() -> Self {
+        RelayProvider {
+            url: environment::legacy_var("RELAY_URL"),
+            username: environment::legacy_var("RELAY_USERNAME"),
+            password: environment::legacy_var("RELAY_PASSWORD").or_else(|| {
+                match get_credential(KEYRING_PASSWORD) {
+                    Ok(password) => password,
+                    Err(err) => {
+                        error!("{:?}", anyhow::Error::from(err));
+                        None
+                    }
+                }
+            }),
+            pushed: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl RelayProvider {
+    pub fn with_url(url: &str) -> Self {
+        RelayProvider {
+            url: Some(url.into()),
+            ..RelayProvider::default()
+        }
+    }
+
+    fn push(&self, zone: &Zone, hostname: &str, addresses: &[String]) -> Result<()> {
+        let url = zone
+            .credential("url")
+            .or(self.url.as_deref())
+            .ok_or_else(|| Error::Config("relay URL not configured".into()))?;
+
+        let username = zone
+            .credential("username")
+            .or(self.username.as_deref())
+            .unwrap_or(DEFAULT_USERNAME);
+
+        let password = zone
+            .credential("password")
+            .or(self.password.as_deref())
+            .ok_or_else(|| Error::Config("relay password not configured".into()))?;
+
+        let request = http::get(url)
+            .query("hostname", hostname)
+            .query("myip", &addresses.join(","))
+            .set(
+                "Authorization",
+                &format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{}:{}", username, password))
+                ),
+            );
+
+        let body = match with_retry(|| handle_response(request.clone().call())) {
+            Ok(response) => response.into_string().map_err(|err| {
+                Error::provider(
+                    ProviderErrorKind::Other,
+                    anyhow::Error::from(err).context("failed to read the relay response"),
+                )
+            })?,
+            // Bridges answer badauth with 401, the body still tells what went wrong
+            Err(Error::Http(http_error)) if return_code(&http_error.body).is_some() => {
+                http_error.body
+            }
+            Err(err) => {
+                let kind = err.provider_kind().unwrap_or(ProviderErrorKind::Other);
+                return Err(Error::provider(
+                    kind,
+                    anyhow::Error::from(err).context("failed to call the relay"),
+                ));
+            }
+        };
+
+        match return_code(&body) {
+            Some("good") | Some("nochg") => {
+                debug!("Relay answered {}", body.trim());
+                Ok(())
+            }
+            code => Err(Error::provider(
+                code.map(error_kind).unwrap_or(ProviderErrorKind::Other),
+                format!("relay rejected the update of {}: {}", hostname, body.trim()),
+            )),
+        }
+    }
+}
+
+impl DnsProvider for RelayProvider {
+    fn name(&self) -> &str {
+        "relay"
+    }
+
+    fn current(&self, config: &Config) -> Result<ZoneResults> {
+        let pushed = self.pushed.lock().unwrap();
+
+        Ok(config
+            .zones
+            .iter()
+            .filter(|(_, zone_config)| zone_config.is_handled_by(self.name()))
+            .map(|(fqdn, _)| {
+                let zone = Zone::new(fqdn.to_string());
+                let records = pushed.get(&zone).cloned().unwrap_or_default();
+
+                (zone, Ok(records))
+            })
+            .collect())
+    }
+
+    fn update(&self, zone: &Zone, record: Record) -> Result<()> {
+        if !matches!(record, Record::A { .. } | Record::AAAA { .. }) {
+            return Err(Error::provider(
+                ProviderErrorKind::Unsupported,
+                format!("the relay only supports A and AAAA records ({})", record),
+            ));
+        }
+
+        self.push(zone, record.name(), &record.values())?;
+
+        let mut pushed = self.pushed.lock().unwrap();
+        let records = pushed.entry(zone.clone()).or_default();
+        records.retain(|current| {
+            current.record_type() != record.record_type()
+                || DnsName::from(current.name()) != DnsName::from(record.name())
+        });
+        records.push(record);
+
+        Ok(())
+    }
+}
+
+fn return_code(body: &str) -> Option<&str> {
+    let code = body.split_whitespace().next()?;
+
+    match code {
+        "good" | "nochg" | "badauth" | "nohost" | "notfqdn" | "numhost" | "abuse" | "badagent"
+        | "badip" | "dnserr" | "911" => Some(code),
+        _ => None,
+    }
+}
+
+fn error_kind(code: &str) -> ProviderErrorKind {
+    match code {
+        "badauth" => ProviderErrorKind::Authentication,
+        "nohost" | "notfqdn" => ProviderErrorKind::NotFound,
+        "abuse" => ProviderErrorKind::RateLimited,
+        "dnserr" | "911" => ProviderErrorKind::Unavailable,
+        _ => ProviderErrorKind::Other,
+    }
+}
+
+trait ZoneCredentials {
+    fn credential(&self, name: &str) -> Option<&str>;
+}
+
+impl ZoneCredentials for Zone {
+    fn credential(&self, name: &str) -> Option<&str> {
+        self.credentials.get(name).map(String::as_str)
+    }
+}
@@ -0,0 +1,152 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::io::Read;
+use std::path::Path;
+
+use dyndns::http;
+use dyndns::log::info;
+use dyndns::ureq;
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Config, Engine, Store, StoreLimits, StoreLimitsBuilder};
+
+pub(crate) use self::ez_dyndns::provider::types::*;
+
+wasmtime::component::bindgen!({ path: "wit", world: "plugin" });
+
+const MAX_MEMORY: usize = 64 << 20;
+const MAX_RESPONSE_SIZE: u64 = 10 << 20;
+// Roughly a few seconds of computation, stops plugins stuck in a loop
+const FUEL_PER_CALL: u64 = 10_000_000_000;
+
+pub(crate) struct PluginModule {
+    name: String,
+    pre: PluginPre<Host>,
+}
+
+impl PluginModule {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<PluginModule> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+
+        let engine = Engine::new(&config)?;
+        let component = Component::from_file(&engine, path)?;
+
+        let mut linker = Linker::new(&engine);
+        Plugin::add_to_linker(&mut linker, |host: &mut Host| host)?;
+
+        let pre = PluginPre::new(linker.instantiate_pre(&component)?)?;
+
+        let mut module = PluginModule {
+            name: String::new(),
+            pre,
+        };
+        module.name = module.call(|plugin, store| plugin.call_name(store))?;
+
+        Ok(module)
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Every call gets a fresh instance, so a trapped or misbehaving call can't affect the next one
+    pub(crate) fn call<T, F>(&self, call: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&Plugin, &mut Store<Host>) -> anyhow::Result<T>,
+    {
+        let mut store = Store::new(
+            self.pre.engine(),
+            Host {
+                limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+            },
+        );
+        store.limiter(|host| &mut host.limits);
+        store.set_fuel(FUEL_PER_CALL)?;
+
+        let plugin = self.pre.instantiate(&mut store)?;
+        call(&plugin, &mut store)
+    }
+}
+
+pub(crate) struct Host {
+    limits: StoreLimits,
+}
+
+impl PluginImports for Host {
+    fn send(&mut self, request: HttpRequest) -> Result<HttpResponse, String> {
+        let mut http_request = http::request(&request.method, &request.url);
+        for (name, value) in &request.headers {
+            http_request = http_request.set(name, value);
+        }
+
+        let result = match &request.body {
+            Some(body) => http_request.send_bytes(body),
+            None => http_request.call(),
+        };
+
+        let response = match result {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(ureq::Error::Transport(transport)) => return Err(transport.to_string()),
+        };
+
+        let status = response.status();
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_owned();
+                Some((name, value))
+            })
+            .collect();
+
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_RESPONSE_SIZE)
+            .read_to_end(&mut body)
+            .map_err(|err| err.to_string())?;
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    fn log(&mut self, message: String) {
+        info!("{}", message);
+    }
+}
+
+impl ez_dyndns::provider::types::Host for Host {}
@@ -0,0 +1,200 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use dyndns::environment;
+use dyndns::log::{error, warn};
+use dyndns::prelude::{
+    Config, DnsProvider, Error, ProviderErrorKind, Record, Result, Zone, ZoneResults,
+};
+use dyndns::provider::add_missing_zones;
+use std::path::{Path, PathBuf};
+
+use crate::plugin::{self, DnsRecord, ErrorKind, PluginModule, ProviderError};
+
+pub struct WasmProvider {
+    plugin: std::result::Result<PluginModule, String>,
+}
+
+impl Default for WasmProvider {
+    fn default() -> Self {
+        WasmProvider::new(environment::legacy_var_os("WASM_PROVIDER_PLUGIN").map(PathBuf::from))
+    }
+}
+
+impl WasmProvider {
+    pub fn with_plugin(path: &Path) -> Self {
+        WasmProvider::new(Some(path.into()))
+    }
+
+    fn new(path: Option<PathBuf>) -> Self {
+        let plugin = match path {
+            Some(path) => PluginModule::load(&path).map_err(|err| {
+                let message = format!("failed to load WASM plugin {}: {:#}", path.display(), err);
+                error!("{}", message);
+                message
+            }),
+            None => Err("WASM provider plugin not configured".into()),
+        };
+
+        WasmProvider { plugin }
+    }
+
+    fn plugin(&self) -> Result<&PluginModule> {
+        self.plugin
+            .as_ref()
+            .map_err(|err| Error::Config(err.clone().into()))
+    }
+}
+
+impl DnsProvider for WasmProvider {
+    fn name(&self) -> &str {
+        match &self.plugin {
+            Ok(plugin) => plugin.name(),
+            Err(_) => "wasm",
+        }
+    }
+
+    fn current(&self, config: &Config) -> Result<ZoneResults> {
+        let plugin = self.plugin()?;
+
+        let zones = config
+            .zones
+            .iter()
+            .filter(|(_, zone_config)| zone_config.is_handled_by(self.name()))
+            .map(|(fqdn, zone_config)| Zone {
+                name: fqdn.to_string(),
+                id: zone_config.id.clone(),
+                credentials: zone_config.credentials.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let plugin_zones = zones.iter().map(plugin_zone).collect::<Vec<_>>();
+        let zone_records = plugin
+            .call(|plugin, store| plugin.call_current(store, &plugin_zones))
+            .map_err(|err| plugin_failed(err, "current"))?;
+
+        let mut results = zone_records
+            .into_iter()
+            .map(|zone_records| {
+                let zone = Zone {
+                    id: zone_records.zone.id,
+                    ..Zone::new(zone_records.zone.name)
+                };
+
+                let records = zone_records
+                    .records
+                    .map(|records| {
+                        records
+                            .into_iter()
+                            .filter_map(|record| parse_record(record, &zone.name))
+                            .collect()
+                    })
+                    .map_err(provider_error);
+
+                (zone, records)
+            })
+            .collect::<ZoneResults>();
+
+        add_missing_zones(&mut results, zones, "plugin");
+
+        Ok(results)
+    }
+
+    fn update(&self, zone: &Zone, record: Record) -> Result<()> {
+        let plugin_zone = plugin_zone(zone);
+        let plugin_record = DnsRecord {
+            record_type: record.record_type().into(),
+            name: record.name().into(),
+            values: record.values(),
+            ttl: record.ttl(),
+        };
+
+        self.plugin()?
+            .call(|plugin, store| plugin.call_update(store, &plugin_zone, &plugin_record))
+            .map_err(|err| plugin_failed(err, "update"))?
+            .map_err(provider_error)
+    }
+}
+
+fn plugin_zone(zone: &Zone) -> plugin::Zone {
+    let mut credentials = zone
+        .credentials
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect::<Vec<_>>();
+    credentials.sort();
+
+    plugin::Zone {
+        name: zone.name.clone(),
+        id: zone.id.clone(),
+        credentials,
+    }
+}
+
+fn plugin_failed(err: anyhow::Error, function: &str) -> Error {
+    Error::provider(
+        ProviderErrorKind::Other,
+        err.context(format!("WASM plugin failed in {}", function)),
+    )
+}
+
+fn provider_error(err: ProviderError) -> Error {
+    let kind = match err.kind {
+        ErrorKind::Authentication => ProviderErrorKind::Authentication,
+        ErrorKind::NotFound => ProviderErrorKind::NotFound,
+        ErrorKind::RateLimited => ProviderErrorKind::RateLimited,
+        ErrorKind::Unavailable => ProviderErrorKind::Unavailable,
+        ErrorKind::Unsupported => ProviderErrorKind::Unsupported,
+        ErrorKind::Other => ProviderErrorKind::Other,
+    };
+
+    Error::provider(kind, err.message)
+}
+
+fn parse_record(record: DnsRecord, zone: &str) -> Option<Record> {
+    let parsed = Record::parse(
+        &record.record_type,
+        &record.name,
+        &record.values,
+        record.ttl,
+    );
+
+    if parsed.is_none() {
+        warn!(
+            "Ignoring invalid {} record {} of zone {} returned by the WASM plugin",
+            record.record_type, record.name, zone
+        );
+    }
+
+    parsed
+}
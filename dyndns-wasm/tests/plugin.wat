@@ -0,0 +1,71 @@
+;; Minimal plugin written by hand, so the tests don't need a WASM toolchain. It's called "example", lists no records
+;; for example.com and doesn't support updates.
+(component
+  (core module $plugin
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 4096))
+
+    ;; Bump allocator for the arguments passed by the host
+    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+      (local $ptr i32)
+      (local.set $ptr
+        (i32.and
+          (i32.add (global.get $heap) (i32.sub (local.get 2) (i32.const 1)))
+          (i32.sub (i32.const 0) (local.get 2))))
+      (global.set $heap (i32.add (local.get $ptr) (local.get 3)))
+      (local.get $ptr))
+
+    ;; name: the string "example"
+    (data (i32.const 0) "\10\00\00\00\07\00\00\00")
+    (data (i32.const 16) "example")
+    (func (export "name") (result i32)
+      (i32.const 0))
+
+    ;; current: a list of one zone-records, example.com without records
+    (data (i32.const 32) "\40\00\00\00\01\00\00\00")
+    (data (i32.const 64) "\80\00\00\00\0b\00\00\00")
+    (data (i32.const 128) "example.com")
+    (func (export "current") (param i32 i32) (result i32)
+      (i32.const 32))
+
+    ;; update: the error unsupported with the message "not supported"
+    (data (i32.const 160) "\01\00\00\00\04\00\00\00\c0\00\00\00\0d\00\00\00")
+    (data (i32.const 192) "not supported")
+    (func (export "update")
+      (param i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+      (i32.const 160)))
+
+  (core instance $instance (instantiate $plugin))
+
+  (type $zone' (record
+    (field "name" string)
+    (field "id" (option string))
+    (field "credentials" (list (tuple string string)))))
+  (export $zone "zone" (type $zone'))
+  (type $dns-record' (record
+    (field "record-type" string)
+    (field "name" string)
+    (field "values" (list string))
+    (field "ttl" u32)))
+  (export $dns-record "dns-record" (type $dns-record'))
+  (type $error-kind' (enum "authentication" "not-found" "rate-limited" "unavailable" "unsupported" "other"))
+  (export $error-kind "error-kind" (type $error-kind'))
+  (type $provider-error' (record
+    (field "kind" $error-kind)
+    (field "message" string)))
+  (export $provider-error "provider-error" (type $provider-error'))
+  (type $zone-records' (record
+    (field "zone" $zone)
+    (field "records" (result (list $dns-record) (error $provider-error)))))
+  (export $zone-records "zone-records" (type $zone-records'))
+
+  (func (export "name") (result string)
+    (canon lift (core func $instance "name")
+      (memory $instance "memory") (realloc (func $instance "realloc"))))
+  (func (export "current") (param "zones" (list $zone)) (result (list $zone-records))
+    (canon lift (core func $instance "current")
+      (memory $instance "memory") (realloc (func $instance "realloc"))))
+  (func (export "update") (param "zone" $zone) (param "record" $dns-record)
+    (result (result (error $provider-error)))
+    (canon lift (core func $instance "update")
+      (memory $instance "memory") (realloc (func $instance "realloc")))))
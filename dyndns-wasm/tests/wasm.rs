@@ -0,0 +1,106 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use dyndns::config::Config;
+use dyndns::provider::{DnsProvider, Record, Zone};
+use dyndns::{serde_yaml, Error, ProviderErrorKind};
+use dyndns_wasm::WasmProvider;
+use std::path::Path;
+
+fn provider() -> WasmProvider {
+    WasmProvider::with_plugin(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/plugin.wat"))
+}
+
+#[test]
+fn test_plugin() {
+    let provider = provider();
+    assert_eq!("example", provider.name());
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+zones:
+  example.com:
+    credentials:
+      api_key: secret
+    records:
+      - a: home.example.com
+  example.net:
+    - a: home.example.net
+"#,
+    )
+    .unwrap();
+
+    let current = provider.current(&config).unwrap();
+    assert!(current[&Zone::new("example.com".into())]
+        .as_ref()
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        Some(ProviderErrorKind::NotFound),
+        current[&Zone::new("example.net".into())]
+            .as_ref()
+            .unwrap_err()
+            .provider_kind()
+    );
+
+    let err = provider
+        .update(
+            &Zone::new("example.com".into()),
+            Record::A {
+                name: "home.example.com".into(),
+                values: vec!["203.0.113.10".parse().unwrap()],
+                ttl: 300,
+            },
+        )
+        .unwrap_err();
+    assert_eq!(Some(ProviderErrorKind::Unsupported), err.provider_kind());
+    assert!(format!("{:?}", err).contains("not supported"));
+}
+
+#[test]
+fn test_missing_plugin() {
+    let provider = WasmProvider::with_plugin(Path::new("missing.wasm"));
+    assert_eq!("wasm", provider.name());
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+zones:
+  example.com:
+    - a: home.example.com
+"#,
+    )
+    .unwrap();
+
+    let err = provider.current(&config).unwrap_err();
+    assert!(matches!(err, Error::Config(_)));
+}
@@ -0,0 +1,323 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+use anyhow::{bail, Context, Error};
+use log::{info, warn};
+
+use crate::config::{Config, ZoneConfig, DEFAULT_TTL};
+use crate::diff::diff;
+use crate::name::DnsName;
+use crate::provider::{Action, DnsProvider, Record, Zone};
+use crate::result::DynResult;
+use crate::rollback::find_zone;
+use crate::ttl::Ttl;
+
+pub(crate) fn add_record<D: DnsProvider>(
+    config: &Config,
+    provider: &D,
+    record: Record,
+) -> DynResult<()> {
+    check_ttl(provider, record.ttl())?;
+
+    let (zone, current) = current_record(config, provider, record.record_type(), record.name())?;
+
+    match diff(&[record], &current.into_iter().collect::<Vec<_>>()).remove(0) {
+        Action::Create(record) => apply(provider, &zone, Action::Create(record)),
+        Action::Update { current, .. } => bail!(
+            "record {} already exists, remove it first to replace it",
+            current
+        ),
+        action => {
+            info!("Record {} already exists", action.record());
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn remove_record<D: DnsProvider>(
+    config: &Config,
+    provider: &D,
+    record_type: &str,
+    name: &str,
+) -> DynResult<()> {
+    match current_record(config, provider, record_type, name)? {
+        (zone, Some(current)) => apply(provider, &zone, Action::Delete(current)),
+        (_, None) => bail!("{} record {} not found", record_type, name),
+    }
+}
+
+pub(crate) fn set_ttl<D: DnsProvider>(
+    config: &Config,
+    provider: &D,
+    record_type: &str,
+    name: &str,
+    ttl: Ttl,
+) -> DynResult<()> {
+    check_ttl(provider, ttl.get())?;
+
+    let (zone, current) = match current_record(config, provider, record_type, name)? {
+        (zone, Some(current)) => (zone, current),
+        (_, None) => bail!("{} record {} not found", record_type, name),
+    };
+
+    if current.ttl() == ttl.get() {
+        info!("TTL of record {} is already {} seconds", current, ttl);
+        return Ok(());
+    }
+
+    let mut desired = current.clone();
+    match &mut desired {
+        Record::A { ttl: value, .. }
+        | Record::AAAA { ttl: value, .. }
+        | Record::PTR { ttl: value, .. }
+        | Record::CNAME { ttl: value, .. }
+        | Record::SRV { ttl: value, .. }
+        | Record::HTTPS { ttl: value, .. } => *value = ttl.get(),
+    }
+
+    apply(provider, &zone, Action::Update { current, desired })
+}
+
+// TTL of records added without one, the default of the zone the record belongs to
+pub(crate) fn default_ttl<D: DnsProvider>(
+    config: &Config,
+    provider: &D,
+    name: &str,
+) -> DynResult<Ttl> {
+    let (_, zone_config) = configured_zone(config, provider, name)?;
+
+    Ok(zone_config.default_ttl.unwrap_or(DEFAULT_TTL))
+}
+
+// The most specific configured zone of the provider the record belongs to
+fn configured_zone<'a, D: DnsProvider>(
+    config: &'a Config,
+    provider: &D,
+    name: &str,
+) -> DynResult<(&'a String, &'a ZoneConfig)> {
+    let record_name = DnsName::from(name);
+
+    config
+        .zones
+        .iter()
+        .filter(|(zone, zone_config)| {
+            zone_config.is_handled_by(provider.name())
+                && record_name.is_in_zone(&DnsName::from(*zone))
+        })
+        .max_by_key(|(zone, _)| zone.len())
+        .with_context(|| format!("{} isn't part of any configured zone", name))
+}
+
+fn current_record<D: DnsProvider>(
+    config: &Config,
+    provider: &D,
+    record_type: &str,
+    name: &str,
+) -> DynResult<(Zone, Option<Record>)> {
+    let record_name = DnsName::from(name);
+    let (zone_name, zone_config) = configured_zone(config, provider, name)?;
+
+    // one-off changes to records the updater manages don't last
+    if zone_config
+        .records
+        .iter()
+        .flat_map(|record| record.names())
+        .any(|configured| DnsName::from(configured) == record_name)
+    {
+        warn!(
+            "Record {} is managed by the configuration, the next update may overwrite it",
+            name
+        );
+    }
+
+    let mut current_zones = provider
+        .current(config)
+        .context("failed to retrieve current DNS data")?;
+
+    let zone = find_zone(config, &current_zones, zone_name);
+    let records = match current_zones.remove(&zone) {
+        Some(Ok(records)) => records,
+        Some(Err(err)) => {
+            return Err(Error::from(err).context(format!(
+                "failed to retrieve current records of zone {}",
+                zone_name
+            )))
+        }
+        None => bail!("zone {} not found", zone_name),
+    };
+
+    let current = records.into_iter().find(|record| {
+        record.record_type() == record_type && DnsName::from(record.name()) == record_name
+    });
+
+    Ok((zone, current))
+}
+
+fn check_ttl<D: DnsProvider>(provider: &D, ttl: u32) -> DynResult<()> {
+    let range = provider.ttl_range();
+
+    match Ttl::try_from(ttl) {
+        Ok(ttl) if range.contains(&ttl) => Ok(()),
+        _ => bail!(
+            "TTL is {} seconds, {} supports {} to {} seconds",
+            ttl,
+            provider.name(),
+            range.start(),
+            range.end()
+        ),
+    }
+}
+
+fn apply<D: DnsProvider>(provider: &D, zone: &Zone, action: Action) -> DynResult<()> {
+    let message = match &action {
+        Action::Create(record) => format!("Created record {}", record),
+        Action::Update { desired, .. } => {
+            format!("Updated record {}, TTL {} seconds", desired, desired.ttl())
+        }
+        Action::Delete(record) => format!("Deleted record {}", record),
+        Action::Noop(record) => format!("Record {} is unchanged", record),
+    };
+
+    provider
+        .apply(zone, action)
+        .with_context(|| format!("failed to change records of zone {}", zone.name))?;
+
+    info!("{}", message);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::provider::ZoneResults;
+
+    struct Memory(RefCell<Vec<Record>>);
+
+    impl DnsProvider for Memory {
+        fn name(&self) -> &str {
+            "memory"
+        }
+
+        fn current(&self, _config: &Config) -> crate::Result<ZoneResults> {
+            Ok(HashMap::from([(
+                Zone::new("example.com".into()),
+                Ok(self.0.borrow().clone()),
+            )]))
+        }
+
+        fn update(&self, _zone: &Zone, record: Record) -> crate::Result<()> {
+            let mut records = self.0.borrow_mut();
+            records.retain(|current| {
+                current.record_type() != record.record_type() || current.name() != record.name()
+            });
+            records.push(record);
+            Ok(())
+        }
+
+        fn delete(&self, _zone: &Zone, record: Record) -> crate::Result<()> {
+            self.0.borrow_mut().retain(|current| current != &record);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_manage_records() {
+        let config: Config = serde_yaml::from_str("zones: { example.com: [] }").unwrap();
+        let provider = Memory(RefCell::new(Vec::new()));
+
+        let record = Record::CNAME {
+            name: "www.example.com".into(),
+            target: "example.com".into(),
+            ttl: 300,
+        };
+
+        add_record(&config, &provider, record.clone()).unwrap();
+        add_record(&config, &provider, record.clone()).unwrap();
+        assert_eq!(vec![record], *provider.0.borrow());
+
+        let replaced = Record::CNAME {
+            name: "www.example.com".into(),
+            target: "home.example.com".into(),
+            ttl: 300,
+        };
+        assert!(add_record(&config, &provider, replaced).is_err());
+
+        let outside = Record::CNAME {
+            name: "www.example.net".into(),
+            target: "example.com".into(),
+            ttl: 300,
+        };
+        assert!(add_record(&config, &provider, outside).is_err());
+
+        set_ttl(
+            &config,
+            &provider,
+            "CNAME",
+            "WWW.example.com.",
+            Ttl::from_secs(3600),
+        )
+        .unwrap();
+        assert_eq!(3600, provider.0.borrow()[0].ttl());
+
+        remove_record(&config, &provider, "CNAME", "www.example.com").unwrap();
+        assert!(provider.0.borrow().is_empty());
+
+        assert!(remove_record(&config, &provider, "CNAME", "www.example.com").is_err());
+    }
+
+    #[test]
+    fn test_default_ttl() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+zones:
+  example.com:
+    default_ttl: 600
+    records: []
+  home.example.com:
+    provider: other
+    default_ttl: 60
+    records: []
+"#,
+        )
+        .unwrap();
+        let provider = Memory(RefCell::new(Vec::new()));
+
+        assert_eq!(
+            Ttl::from_secs(600),
+            default_ttl(&config, &provider, "nas.home.example.com").unwrap()
+        );
+        assert!(default_ttl(&config, &provider, "www.example.net").is_err());
+    }
+}
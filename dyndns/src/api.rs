@@ -0,0 +1,960 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+use sha2::{Digest, Sha256};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::approval::{Approvals, Decision};
+use crate::config::{Config, DomainRecord};
+use crate::drift::PublishedRecord;
+use crate::host::host;
+#[cfg(feature = "blocking")]
+use crate::http::{agent_builder, handle_response};
+use crate::ip::ExternalIp;
+use crate::job::Sleeper;
+use crate::name::DnsName;
+use crate::result::DynResult;
+use crate::ttl::Ttl;
+
+// A day of runs even at an interval of 5 minutes
+const MAX_HISTORY: usize = 288;
+
+const UI: &str = include_str!("../assets/ui.html");
+
+// Routers often push each hostname with its own request
+const DEFAULT_BRIDGE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ApiConfig {
+    pub listen: SocketAddr,
+    pub token: String,
+    #[serde(default)]
+    pub debounce: DebounceConfig,
+}
+
+// Quiet periods after each kind of API request before the run starts, repeated requests restart them
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DebounceConfig {
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default)]
+    pub run: Duration,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_bridge_debounce")]
+    pub bridge: Duration,
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        DebounceConfig {
+            run: Duration::ZERO,
+            bridge: DEFAULT_BRIDGE_DEBOUNCE,
+        }
+    }
+}
+
+fn default_bridge_debounce() -> Duration {
+    DEFAULT_BRIDGE_DEBOUNCE
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Trigger {
+    // POST /run and TTL changes
+    Run,
+    // DynDNS2 updates of bridged records
+    Bridge,
+}
+
+// Shared between the scheduler and the API thread
+pub(crate) struct Control {
+    state: Mutex<ControlState>,
+    wakeup: Condvar,
+    debounce: DebounceConfig,
+    // The bridged hostnames with the token of the record or its zone, if any
+    bridged: HashMap<DnsName, Option<String>>,
+    approvals: Arc<Approvals>,
+    // TTLs accepted by the provider, overrides are checked against them
+    ttl_range: RangeInclusive<Ttl>,
+}
+
+#[derive(Default)]
+struct ControlState {
+    // When each pending trigger is due, the earliest one starts the run
+    pending: HashMap<Trigger, Instant>,
+    force: bool,
+    status: Status,
+    records: Vec<RecordStatus>,
+    ttls: HashMap<DnsName, Ttl>,
+    history: VecDeque<HistoryEntry>,
+    pushed: HashMap<DnsName, ExternalIp>,
+}
+
+#[derive(Clone, Serialize)]
+struct HistoryEntry {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    ip: ExternalIp,
+    success: bool,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub(crate) struct Status {
+    host: String,
+    paused: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_ip: Option<ExternalIp>,
+    last_run: Option<DateTime<Utc>>,
+    last_success: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+struct RecordStatus {
+    zone: String,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: &'static str,
+    ttl: Ttl,
+    values: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct TtlRequest {
+    ttl: Ttl,
+}
+
+impl Control {
+    pub(crate) fn new(config: &Config, ttl_range: RangeInclusive<Ttl>) -> Control {
+        let mut zones = config.zones.iter().collect::<Vec<_>>();
+        zones.sort_by_key(|(zone, _)| DnsName::from(*zone));
+
+        let records = zones
+            .into_iter()
+            .flat_map(|(zone, zone_config)| {
+                zone_config.records.iter().flat_map(move |record| {
+                    let status = move |name: &String, record_type, values| RecordStatus {
+                        zone: zone.clone(),
+                        name: name.clone(),
+                        record_type,
+                        ttl: record.ttl(),
+                        values,
+                    };
+
+                    record
+                        .a
+                        .iter()
+                        .map(move |name| status(name, "A", Vec::new()))
+                        .chain(
+                            record
+                                .aaaa
+                                .iter()
+                                .map(move |name| status(name, "AAAA", Vec::new())),
+                        )
+                        .chain(record.cname.iter().map(move |cname| {
+                            status(&cname.name, "CNAME", vec![cname.target.clone()])
+                        }))
+                })
+            })
+            .collect();
+
+        let bridged = config
+            .zones
+            .values()
+            .flat_map(|zone_config| {
+                zone_config
+                    .records
+                    .iter()
+                    .filter(|record| record.bridged)
+                    .flat_map(move |record| {
+                        let token = record
+                            .bridge_token
+                            .clone()
+                            .or_else(|| zone_config.bridge_token.clone());
+
+                        record
+                            .a
+                            .iter()
+                            .chain(record.aaaa.iter())
+                            .map(move |name| (DnsName::from(name), token.clone()))
+                    })
+            })
+            .collect();
+
+        Control {
+            state: Mutex::new(ControlState {
+                records,
+                status: Status {
+                    host: host(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            wakeup: Condvar::new(),
+            debounce: config
+                .api
+                .as_ref()
+                .map(|api| api.debounce.clone())
+                .unwrap_or_default(),
+            bridged,
+            approvals: Arc::new(Approvals::default()),
+            ttl_range,
+        }
+    }
+
+    pub(crate) fn approvals(&self) -> Arc<Approvals> {
+        self.approvals.clone()
+    }
+
+    pub(crate) fn pushed(&self, record: &DomainRecord) -> Option<ExternalIp> {
+        let state = self.state.lock().unwrap();
+        let pushed = |name: &Option<String>| state.pushed.get(&DnsName::from(name.as_ref()?));
+
+        let ip = ExternalIp {
+            ipv4: pushed(&record.a).and_then(|ip| ip.ipv4),
+            ipv6: pushed(&record.aaaa).and_then(|ip| ip.ipv6),
+        };
+
+        (ip != ExternalIp::default()).then_some(ip)
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().status.paused
+    }
+
+    pub(crate) fn take_force(&self) -> bool {
+        std::mem::take(&mut self.state.lock().unwrap().force)
+    }
+
+    pub(crate) fn ttl(&self, name: &str) -> Option<Ttl> {
+        self.state
+            .lock()
+            .unwrap()
+            .ttls
+            .get(&DnsName::from(name))
+            .copied()
+    }
+
+    pub(crate) fn published(&self, current_ip: &ExternalIp, published: &[PublishedRecord]) {
+        let mut state = self.state.lock().unwrap();
+        state.status.current_ip = Some(*current_ip);
+
+        for record in published {
+            let record_type = if record.ipv6 { "AAAA" } else { "A" };
+            let name = DnsName::from(&record.name);
+
+            if let Some(status) = state.records.iter_mut().find(|status| {
+                status.record_type == record_type && DnsName::from(&status.name) == name
+            }) {
+                status.ttl = record.ttl;
+                status.values = record
+                    .addresses
+                    .iter()
+                    .map(|address| address.to_string())
+                    .collect();
+            }
+        }
+    }
+
+    pub(crate) fn finished(&self, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now();
+
+        state.status.last_run = Some(now);
+        if error.is_none() {
+            state.status.last_success = Some(now);
+        }
+        state.status.last_error = error;
+
+        let entry = HistoryEntry {
+            time: now,
+            ip: state.status.current_ip.unwrap_or_default(),
+            success: state.status.last_error.is_none(),
+        };
+
+        if state.history.len() >= MAX_HISTORY {
+            state.history.pop_front();
+        }
+        state.history.push_back(entry);
+    }
+
+    fn trigger(&self, trigger: Trigger, force: bool) {
+        let quiet_period = match trigger {
+            Trigger::Run => self.debounce.run,
+            Trigger::Bridge => self.debounce.bridge,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.pending.insert(trigger, Instant::now() + quiet_period);
+        state.force |= force;
+        self.wakeup.notify_all();
+    }
+
+    // Runs the job right away, without the quiet period of the triggers
+    pub(crate) fn wake(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.insert(Trigger::Run, Instant::now());
+        self.wakeup.notify_all();
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) -> Status {
+        let mut state = self.state.lock().unwrap();
+        state.status.paused = paused;
+        state.status.clone()
+    }
+
+    // Whether the token is valid for any of the bridged hostnames
+    fn is_bridge_token(&self, provided: &str) -> bool {
+        self.bridged
+            .values()
+            .flatten()
+            .any(|token| token_matches(provided, token))
+    }
+
+    // None if the hostname isn't a bridged record of the token, otherwise whether its address changed
+    fn push(&self, hostname: &str, ip: ExternalIp, provided: &str, admin: bool) -> Option<bool> {
+        let name = DnsName::from(hostname);
+
+        match self.bridged.get(&name) {
+            Some(_) if admin => {}
+            Some(Some(token)) if token_matches(provided, token) => {}
+            _ => return None,
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let pushed = state.pushed.entry(name).or_default();
+        let previous = *pushed;
+
+        pushed.ipv4 = ip.ipv4.or(pushed.ipv4);
+        pushed.ipv6 = ip.ipv6.or(pushed.ipv6);
+
+        Some(*pushed != previous)
+    }
+
+    fn set_ttl(&self, name: &str, ttl: Ttl) -> Vec<RecordStatus> {
+        let mut state = self.state.lock().unwrap();
+        let name = DnsName::from(name);
+
+        let records = state
+            .records
+            .iter_mut()
+            .filter(|status| DnsName::from(&status.name) == name)
+            .map(|status| {
+                status.ttl = ttl;
+                status.clone()
+            })
+            .collect::<Vec<_>>();
+
+        if !records.is_empty() {
+            state.ttls.insert(name, ttl);
+        }
+
+        records
+    }
+}
+
+impl Sleeper for Control {
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            let now = Instant::now();
+            let wake_at = match state.pending.values().min() {
+                Some(due) if *due <= now => {
+                    state.pending.clear();
+                    return true;
+                }
+                Some(due) => deadline.min(*due),
+                None => deadline,
+            };
+
+            if wake_at <= now {
+                return false;
+            }
+
+            state = self.wakeup.wait_timeout(state, wake_at - now).unwrap().0;
+        }
+    }
+}
+
+pub(crate) fn serve(config: &ApiConfig, control: Arc<Control>) -> DynResult<()> {
+    let server = Server::http(config.listen)
+        .map_err(|err| Error::msg(format!("failed to listen on {}: {}", config.listen, err)))?;
+
+    info!("HTTP API listening on {}", config.listen);
+
+    spawn(server, config.token.clone(), control);
+    Ok(())
+}
+
+// Requests to the API of an updater running on this host, e.g. from the command line
+pub(crate) fn call(config: &ApiConfig, method: &str, path: &str) -> DynResult<serde_json::Value> {
+    let mut address = config.listen;
+    if address.ip().is_unspecified() {
+        address.set_ip(match address {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+
+    send(
+        method,
+        &format!("http://{}{}", address, path),
+        &config.token,
+    )
+}
+
+#[cfg(feature = "blocking")]
+fn send(method: &str, url: &str, token: &str) -> DynResult<serde_json::Value> {
+    let response = handle_response(
+        agent_builder(None, None)
+            .build()
+            .request(method, url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .call(),
+    )?;
+
+    Ok(response.into_json()?)
+}
+
+#[cfg(not(feature = "blocking"))]
+fn send(_method: &str, url: &str, _token: &str) -> DynResult<serde_json::Value> {
+    Err(Error::msg(format!(
+        "failed to call {}: this executable was built without blocking HTTP support",
+        url
+    )))
+}
+
+fn spawn(server: Server, token: String, control: Arc<Control>) {
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            if let Err(err) = handle(&token, &control, request) {
+                error!("Failed to respond to API request: {}", err);
+            }
+        }
+    });
+}
+
+fn handle(token: &str, control: &Control, mut request: Request) -> std::io::Result<()> {
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    if let (Method::Get, "/") = (request.method(), path.as_str()) {
+        return request.respond(
+            Response::from_string(UI)
+                .with_header(header("Content-Type", "text/html; charset=utf-8"))
+                .with_header(header(
+                    "Content-Security-Policy",
+                    "default-src 'none'; script-src 'unsafe-inline'; style-src 'unsafe-inline'; connect-src 'self'",
+                )),
+        );
+    }
+
+    if let (Method::Get, "/nic/update") = (request.method(), path.as_str()) {
+        let response = nic_update(token, control, &request);
+        return request.respond(response);
+    }
+
+    if let (Method::Get, "/health") = (request.method(), path.as_str()) {
+        let healthy = control.state.lock().unwrap().status.last_error.is_none();
+        let code = if healthy { 200 } else { 503 };
+
+        return request.respond(json_response(
+            code,
+            &serde_json::json!({ "healthy": healthy }),
+        ));
+    }
+
+    if !is_authorized(token, &request) {
+        return request.respond(
+            error_response(401, "missing or invalid token")
+                .with_header(header("WWW-Authenticate", "Bearer")),
+        );
+    }
+
+    if let Some((approval_token, decision)) = path
+        .strip_prefix("/approvals/")
+        .and_then(|path| path.split_once('/'))
+    {
+        let decision = match decision {
+            "approve" => Decision::Approved,
+            "reject" => Decision::Rejected,
+            _ => return request.respond(error_response(404, "not found")),
+        };
+
+        let response = if request.method() != &Method::Post {
+            error_response(405, "method not allowed")
+        } else if control.approvals.decide(approval_token, decision) {
+            info!("Change {} via the HTTP API", decision.as_str());
+            json_response(200, &serde_json::json!({ "decision": decision.as_str() }))
+        } else {
+            error_response(404, "no change waiting for approval with that token")
+        };
+
+        return request.respond(response);
+    }
+
+    let ttl_name = path
+        .strip_prefix("/records/")
+        .and_then(|path| path.strip_suffix("/ttl"))
+        .map(percent_decode);
+
+    let response = match (request.method(), path.as_str(), ttl_name) {
+        (Method::Get, "/status", _) => {
+            json_response(200, &control.state.lock().unwrap().status.clone())
+        }
+        (Method::Get, "/records", _) => {
+            json_response(200, &control.state.lock().unwrap().records.clone())
+        }
+        (Method::Get, "/history", _) => {
+            json_response(200, &control.state.lock().unwrap().history.clone())
+        }
+        (Method::Post, "/run", _) if control.is_paused() => {
+            error_response(409, "updater is paused")
+        }
+        (Method::Post, "/run", _) => {
+            info!("Update requested via the HTTP API");
+            control.trigger(Trigger::Run, false);
+            json_response(202, &control.state.lock().unwrap().status.clone())
+        }
+        (Method::Post, "/pause", _) => {
+            info!("Paused via the HTTP API");
+            json_response(200, &control.set_paused(true))
+        }
+        (Method::Post, "/resume", _) => {
+            info!("Resumed via the HTTP API");
+            json_response(200, &control.set_paused(false))
+        }
+        (Method::Put, _, Some(name)) => {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body)?;
+
+            match serde_json::from_str::<TtlRequest>(&body) {
+                Err(_) => error_response(400, "expected a body like {\"ttl\": 300}"),
+                Ok(TtlRequest { ttl }) if !control.ttl_range.contains(&ttl) => error_response(
+                    400,
+                    &format!(
+                        "TTL of {} seconds is out of the range supported by the provider ({} to {})",
+                        ttl,
+                        control.ttl_range.start(),
+                        control.ttl_range.end()
+                    ),
+                ),
+                Ok(TtlRequest { ttl }) => match control.set_ttl(&name, ttl) {
+                    records if records.is_empty() => {
+                        error_response(404, &format!("record {} is not configured", name))
+                    }
+                    records => {
+                        info!("TTL of {} set to {} via the HTTP API", name, ttl);
+                        control.trigger(Trigger::Run, true);
+                        json_response(200, &records)
+                    }
+                },
+            }
+        }
+        (_, "/status" | "/records" | "/history" | "/run" | "/pause" | "/resume", _)
+        | (_, _, Some(_)) => error_response(405, "method not allowed"),
+        _ => error_response(404, "not found"),
+    };
+
+    request.respond(response)
+}
+
+// DynDNS2 compatible, so routers can push their WAN address to bridged records
+fn nic_update(token: &str, control: &Control, request: &Request) -> Response<Cursor<Vec<u8>>> {
+    let provided = provided_token(request).unwrap_or_default();
+    let admin = token_matches(&provided, token);
+
+    if !admin && !control.is_bridge_token(&provided) {
+        return text_response(401, "badauth")
+            .with_header(header("WWW-Authenticate", "Basic realm=\"ez-dyndns\""));
+    }
+
+    let query = query_params(request.url());
+
+    let hostnames = match query.get("hostname") {
+        Some(hostnames) if !hostnames.trim().is_empty() => hostnames,
+        _ => return text_response(400, "notfqdn"),
+    };
+
+    let mut addresses = query
+        .get("myip")
+        .into_iter()
+        .chain(query.get("myipv6"))
+        .flat_map(|addresses| addresses.split(','))
+        .filter(|address| !address.trim().is_empty())
+        .peekable();
+
+    let mut ip = ExternalIp::default();
+    if addresses.peek().is_some() {
+        for address in addresses {
+            match address.trim().parse() {
+                Ok(IpAddr::V4(ipv4)) => ip.ipv4 = Some(ipv4),
+                Ok(IpAddr::V6(ipv6)) => ip.ipv6 = Some(ipv6),
+                Err(_) => return text_response(400, "badip"),
+            }
+        }
+    } else {
+        match request.remote_addr().map(SocketAddr::ip) {
+            Some(IpAddr::V4(ipv4)) => ip.ipv4 = Some(ipv4),
+            Some(IpAddr::V6(ipv6)) => match ipv6.to_ipv4_mapped() {
+                Some(ipv4) => ip.ipv4 = Some(ipv4),
+                None => ip.ipv6 = Some(ipv6),
+            },
+            None => return text_response(400, "badip"),
+        }
+    }
+
+    let ip_text = [ip.ipv4.map(IpAddr::V4), ip.ipv6.map(IpAddr::V6)]
+        .into_iter()
+        .flatten()
+        .map(|address| address.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut changed = false;
+    let lines = hostnames
+        .split(',')
+        .map(
+            |hostname| match control.push(hostname.trim(), ip, &provided, admin) {
+                Some(true) => {
+                    info!(
+                        "Received {} for {} via /nic/update",
+                        ip_text,
+                        hostname.trim()
+                    );
+                    changed = true;
+                    format!("good {}", ip_text)
+                }
+                Some(false) => format!("nochg {}", ip_text),
+                None => "nohost".into(),
+            },
+        )
+        .collect::<Vec<_>>();
+
+    if changed {
+        control.trigger(Trigger::Bridge, false);
+    }
+
+    text_response(200, &lines.join("\n"))
+}
+
+fn is_authorized(token: &str, request: &Request) -> bool {
+    provided_token(request).is_some_and(|provided| token_matches(&provided, token))
+}
+
+// Accepts the token as bearer token or as password of basic authentication, which routers support
+fn provided_token(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| match header.value.as_str().split_once(' ') {
+            Some(("Bearer", provided)) => Some(provided.trim().to_string()),
+            Some(("Basic", credentials)) => STANDARD
+                .decode(credentials.trim())
+                .ok()
+                .and_then(|credentials| String::from_utf8(credentials).ok())
+                .and_then(|credentials| Some(credentials.split_once(':')?.1.to_string())),
+            _ => None,
+        })
+}
+
+// Compares digests, so the time taken doesn't depend on how much of the token matched
+fn token_matches(provided: &str, token: &str) -> bool {
+    !provided.is_empty() && Sha256::digest(provided) == Sha256::digest(token)
+}
+
+fn query_params(url: &str) -> HashMap<String, String> {
+    url.split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            Some((
+                percent_decode(name),
+                percent_decode(&value.replace('+', " ")),
+            ))
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn text_response(code: u16, body: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_data(format!("{}\n", body).into_bytes())
+        .with_status_code(code)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+fn json_response<T: Serialize>(code: u16, body: &T) -> Response<Cursor<Vec<u8>>> {
+    let mut body = serde_json::to_vec_pretty(body).unwrap_or_default();
+    body.push(b'\n');
+
+    Response::from_data(body)
+        .with_status_code(code)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error_response(code: u16, message: &str) -> Response<Cursor<Vec<u8>>> {
+    json_response(code, &serde_json::json!({ "error": message }))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::load_config;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_api() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"---\nzones:\n  example.com:\n    - a: home.example.com\n")
+            .unwrap();
+
+        let control = Arc::new(Control::new(
+            &load_config(file).unwrap(),
+            Ttl::from_secs(60)..=Ttl::from_secs(86_400),
+        ));
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        spawn(server, "secret".into(), control.clone());
+
+        let request = |method: &str, path: &str| {
+            ureq::request(method, &format!("{}{}", base_url, path))
+                .set("Authorization", "Bearer secret")
+        };
+
+        let status = |result: Result<ureq::Response, ureq::Error>| match result {
+            Ok(response) => response.status(),
+            Err(ureq::Error::Status(status, _)) => status,
+            Err(err) => panic!("{}", err),
+        };
+
+        assert_eq!(200, status(ureq::get(&base_url).call()));
+        assert_eq!(
+            200,
+            status(ureq::get(&format!("{}/health", base_url)).call())
+        );
+        assert_eq!(
+            401,
+            status(ureq::get(&format!("{}/status", base_url)).call())
+        );
+
+        assert_eq!(200, status(request("POST", "/pause").call()));
+        assert!(control.is_paused());
+        assert_eq!(409, status(request("POST", "/run").call()));
+        assert_eq!(200, status(request("POST", "/resume").call()));
+        assert_eq!(202, status(request("POST", "/run").call()));
+
+        assert_eq!(
+            404,
+            status(request("POST", "/approvals/unknown/approve").call())
+        );
+        assert_eq!(
+            405,
+            status(request("GET", "/approvals/unknown/approve").call())
+        );
+        assert!(control.sleep(Duration::from_secs(5)));
+
+        control.finished(Some("failed to detect the external IP address".into()));
+        assert_eq!(
+            503,
+            status(ureq::get(&format!("{}/health", base_url)).call())
+        );
+
+        let history: serde_json::Value = request("GET", "/history")
+            .call()
+            .unwrap()
+            .into_json()
+            .unwrap();
+        assert_eq!(false, history[0]["success"]);
+
+        assert_eq!(
+            404,
+            status(request("PUT", "/records/nas.example.com/ttl").send_string("{\"ttl\": 600}"))
+        );
+        assert_eq!(
+            400,
+            status(request("PUT", "/records/home.example.com/ttl").send_string("{\"ttl\": 30}"))
+        );
+        assert_eq!(None, control.ttl("home.example.com"));
+        assert_eq!(
+            200,
+            status(request("PUT", "/records/home.example.com/ttl").send_string("{\"ttl\": 600}"))
+        );
+        assert_eq!(Some(Ttl::from_secs(600)), control.ttl("Home.example.com"));
+        assert!(control.take_force());
+
+        let records: serde_json::Value = request("GET", "/records")
+            .call()
+            .unwrap()
+            .into_json()
+            .unwrap();
+        assert_eq!(600, records[0]["ttl"]);
+    }
+
+    #[test]
+    fn test_debounce() {
+        let config: Config = serde_yaml::from_str(
+            "{ api: { listen: '127.0.0.1:0', token: secret, debounce: { run: 1 } }, zones: {} }",
+        )
+        .unwrap();
+
+        let control = Control::new(&config, Ttl::MIN..=Ttl::MAX);
+        control.trigger(Trigger::Run, false);
+        assert!(!control.sleep(Duration::from_millis(100)));
+
+        // Restarts the quiet period
+        let triggered_at = Instant::now();
+        control.trigger(Trigger::Run, false);
+        assert!(control.sleep(Duration::from_secs(5)));
+        assert!(triggered_at.elapsed() >= Duration::from_secs(1));
+
+        assert!(!control.sleep(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_nic_update() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            b"---\napi:\n  listen: 127.0.0.1:0\n  token: secret-api-token\nzones:\n  example.com:\n    - a: router.example.com\n      bridged: true\n    - a: home.example.com\n  example.net:\n    bridge_token: friend\n    records:\n      - a: home.example.net\n        bridged: true\n",
+        )
+        .unwrap();
+
+        let config = load_config(file).unwrap();
+        let control = Arc::new(Control::new(&config, Ttl::MIN..=Ttl::MAX));
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        spawn(server, "secret-api-token".into(), control.clone());
+
+        let update = |query: &str, password: &str| {
+            let credentials = STANDARD.encode(format!("router:{}", password));
+
+            match ureq::get(&format!("{}/nic/update?{}", base_url, query))
+                .set("Authorization", &format!("Basic {}", credentials))
+                .call()
+            {
+                Ok(response) => response.into_string().unwrap(),
+                Err(ureq::Error::Status(_, response)) => response.into_string().unwrap(),
+                Err(err) => panic!("{}", err),
+            }
+        };
+
+        assert_eq!("badauth\n", update("hostname=router.example.com", "wrong"));
+        assert_eq!(
+            "good 203.0.113.10\nnohost\n",
+            update(
+                "hostname=router.example.com,home.example.com&myip=203.0.113.10",
+                "secret-api-token"
+            )
+        );
+        assert_eq!(
+            "nochg 203.0.113.10\n",
+            update(
+                "hostname=router.example.com&myip=203.0.113.10",
+                "secret-api-token"
+            )
+        );
+        assert!(control.sleep(Duration::from_secs(5)));
+
+        let records = &config.zones["example.com"].records;
+        assert_eq!(
+            Some("203.0.113.10".parse().unwrap()),
+            control.pushed(&records[0]).and_then(|ip| ip.ipv4)
+        );
+        assert_eq!(None, control.pushed(&records[1]));
+
+        assert_eq!(
+            "good 198.51.100.7\nnohost\n",
+            update(
+                "hostname=home.example.net,router.example.com&myip=198.51.100.7",
+                "friend"
+            )
+        );
+        assert_eq!(
+            Some("203.0.113.10".parse().unwrap()),
+            control.pushed(&records[0]).and_then(|ip| ip.ipv4)
+        );
+    }
+}
@@ -0,0 +1,310 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "blocking")]
+use anyhow::Context;
+use chrono::Utc;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+use crate::host::host;
+#[cfg(feature = "blocking")]
+use crate::http::{self, handle_response};
+use crate::logging::timestamp;
+use crate::result::DynResult;
+
+const DEFAULT_TIMEOUT: u64 = 600;
+
+#[cfg(feature = "blocking")]
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ApprovalConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_timeout")]
+    pub timeout: Duration,
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(DEFAULT_TIMEOUT)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Decision {
+    Approved,
+    Rejected,
+}
+
+impl Decision {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Decision::Approved => "approved",
+            Decision::Rejected => "rejected",
+        }
+    }
+}
+
+// Changes waiting for a decision, shared with the API thread that receives them
+#[derive(Default)]
+pub(crate) struct Approvals {
+    pending: Mutex<HashMap<String, Option<Decision>>>,
+    decided: Condvar,
+}
+
+impl Approvals {
+    // false if there is no pending change with that token
+    pub(crate) fn decide(&self, token: &str, decision: Decision) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+
+        match pending.get_mut(token) {
+            Some(entry @ None) => {
+                *entry = Some(decision);
+                self.decided.notify_all();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn propose(&self) -> String {
+        let mut bytes = [0u8; 16];
+        getrandom::getrandom(&mut bytes).expect("failed to generate a random token");
+
+        let token = bytes.iter().fold(String::new(), |mut token, byte| {
+            write!(token, "{:02x}", byte).unwrap();
+            token
+        });
+
+        self.pending.lock().unwrap().insert(token.clone(), None);
+        token
+    }
+
+    fn wait(&self, token: &str, timeout: Duration) -> Option<Decision> {
+        let deadline = Instant::now() + timeout;
+        let mut pending = self.pending.lock().unwrap();
+
+        loop {
+            let now = Instant::now();
+
+            match pending.get(token) {
+                Some(Some(_)) => return pending.remove(token).flatten(),
+                _ if now >= deadline => {
+                    pending.remove(token);
+                    return None;
+                }
+                _ => {}
+            }
+
+            pending = self
+                .decided
+                .wait_timeout(pending, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProposedChange<'a> {
+    zone: &'a str,
+    change: &'a str,
+}
+
+#[derive(Serialize)]
+struct Proposal<'a> {
+    token: &'a str,
+    host: String,
+    changes: Vec<ProposedChange<'a>>,
+    expires: String,
+}
+
+pub(crate) struct Approver {
+    config: ApprovalConfig,
+    approvals: Arc<Approvals>,
+}
+
+impl Approver {
+    pub(crate) fn new(config: &ApprovalConfig, approvals: Arc<Approvals>) -> Approver {
+        Approver {
+            config: config.clone(),
+            approvals,
+        }
+    }
+
+    // Blocks until the changes of the run, as (zone, change), are approved, rejected or the timeout expires
+    pub(crate) fn approve(&self, changes: &[(String, String)]) -> bool {
+        let token = self.approvals.propose();
+        let expires = Utc::now()
+            + chrono::Duration::from_std(self.config.timeout).unwrap_or(chrono::Duration::zero());
+
+        if let Some(webhook) = &self.config.webhook {
+            let proposal = Proposal {
+                token: &token,
+                host: host(),
+                changes: changes
+                    .iter()
+                    .map(|(zone, change)| ProposedChange { zone, change })
+                    .collect(),
+                expires: timestamp(expires),
+            };
+
+            if let Err(err) = send_proposal(webhook, &proposal) {
+                error!("{:?}", err);
+                self.approvals.pending.lock().unwrap().remove(&token);
+                return false;
+            }
+        }
+
+        info!(
+            "Waiting for approval of {} changes until {} (token {})",
+            changes.len(),
+            timestamp(expires),
+            token
+        );
+        for (zone, change) in changes {
+            info!("  {}: {}", zone, change);
+        }
+
+        match self.approvals.wait(&token, self.config.timeout) {
+            Some(Decision::Approved) => {
+                info!("Changes approved (token {})", token);
+                true
+            }
+            Some(Decision::Rejected) => {
+                warn!("Changes rejected (token {})", token);
+                false
+            }
+            None => {
+                warn!("Changes not approved in time (token {})", token);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+fn send_proposal(webhook: &str, proposal: &Proposal) -> DynResult<()> {
+    handle_response(
+        http::post(webhook)
+            .timeout(WEBHOOK_TIMEOUT)
+            .send_json(proposal),
+    )
+    .context("failed to send the changes to the approval webhook")?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "blocking"))]
+fn send_proposal(_webhook: &str, _proposal: &Proposal) -> DynResult<()> {
+    Err(anyhow::Error::msg(
+        "failed to send the changes to the approval webhook: this executable was built without blocking HTTP support",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn test_approvals() {
+        let approvals = Arc::new(Approvals::default());
+
+        let token = approvals.propose();
+        assert!(!approvals.decide("unknown", Decision::Approved));
+
+        let deciding = approvals.clone();
+        let decided = token.clone();
+        let handle = thread::spawn(move || deciding.decide(&decided, Decision::Rejected));
+
+        assert_eq!(
+            Some(Decision::Rejected),
+            approvals.wait(&token, Duration::from_secs(10))
+        );
+        assert!(handle.join().unwrap());
+
+        // decided changes are forgotten, so they can't be decided again
+        assert!(!approvals.decide(&token, Decision::Approved));
+
+        let token = approvals.propose();
+        assert_eq!(None, approvals.wait(&token, Duration::from_millis(10)));
+        assert!(!approvals.decide(&token, Decision::Approved));
+    }
+
+    #[test]
+    fn test_approve_changes_together() {
+        let approvals = Arc::new(Approvals::default());
+        let config = ApprovalConfig {
+            webhook: None,
+            timeout: Duration::from_secs(10),
+        };
+        let approver = Approver::new(&config, approvals.clone());
+
+        let handle = thread::spawn(move || loop {
+            let tokens = approvals
+                .pending
+                .lock()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>();
+            match tokens.as_slice() {
+                [] => thread::sleep(Duration::from_millis(10)),
+                [token] => break approvals.decide(token, Decision::Approved),
+                _ => panic!("changes proposed separately: {:?}", tokens),
+            }
+        });
+
+        let changes = vec![
+            (
+                "example.com".to_string(),
+                "+ a.example.com A 192.0.2.1".to_string(),
+            ),
+            (
+                "example.com".to_string(),
+                "+ b.example.com A 192.0.2.1".to_string(),
+            ),
+        ];
+        assert!(approver.approve(&changes));
+        assert!(handle.join().unwrap());
+    }
+}
@@ -0,0 +1,281 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+//! Runs the updater for thousands of cycles against an in-memory provider and fails if memory
+//! or file descriptor usage keeps growing, see "Tests" in the README.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use dyndns::config::load_config;
+use dyndns::ip::IpSource;
+use dyndns::prelude::{Config, DnsProvider, Record, Result, Updater, Zone, ZoneResults};
+
+#[derive(Parser)]
+#[command(about = "Checks that repeated runs don't accumulate memory or file descriptors")]
+struct Cli {
+    #[arg(long, default_value_t = 5000)]
+    cycles: u32,
+    // The external address changes every this many cycles
+    #[arg(long, default_value_t = 10)]
+    change_every: u32,
+    #[arg(long, default_value_t = 8)]
+    max_growth_mib: u64,
+}
+
+#[derive(Default)]
+struct MemoryProvider {
+    zones: RefCell<HashMap<String, Vec<Record>>>,
+}
+
+impl DnsProvider for MemoryProvider {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn current(&self, config: &Config) -> Result<ZoneResults> {
+        let zones = self.zones.borrow();
+
+        Ok(config
+            .zones
+            .keys()
+            .map(|zone| {
+                let records = zones.get(zone).cloned().unwrap_or_default();
+                (Zone::new(zone.clone()), Ok(records))
+            })
+            .collect())
+    }
+
+    fn update(&self, zone: &Zone, record: Record) -> Result<()> {
+        let mut zones = self.zones.borrow_mut();
+        let records = zones.entry(zone.name.clone()).or_default();
+
+        records.retain(|existing| {
+            existing.name() != record.name() || existing.record_type() != record.record_type()
+        });
+        records.push(record);
+
+        Ok(())
+    }
+
+    fn delete(&self, zone: &Zone, record: Record) -> Result<()> {
+        if let Some(records) = self.zones.borrow_mut().get_mut(&zone.name) {
+            records.retain(|existing| *existing != record);
+        }
+
+        Ok(())
+    }
+}
+
+struct ChangingIpSource {
+    cycle: Rc<Cell<u32>>,
+    change_every: u32,
+}
+
+impl ChangingIpSource {
+    fn generation(&self) -> u32 {
+        self.cycle.get() / self.change_every.max(1)
+    }
+}
+
+impl IpSource for ChangingIpSource {
+    fn ipv4(&self) -> Result<Option<Ipv4Addr>> {
+        let generation = self.generation();
+        Ok(Some(Ipv4Addr::new(
+            203,
+            0,
+            113,
+            (generation % 254 + 1) as u8,
+        )))
+    }
+
+    fn ipv6(&self) -> Result<Option<Ipv6Addr>> {
+        let generation = self.generation();
+        Ok(Some(Ipv6Addr::new(
+            0x2001,
+            0xdb8,
+            0,
+            0,
+            0,
+            0,
+            0,
+            (generation % 0xffff + 1) as u16,
+        )))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Usage {
+    rss_kib: u64,
+    fds: usize,
+}
+
+// Only Linux exposes both values without extra dependencies
+fn usage() -> anyhow::Result<Usage> {
+    let status =
+        fs::read_to_string("/proc/self/status").context("failed to read process status")?;
+
+    let rss_kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .ok_or_else(|| anyhow!("VmRSS missing from process status"))?;
+
+    let fds = fs::read_dir("/proc/self/fd")
+        .context("failed to list file descriptors")?
+        .count();
+
+    Ok(Usage { rss_kib, fds })
+}
+
+fn write_config(dir: &Path) -> anyhow::Result<PathBuf> {
+    let path = dir.join("config.yml");
+
+    let config = format!(
+        r#"---
+state_file: {}
+ip_filter:
+  allow_non_public: true # documentation addresses
+api: # only keeps the status and history, the server isn't started
+  listen: 127.0.0.1:0
+  token: soak-test-api-token
+zones:
+  example.com:
+    prune: true
+    records:
+      - a: example.com
+        aaaa: example.com
+      - a: home.example.com
+        aaaa: home.example.com
+        ttl: 600
+      - a: nas.example.com
+        after: home.example.com
+      - cname: www.example.com -> home.example.com
+  example.net:
+    - aaaa: vpn.example.net
+"#,
+        dir.join("state.yml").display()
+    );
+
+    fs::write(&path, config).context("failed to write config")?;
+    Ok(path)
+}
+
+fn soak(cli: &Cli) -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("dyndns-soak-{}", std::process::id()));
+    fs::create_dir_all(&dir).context("failed to create working directory")?;
+
+    let config = load_config(write_config(&dir)?)?;
+    let provider = MemoryProvider::default();
+    let current_cycle = Rc::new(Cell::new(0));
+    let updater = Updater::new(&config, &provider).with_ip_source(ChangingIpSource {
+        cycle: current_cycle.clone(),
+        change_every: cli.change_every,
+    });
+
+    // Caches and bounded histories fill up during the first runs
+    let warmup = (cli.cycles / 10).max(1);
+    let mut baseline = None;
+
+    for cycle in 0..cli.cycles {
+        current_cycle.set(cycle);
+        updater
+            .run_once()
+            .with_context(|| format!("run {} failed", cycle + 1))?;
+
+        if cycle + 1 == warmup {
+            baseline = Some(usage()?);
+        }
+
+        if (cycle + 1) % 1000 == 0 {
+            let current = usage()?;
+            println!(
+                "{} runs: {} KiB resident, {} file descriptors",
+                cycle + 1,
+                current.rss_kib,
+                current.fds
+            );
+        }
+    }
+
+    fs::remove_dir_all(&dir).ok();
+
+    let baseline = baseline.ok_or_else(|| anyhow!("no runs"))?;
+    let end = usage()?;
+
+    let growth_kib = end.rss_kib.saturating_sub(baseline.rss_kib);
+    if growth_kib > cli.max_growth_mib * 1024 {
+        return Err(anyhow!(
+            "resident memory grew by {} KiB after the warmup ({} KiB -> {} KiB)",
+            growth_kib,
+            baseline.rss_kib,
+            end.rss_kib
+        ));
+    }
+
+    if end.fds > baseline.fds {
+        return Err(anyhow!(
+            "file descriptors grew after the warmup ({} -> {})",
+            baseline.fds,
+            end.fds
+        ));
+    }
+
+    let records = provider
+        .zones
+        .borrow()
+        .values()
+        .map(Vec::len)
+        .sum::<usize>();
+    println!(
+        "Stable after {} runs with {} records: {} KiB growth, {} file descriptors",
+        cli.cycles, records, growth_kib, end.fds
+    );
+
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    if let Err(err) = soak(&cli) {
+        eprintln!("Soak test failed: {:?}", err);
+        exit(1);
+    }
+}
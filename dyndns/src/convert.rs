@@ -0,0 +1,202 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::config::{Config, Credentials, DomainRecord, CONFIG_VERSION};
+use crate::ip::{InterfaceConfig, IpSourcesConfig};
+use crate::result::DynResult;
+use crate::ttl::Ttl;
+
+mod ddclient;
+mod ddns_go;
+mod inadyn;
+
+pub(crate) use ddclient::from_ddclient;
+pub(crate) use ddns_go::from_ddns_go;
+pub(crate) use inadyn::from_inadyn;
+
+pub(crate) struct Conversion {
+    pub config: Config,
+    pub warnings: Vec<String>,
+}
+
+impl Conversion {
+    fn new() -> Conversion {
+        let mut config: Config = serde_yaml::from_str("{}").expect("empty config is valid");
+        config.version = CONFIG_VERSION;
+
+        Conversion {
+            config,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn warn(&mut self, warning: String) {
+        self.warnings.push(warning);
+    }
+
+    fn credentials(&mut self, provider: &str, secret: Option<&str>, hosts: &str) -> Credentials {
+        let mut credentials = Credentials::new();
+
+        match (provider, secret) {
+            ("gandi-livedns", Some(secret)) => {
+                credentials.insert("api_key".into(), secret.into());
+            }
+            ("aws-route53", Some(_)) => self.warn(format!(
+                "credentials of {} are not carried over, provide them using AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+                hosts
+            )),
+            _ => {}
+        }
+
+        credentials
+    }
+
+    fn set_ip_source(&mut self, ipv6: bool, url: &str) {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            self.warn(format!(
+                "IP address source {} is not supported, using the default",
+                url
+            ));
+            return;
+        }
+
+        let ip_sources = self
+            .config
+            .ip_sources
+            .get_or_insert_with(IpSourcesConfig::default);
+
+        if ipv6 {
+            ip_sources.ipv6 = url.into();
+        } else {
+            ip_sources.ipv4 = url.into();
+        }
+    }
+
+    fn set_interface(&mut self, name: &str) {
+        match &self.config.interface {
+            Some(interface) if interface.name != name => self.warn(format!(
+                "only one interface is supported, ignoring {}",
+                name
+            )),
+            _ => {
+                self.config.interface = Some(InterfaceConfig {
+                    name: name.into(),
+                    all_ipv6: false,
+                })
+            }
+        }
+    }
+
+    fn add_record(
+        &mut self,
+        zone: &str,
+        provider: &str,
+        credentials: Credentials,
+        ttl: Option<Ttl>,
+        (ipv4, ipv6): (bool, bool),
+        host: &str,
+    ) {
+        let zone_config = self.config.zones.entry(zone.into()).or_default();
+        zone_config.provider = Some(provider.into());
+        zone_config.credentials.extend(credentials);
+
+        zone_config.records.push(DomainRecord {
+            a: ipv4.then(|| host.into()),
+            aaaa: ipv6.then(|| host.into()),
+            cname: None,
+            srv: Vec::new(),
+            https: Vec::new(),
+            ttl,
+            ptr: false,
+            providers: Vec::new(),
+            tags: Vec::new(),
+            failover: None,
+            additional_addresses: Vec::new(),
+            value: None,
+            vars: BTreeMap::new(),
+            bridged: false,
+            bridge_token: None,
+            after: Vec::new(),
+        });
+    }
+
+    pub(crate) fn to_yaml(&self) -> DynResult<String> {
+        Ok(serde_yaml::to_string(&self.config)?)
+    }
+}
+
+fn provider_for(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+
+    if name.contains("gandi") {
+        Some("gandi-livedns")
+    } else if name.contains("route53") {
+        Some("aws-route53")
+    } else {
+        None
+    }
+}
+
+fn parse_interval(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    let (number, factor) = match value.char_indices().last()? {
+        (i, 's') => (&value[..i], 1),
+        (i, 'm') => (&value[..i], 60),
+        (i, 'h') => (&value[..i], 3600),
+        (i, 'd') => (&value[..i], 86400),
+        _ => (value, 1),
+    };
+
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(|number| Duration::from_secs(number * factor))
+}
+
+fn parse_ttl(value: &str) -> Option<Ttl> {
+    value
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .and_then(|ttl| Ttl::try_from(ttl).ok())
+}
+
+fn guess_zone(host: &str) -> String {
+    let labels = host.trim_end_matches('.').split('.').collect::<Vec<_>>();
+    labels[labels.len().saturating_sub(2)..].join(".")
+}
@@ -0,0 +1,329 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::collections::HashMap;
+
+use crate::result::DynResult;
+
+use super::{guess_zone, parse_interval, parse_ttl, provider_for, Conversion};
+
+type Options = HashMap<String, String>;
+
+pub(crate) fn from_ddclient(source: &str) -> DynResult<Conversion> {
+    let mut conversion = Conversion::new();
+    let mut globals = Options::new();
+
+    for line in logical_lines(source) {
+        let (options, hosts) = parse_line(&line);
+
+        if hosts.is_empty() {
+            globals.extend(options);
+            continue;
+        }
+
+        let mut entry = globals.clone();
+        entry.extend(options);
+
+        convert_entry(&mut conversion, &entry, &hosts);
+    }
+
+    convert_globals(&mut conversion, &globals);
+
+    Ok(conversion)
+}
+
+fn convert_entry(conversion: &mut Conversion, options: &Options, hosts: &[String]) {
+    let protocol = options
+        .get("protocol")
+        .map(String::as_str)
+        .unwrap_or("dyndns2");
+
+    let provider = match provider_for(protocol) {
+        Some(provider) => provider,
+        None => {
+            conversion.warn(format!(
+                "protocol {} of {} is not supported, skipping",
+                protocol,
+                hosts.join(", ")
+            ));
+            return;
+        }
+    };
+
+    if is_enabled(options.get("use-personal-access-token")) {
+        conversion.warn(format!(
+            "personal access tokens are not supported, replace the api_key of {} with a LiveDNS API key",
+            hosts.join(", ")
+        ));
+    }
+
+    let credentials = conversion.credentials(
+        provider,
+        options.get("password").map(String::as_str),
+        &hosts.join(", "),
+    );
+
+    let ttl = options.get("ttl").and_then(|ttl| parse_ttl(ttl));
+
+    let ipv4 = !matches!(options.get("usev4").map(String::as_str), Some("disabled"));
+    let ipv6 = matches!(options.get("usev6"), Some(usev6) if usev6 != "disabled")
+        || is_enabled(options.get("ipv6"));
+
+    for host in hosts {
+        let zone = match options.get("zone") {
+            Some(zone) => zone.clone(),
+            None => guess_zone(host),
+        };
+
+        conversion.add_record(
+            &zone,
+            provider,
+            credentials.clone(),
+            ttl,
+            (ipv4, ipv6),
+            host,
+        );
+    }
+}
+
+fn convert_globals(conversion: &mut Conversion, globals: &Options) {
+    if let Some(daemon) = globals.get("daemon") {
+        match parse_interval(daemon) {
+            Some(interval) => conversion.config.interval = interval,
+            None => conversion.warn(format!("invalid daemon interval {}, ignoring", daemon)),
+        }
+    }
+
+    for (method_key, ipv6) in [("use", false), ("usev4", false), ("usev6", true)] {
+        let method = match globals.get(method_key) {
+            Some(method) => method.as_str(),
+            None => continue,
+        };
+
+        let suffix = method_key.trim_start_matches("use");
+
+        let (setting, value) = match method {
+            "disabled" => continue,
+            "if" | "ifv4" | "ifv6" => ("if", globals.get(&format!("if{}", suffix))),
+            "web" | "webv4" | "webv6" => ("web", globals.get(&format!("web{}", suffix))),
+            _ => {
+                conversion.warn(format!(
+                    "{}={} is not supported, using the default IP address sources",
+                    method_key, method
+                ));
+                continue;
+            }
+        };
+
+        match (setting, value) {
+            ("if", Some(interface)) => conversion.set_interface(interface),
+            ("web", Some(url)) => conversion.set_ip_source(ipv6, url),
+            _ => conversion.warn(format!(
+                "{}={} without {}{}, ignoring",
+                method_key, method, setting, suffix
+            )),
+        }
+    }
+}
+
+fn is_enabled(value: Option<&String>) -> bool {
+    matches!(value.map(String::as_str), Some("yes" | "true" | "1"))
+}
+
+fn logical_lines(source: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for line in source.lines() {
+        let line = strip_comment(line);
+
+        match line.trim_end().strip_suffix('\\') {
+            Some(continued) => {
+                current.push_str(continued);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(line);
+
+                if !current.trim().is_empty() {
+                    lines.push(current.trim().to_string());
+                }
+
+                current.clear();
+            }
+        }
+    }
+
+    if !current.trim().is_empty() {
+        lines.push(current.trim().to_string());
+    }
+
+    lines
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn parse_line(line: &str) -> (Options, Vec<String>) {
+    let mut options = Options::new();
+    let mut hosts = Vec::new();
+
+    for token in tokenize(line) {
+        match token.split_once('=') {
+            Some((key, value)) => {
+                options.insert(
+                    key.trim().to_lowercase().replace('_', "-"),
+                    unquote(value.trim()),
+                );
+            }
+            None => hosts.push(token),
+        }
+    }
+
+    (options, hosts)
+}
+
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (Some(q), c) if q == c => {
+                quote = None;
+                current.push(c);
+            }
+            (None, c) if c == ',' || c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            (_, c) => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    // "key = value" is split into three tokens
+    let mut joined: Vec<String> = Vec::new();
+    for token in tokens {
+        match joined.last_mut() {
+            Some(last) if last.ends_with('=') || token.starts_with('=') => last.push_str(&token),
+            _ => joined.push(token),
+        }
+    }
+
+    joined
+}
+
+fn unquote(value: &str) -> String {
+    for quote in ['\'', '"'] {
+        if let Some(unquoted) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return unquoted.into();
+        }
+    }
+
+    value.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_from_ddclient() {
+        let conversion = from_ddclient(
+            r#"
+# ddclient.conf
+daemon=5m
+use=web, web=https://ipv4.example.net/ip
+ssl=yes
+
+protocol=gandi, \
+zone=example.com, password='secret#1'
+home.example.com, nas.example.com
+
+protocol=dyndns2
+server=members.dyndns.org, login=user, password=pass
+myhost.dyndns.org
+"#,
+        )
+        .unwrap();
+
+        let config = conversion.config;
+        assert_eq!(Duration::from_secs(300), config.interval);
+        assert_eq!(
+            "https://ipv4.example.net/ip",
+            config.ip_sources.unwrap().ipv4
+        );
+
+        let zone_config = &config.zones["example.com"];
+        assert_eq!(Some("gandi-livedns"), zone_config.provider.as_deref());
+        assert_eq!("secret#1", zone_config.credentials["api_key"]);
+        assert_eq!(
+            vec!["home.example.com", "nas.example.com"],
+            zone_config
+                .records
+                .iter()
+                .flat_map(|record| record.a.as_deref())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(1, conversion.warnings.len());
+        assert!(conversion.warnings[0].contains("dyndns2"));
+    }
+}
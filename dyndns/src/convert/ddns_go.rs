@@ -0,0 +1,228 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use serde::Deserialize;
+
+use crate::result::DynResult;
+
+use super::{guess_zone, parse_ttl, provider_for, Conversion};
+
+#[derive(Deserialize)]
+struct DdnsGoConfig {
+    #[serde(default)]
+    dnsconf: Vec<DnsConfig>,
+}
+
+#[derive(Deserialize)]
+struct DnsConfig {
+    #[serde(default)]
+    ipv4: IpConfig,
+    #[serde(default)]
+    ipv6: IpConfig,
+    #[serde(default)]
+    dns: DnsProviderConfig,
+    #[serde(default)]
+    ttl: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct IpConfig {
+    enable: bool,
+    gettype: String,
+    url: String,
+    netinterface: String,
+    domains: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct DnsProviderConfig {
+    name: String,
+    secret: String,
+}
+
+pub(crate) fn from_ddns_go(source: &str) -> DynResult<Conversion> {
+    let ddns_go_config: DdnsGoConfig = serde_yaml::from_str(source)?;
+
+    let mut conversion = Conversion::new();
+
+    for dns_config in &ddns_go_config.dnsconf {
+        let domains = [&dns_config.ipv4, &dns_config.ipv6]
+            .into_iter()
+            .filter(|ip_config| ip_config.enable)
+            .flat_map(|ip_config| ip_config.domains.iter())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if domains.is_empty() {
+            continue;
+        }
+
+        let provider = match provider_for(&dns_config.dns.name) {
+            Some(provider) => provider,
+            None => {
+                conversion.warn(format!(
+                    "DNS provider {} of {} is not supported, skipping",
+                    dns_config.dns.name, domains
+                ));
+                continue;
+            }
+        };
+
+        let secret = Some(dns_config.dns.secret.as_str()).filter(|secret| !secret.is_empty());
+        let credentials = conversion.credentials(provider, secret, &domains);
+
+        let ttl = parse_ttl(&dns_config.ttl);
+
+        for (ip_config, ipv6) in [(&dns_config.ipv4, false), (&dns_config.ipv6, true)] {
+            if !ip_config.enable {
+                continue;
+            }
+
+            match ip_config.gettype.as_str() {
+                "url" => {
+                    // ddns-go accepts a comma separated list of URLs, only the first one is used
+                    if let Some(url) = ip_config
+                        .url
+                        .split(',')
+                        .map(str::trim)
+                        .find(|url| !url.is_empty())
+                    {
+                        conversion.set_ip_source(ipv6, url);
+                    }
+                }
+                "netInterface" if !ip_config.netinterface.is_empty() => {
+                    conversion.set_interface(&ip_config.netinterface)
+                }
+                gettype => conversion.warn(format!(
+                    "IP address source {} is not supported, using the default",
+                    gettype
+                )),
+            }
+
+            for domain in &ip_config.domains {
+                let (zone, host) = parse_domain(domain);
+
+                conversion.add_record(
+                    &zone,
+                    provider,
+                    credentials.clone(),
+                    ttl,
+                    (!ipv6, ipv6),
+                    &host,
+                );
+            }
+        }
+    }
+
+    Ok(conversion)
+}
+
+// domains are written as host.example.com or host:example.com, optionally followed by ?param=value
+fn parse_domain(domain: &str) -> (String, String) {
+    let domain = domain.split_once('?').map_or(domain, |(domain, _)| domain);
+
+    match domain.split_once(':') {
+        Some(("" | "@", zone)) => (zone.into(), zone.into()),
+        Some((subdomain, zone)) => (zone.into(), format!("{}.{}", subdomain, zone)),
+        None => (guess_zone(domain), domain.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ttl::Ttl;
+
+    #[test]
+    fn test_from_ddns_go() {
+        let conversion = from_ddns_go(
+            r#"
+dnsconf:
+  - ipv4:
+      enable: true
+      gettype: url
+      url: https://ip4.example.net, https://ddns.oray.com/checkip
+      domains:
+        - home.example.com
+        - nas:example.co.uk?TTL=600
+    ipv6:
+      enable: true
+      gettype: netInterface
+      netinterface: eth0
+      domains:
+        - home.example.com
+    dns:
+      name: gandi
+      secret: secret
+    ttl: "300"
+  - ipv4:
+      enable: true
+      gettype: url
+      domains:
+        - www.example.org
+    dns:
+      name: alidns
+      id: id
+      secret: secret
+"#,
+        )
+        .unwrap();
+
+        let config = conversion.config;
+        assert_eq!("https://ip4.example.net", config.ip_sources.unwrap().ipv4);
+        assert_eq!("eth0", config.interface.unwrap().name);
+
+        let zone_config = &config.zones["example.com"];
+        assert_eq!(Some("gandi-livedns"), zone_config.provider.as_deref());
+        assert_eq!("secret", zone_config.credentials["api_key"]);
+        assert_eq!(Some(Ttl::from_secs(300)), zone_config.records[0].ttl);
+        assert_eq!(
+            Some("home.example.com"),
+            zone_config.records[1].aaaa.as_deref()
+        );
+
+        assert_eq!(
+            Some("nas.example.co.uk"),
+            config.zones["example.co.uk"].records[0].a.as_deref()
+        );
+
+        assert!(conversion
+            .warnings
+            .iter()
+            .any(|warning| warning.contains("alidns")));
+    }
+}
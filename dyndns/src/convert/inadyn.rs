@@ -0,0 +1,322 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use anyhow::{bail, Context};
+
+use crate::result::DynResult;
+
+use super::{guess_zone, parse_interval, parse_ttl, provider_for, Conversion};
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Equals,
+    Open,
+    Close,
+    Comma,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Scalar(String),
+    List(Vec<String>),
+    Section(String, Vec<(String, Value)>),
+}
+
+pub(crate) fn from_inadyn(source: &str) -> DynResult<Conversion> {
+    let mut tokens = tokenize(source).into_iter();
+    let settings = parse_settings(&mut tokens, false)?;
+
+    let mut conversion = Conversion::new();
+    let allow_ipv6 = is_enabled(scalar(&settings, "allow-ipv6"));
+
+    for (key, value) in &settings {
+        match (key.as_str(), value) {
+            ("period", Value::Scalar(period)) => match parse_interval(period) {
+                Some(interval) => conversion.config.interval = interval,
+                None => conversion.warn(format!("invalid period {}, ignoring", period)),
+            },
+            ("iface", Value::Scalar(iface)) => conversion.set_interface(iface),
+            ("provider", Value::Section(name, settings)) => {
+                convert_provider(&mut conversion, name, settings, allow_ipv6)
+            }
+            ("custom", Value::Section(name, _)) => conversion.warn(format!(
+                "custom provider {} is not supported, skipping",
+                name
+            )),
+            _ => {}
+        }
+    }
+
+    Ok(conversion)
+}
+
+fn convert_provider(
+    conversion: &mut Conversion,
+    name: &str,
+    settings: &[(String, Value)],
+    allow_ipv6: bool,
+) {
+    let hosts = settings
+        .iter()
+        .filter(|(key, _)| key == "hostname")
+        .flat_map(|(_, value)| match value {
+            Value::Scalar(host) => vec![host.clone()],
+            Value::List(hosts) => hosts.clone(),
+            Value::Section(..) => Vec::new(),
+        })
+        .collect::<Vec<_>>();
+
+    // plugin names look like default@dyndns.org, optionally with an index: default@dyndns.org:2
+    let plugin = name.split_once(':').map_or(name, |(plugin, _)| plugin);
+
+    let provider = match provider_for(plugin) {
+        Some(provider) => provider,
+        None => {
+            conversion.warn(format!(
+                "provider {} of {} is not supported, skipping",
+                plugin,
+                hosts.join(", ")
+            ));
+            return;
+        }
+    };
+
+    let credentials =
+        conversion.credentials(provider, scalar(settings, "password"), &hosts.join(", "));
+
+    let ttl = scalar(settings, "ttl").and_then(parse_ttl);
+
+    if let Some(server) = scalar(settings, "checkip-server") {
+        let scheme = match scalar(settings, "checkip-ssl") {
+            Some("false") => "http",
+            _ => "https",
+        };
+
+        let url = format!(
+            "{}://{}{}",
+            scheme,
+            server,
+            scalar(settings, "checkip-path").unwrap_or_default()
+        );
+
+        conversion.set_ip_source(false, &url);
+    }
+
+    if scalar(settings, "checkip-command").is_some() {
+        conversion.warn(format!(
+            "checkip-command of provider {} is not supported, using the default IP address sources",
+            name
+        ));
+    }
+
+    for host in &hosts {
+        conversion.add_record(
+            &guess_zone(host),
+            provider,
+            credentials.clone(),
+            ttl,
+            (true, allow_ipv6),
+            host,
+        );
+    }
+}
+
+fn scalar<'a>(settings: &'a [(String, Value)], key: &str) -> Option<&'a str> {
+    settings.iter().find_map(|(k, value)| match value {
+        Value::Scalar(value) if k == key => Some(value.as_str()),
+        _ => None,
+    })
+}
+
+fn is_enabled(value: Option<&str>) -> bool {
+    matches!(value, Some("true" | "yes" | "on"))
+}
+
+fn parse_settings<I>(tokens: &mut I, nested: bool) -> DynResult<Vec<(String, Value)>>
+where
+    I: Iterator<Item = Token>,
+{
+    let mut settings = Vec::new();
+
+    loop {
+        let key = match tokens.next() {
+            Some(Token::Word(key)) => key,
+            Some(Token::Close) if nested => return Ok(settings),
+            None if !nested => return Ok(settings),
+            None => bail!("unexpected end of file, missing }}"),
+            Some(token) => bail!("unexpected {:?}", token),
+        };
+
+        let value = match tokens.next() {
+            Some(Token::Equals) => match tokens.next() {
+                Some(Token::Word(value)) => Value::Scalar(value),
+                Some(Token::Open) => Value::List(parse_list(tokens)?),
+                _ => bail!("missing value of {}", key),
+            },
+            Some(Token::Word(title)) => match tokens.next() {
+                Some(Token::Open) => Value::Section(
+                    title.clone(),
+                    parse_settings(tokens, true)
+                        .with_context(|| format!("invalid section {} {}", key, title))?,
+                ),
+                _ => bail!("missing {{ after {} {}", key, title),
+            },
+            _ => bail!("missing = after {}", key),
+        };
+
+        settings.push((key, value));
+    }
+}
+
+fn parse_list<I>(tokens: &mut I) -> DynResult<Vec<String>>
+where
+    I: Iterator<Item = Token>,
+{
+    let mut values = Vec::new();
+
+    loop {
+        match tokens.next() {
+            Some(Token::Word(value)) => values.push(value),
+            Some(Token::Comma) => {}
+            Some(Token::Close) => return Ok(values),
+            _ => bail!("unterminated list"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => skip_line(&mut chars),
+            '/' if chars.peek() == Some(&'/') => skip_line(&mut chars),
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+            }
+            '=' => tokens.push(Token::Equals),
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '"' | '\'' => {
+                let mut word = String::new();
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => word.extend(chars.next()),
+                        next if next == c => break,
+                        next => word.push(next),
+                    }
+                }
+                tokens.push(Token::Word(word));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || "={},#\"'".contains(next) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    tokens
+}
+
+fn skip_line<I: Iterator<Item = char>>(chars: &mut I) {
+    for c in chars {
+        if c == '\n' {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_from_inadyn() {
+        let conversion = from_inadyn(
+            r#"
+# inadyn.conf
+period = 600
+allow-ipv6 = true
+
+provider default@gandi.net {
+    password = "secret"
+    hostname = { "home.example.com", "nas.example.com" }
+    checkip-server = ip.example.net
+    checkip-path = /
+}
+
+provider default@dyndns.org:2 {
+    username = user
+    password = pass
+    hostname = myhost.dyndns.org
+}
+"#,
+        )
+        .unwrap();
+
+        let config = conversion.config;
+        assert_eq!(Duration::from_secs(600), config.interval);
+        assert_eq!("https://ip.example.net/", config.ip_sources.unwrap().ipv4);
+
+        let zone_config = &config.zones["example.com"];
+        assert_eq!(Some("gandi-livedns"), zone_config.provider.as_deref());
+        assert_eq!("secret", zone_config.credentials["api_key"]);
+        assert_eq!(2, zone_config.records.len());
+        assert!(zone_config.records[1].aaaa.is_some());
+
+        assert_eq!(1, conversion.warnings.len());
+        assert!(conversion.warnings[0].contains("default@dyndns.org"));
+
+        assert!(from_inadyn("provider default@gandi.net {").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+#[cfg(feature = "keyring")]
+use anyhow::Context;
+
+use crate::error::{Error, Result};
+
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "ez-dyndns";
+
+#[cfg(feature = "keyring")]
+pub fn get_credential(name: &str) -> Result<Option<String>> {
+    let result = keyring::Entry::new(KEYRING_SERVICE, name).and_then(|entry| entry.get_password());
+
+    match result {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err)
+            .context(format!("failed to read credential {} from keyring", name))
+            .map_err(|err| Error::Config(err.into())),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn get_credential(_name: &str) -> Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(feature = "keyring")]
+pub fn store_credential(name: &str, secret: &str) -> Result<()> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .and_then(|entry| entry.set_password(secret))
+        .context(format!("failed to store credential {} in keyring", name))
+        .map_err(|err| Error::Config(err.into()))
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn store_credential(_name: &str, _secret: &str) -> Result<()> {
+    Err(Error::Config(
+        "this executable was built without keyring support".into(),
+    ))
+}
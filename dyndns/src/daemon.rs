@@ -0,0 +1,262 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use crate::result::DynResult;
+
+static PAUSE_TOGGLED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+#[cfg(unix)]
+pub(crate) fn daemonize(pid_file: Option<&Path>, log_file: Option<&Path>) -> DynResult<()> {
+    use std::env;
+    use std::fs::OpenOptions;
+
+    use anyhow::Context;
+    use daemonize::{Daemonize, Stdio};
+
+    let current_dir = env::current_dir().context("failed to determine working directory")?;
+
+    let mut daemon = Daemonize::new().working_directory("/");
+
+    if let Some(pid_file) = pid_file {
+        daemon = daemon.pid_file(current_dir.join(pid_file));
+    }
+
+    let (stdout, stderr) = match log_file {
+        Some(log_file) => {
+            let log_file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(current_dir.join(log_file))
+                .context("failed to open log file")?;
+
+            (
+                Stdio::from(log_file.try_clone().context("failed to open log file")?),
+                Stdio::from(log_file),
+            )
+        }
+        None => (Stdio::devnull(), Stdio::devnull()),
+    };
+
+    daemon
+        .stdout(stdout)
+        .stderr(stderr)
+        .start()
+        .context("failed to start daemon")
+}
+
+#[cfg(not(unix))]
+pub(crate) fn daemonize(_pid_file: Option<&Path>, _log_file: Option<&Path>) -> DynResult<()> {
+    Err(anyhow::Error::msg(
+        "running as a daemon is not supported on this platform",
+    ))
+}
+
+// Switches to an unprivileged user after the root-only setup, e.g. binding a low port for the API
+#[cfg(unix)]
+pub(crate) fn drop_privileges(user: Option<&str>, group: Option<&str>) -> DynResult<()> {
+    use std::ffi::CString;
+    use std::io;
+
+    use anyhow::{bail, Context};
+    use log::info;
+
+    let user = match user {
+        Some(user) => {
+            let name = CString::new(user).context("invalid user name")?;
+            let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+            if passwd.is_null() {
+                bail!("unknown user {}", user);
+            }
+
+            Some(unsafe { (name, (*passwd).pw_uid, (*passwd).pw_gid) })
+        }
+        None => None,
+    };
+
+    let gid = match group {
+        Some(group) => {
+            let name = CString::new(group).context("invalid group name")?;
+            let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+            if entry.is_null() {
+                bail!("unknown group {}", group);
+            }
+
+            Some(unsafe { (*entry).gr_gid })
+        }
+        None => user.as_ref().map(|(_, _, gid)| *gid),
+    };
+
+    if let Some(gid) = gid {
+        // Otherwise the supplementary groups of root would be kept
+        let result = match &user {
+            Some((name, _, _)) => unsafe { libc::initgroups(name.as_ptr(), gid as _) },
+            None => unsafe { libc::setgroups(1, &gid) },
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error())
+                .context("failed to set the supplementary groups");
+        }
+
+        if unsafe { libc::setgid(gid) } != 0 {
+            return Err(io::Error::last_os_error()).context("failed to switch the group");
+        }
+    }
+
+    if let Some((_, uid, _)) = &user {
+        if unsafe { libc::setuid(*uid) } != 0 {
+            return Err(io::Error::last_os_error()).context("failed to switch the user");
+        }
+    }
+
+    info!(
+        "Dropped privileges, running as uid {} and gid {}",
+        unsafe { libc::getuid() },
+        unsafe { libc::getgid() }
+    );
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn drop_privileges(_user: Option<&str>, _group: Option<&str>) -> DynResult<()> {
+    Err(anyhow::Error::msg(
+        "switching the user is not supported on this platform",
+    ))
+}
+
+// SIGUSR2 toggles pausing, the handler only writes to a pipe whose reader calls `wake` so the
+// updater picks it up right away
+#[cfg(unix)]
+pub(crate) fn handle_pause_signal<F>(wake: F) -> DynResult<()>
+where
+    F: Fn() + Send + 'static,
+{
+    use std::{io, mem, ptr, thread};
+
+    use anyhow::Context;
+
+    extern "C" fn toggle(_: libc::c_int) {
+        PAUSE_TOGGLED.store(true, Ordering::SeqCst);
+
+        let byte = 1u8;
+        unsafe {
+            libc::write(
+                SIGNAL_PIPE.load(Ordering::SeqCst),
+                ptr::addr_of!(byte).cast(),
+                1,
+            );
+        }
+    }
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error()).context("failed to create the signal pipe");
+    }
+    SIGNAL_PIPE.store(fds[1], Ordering::SeqCst);
+
+    thread::spawn(move || {
+        let mut byte = 0u8;
+        loop {
+            match unsafe { libc::read(fds[0], ptr::addr_of_mut!(byte).cast(), 1) } {
+                1 => wake(),
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                _ => return,
+            }
+        }
+    });
+
+    // unlike `signal`, the handler stays installed after the first signal on every Unix
+    let result = unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = toggle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        libc::sigaction(libc::SIGUSR2, &action, ptr::null_mut())
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error()).context("failed to handle SIGUSR2");
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn handle_pause_signal<F>(_wake: F) -> DynResult<()>
+where
+    F: Fn() + Send + 'static,
+{
+    Ok(())
+}
+
+pub(crate) fn take_pause_toggle() -> bool {
+    PAUSE_TOGGLED.swap(false, Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_drop_privileges_unknown_user() {
+        assert!(drop_privileges(Some("no-such-user-for-ez-dyndns"), None).is_err());
+        assert!(drop_privileges(None, Some("no-such-group-for-ez-dyndns")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pause_signal() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        handle_pause_signal(move || sender.send(()).unwrap()).unwrap();
+        assert!(!take_pause_toggle());
+
+        for _ in 0..2 {
+            unsafe {
+                libc::raise(libc::SIGUSR2);
+            }
+
+            receiver
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .unwrap();
+            assert!(take_pause_toggle());
+            assert!(!take_pause_toggle());
+        }
+    }
+}
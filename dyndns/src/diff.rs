@@ -0,0 +1,291 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+use crate::name::DnsName;
+use crate::provider::{Action, Record};
+
+// Records are matched by type and name, current records without a desired counterpart are deleted
+pub(crate) fn diff(desired: &[Record], current: &[Record]) -> Vec<Action> {
+    let changes = desired.iter().map(|desired| {
+        match current.iter().find(|current| same_name(current, desired)) {
+            Some(current) if same_content(current, desired) => Action::Noop(desired.clone()),
+            Some(current) => Action::Update {
+                current: current.clone(),
+                desired: desired.clone(),
+            },
+            None => Action::Create(desired.clone()),
+        }
+    });
+
+    let deletes = current
+        .iter()
+        .filter(|current| !desired.iter().any(|desired| same_name(current, desired)))
+        .map(|current| Action::Delete(current.clone()));
+
+    changes.chain(deletes).collect()
+}
+
+pub(crate) fn same_name(left: &Record, right: &Record) -> bool {
+    left.record_type() == right.record_type()
+        && DnsName::from(left.name()) == DnsName::from(right.name())
+}
+
+fn same_content(left: &Record, right: &Record) -> bool {
+    left.ttl() == right.ttl() && same_target(left, right)
+}
+
+// Same values or target, regardless of the TTL
+pub(crate) fn same_target(left: &Record, right: &Record) -> bool {
+    match (left, right) {
+        (Record::A { values: left, .. }, Record::A { values: right, .. }) => {
+            same_values(left, right)
+        }
+        (Record::AAAA { values: left, .. }, Record::AAAA { values: right, .. }) => {
+            same_values(left, right)
+        }
+        (Record::PTR { target: left, .. }, Record::PTR { target: right, .. })
+        | (Record::CNAME { target: left, .. }, Record::CNAME { target: right, .. }) => {
+            DnsName::from(left) == DnsName::from(right)
+        }
+        (
+            Record::SRV {
+                priority,
+                weight,
+                port,
+                target,
+                ..
+            },
+            Record::SRV {
+                priority: other_priority,
+                weight: other_weight,
+                port: other_port,
+                target: other_target,
+                ..
+            },
+        ) => {
+            (priority, weight, port) == (other_priority, other_weight, other_port)
+                && DnsName::from(target) == DnsName::from(other_target)
+        }
+        (
+            Record::HTTPS {
+                priority,
+                target,
+                params,
+                ..
+            },
+            Record::HTTPS {
+                priority: other_priority,
+                target: other_target,
+                params: other_params,
+                ..
+            },
+        ) => {
+            priority == other_priority
+                && params == other_params
+                && DnsName::from(target) == DnsName::from(other_target)
+        }
+        _ => false,
+    }
+}
+
+fn same_values<T: Ord + Clone>(left: &[T], right: &[T]) -> bool {
+    let mut left = left.to_vec();
+    let mut right = right.to_vec();
+
+    left.sort();
+    left.dedup();
+    right.sort();
+    right.dedup();
+
+    left == right
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn a(name: &str, values: &[&str], ttl: u32) -> Record {
+        Record::A {
+            name: name.into(),
+            values: values.iter().map(|value| value.parse().unwrap()).collect(),
+            ttl,
+        }
+    }
+
+    fn aaaa(name: &str, values: &[&str], ttl: u32) -> Record {
+        Record::AAAA {
+            name: name.into(),
+            values: values.iter().map(|value| value.parse().unwrap()).collect(),
+            ttl,
+        }
+    }
+
+    fn cname(name: &str, target: &str, ttl: u32) -> Record {
+        Record::CNAME {
+            name: name.into(),
+            target: target.into(),
+            ttl,
+        }
+    }
+
+    #[test]
+    fn test_diff_create() {
+        let desired = a("home.test.com", &["1.2.3.4"], 300);
+
+        assert_eq!(vec![Action::Create(desired.clone())], diff(&[desired], &[]));
+    }
+
+    #[test]
+    fn test_diff_noop() {
+        let desired = vec![
+            a("home.test.com", &["1.2.3.4"], 300),
+            aaaa("home.test.com", &["2001:db8::1"], 300),
+            cname("www.test.com", "home.test.com", 300),
+        ];
+        let current = vec![
+            cname("WWW.test.com.", "Home.Test.com.", 300),
+            aaaa("home.test.com.", &["2001:db8::1"], 300),
+            a("Home.test.com", &["1.2.3.4"], 300),
+        ];
+
+        assert_eq!(
+            desired
+                .iter()
+                .cloned()
+                .map(Action::Noop)
+                .collect::<Vec<_>>(),
+            diff(&desired, &current)
+        );
+    }
+
+    #[test]
+    fn test_diff_update() {
+        let desired = vec![
+            a("home.test.com", &["1.2.3.4"], 300),
+            cname("www.test.com", "home.test.com", 300),
+        ];
+        let current = vec![
+            a("home.test.com", &["4.3.2.1"], 300),
+            cname("www.test.com", "vpn.test.com", 300),
+        ];
+
+        assert_eq!(
+            vec![
+                Action::Update {
+                    current: current[0].clone(),
+                    desired: desired[0].clone()
+                },
+                Action::Update {
+                    current: current[1].clone(),
+                    desired: desired[1].clone()
+                }
+            ],
+            diff(&desired, &current)
+        );
+    }
+
+    #[test]
+    fn test_diff_delete() {
+        let desired = vec![a("home.test.com", &["1.2.3.4"], 300)];
+        let current = vec![
+            a("home.test.com", &["1.2.3.4"], 300),
+            a("old.test.com", &["1.2.3.4"], 300),
+            aaaa("home.test.com", &["2001:db8::1"], 300),
+        ];
+
+        assert_eq!(
+            vec![
+                Action::Noop(desired[0].clone()),
+                Action::Delete(current[1].clone()),
+                Action::Delete(current[2].clone())
+            ],
+            diff(&desired, &current)
+        );
+    }
+
+    #[test]
+    fn test_diff_multi_value() {
+        let desired = [aaaa("home.test.com", &["2001:db8::1", "2001:db8::2"], 300)];
+
+        let reordered = [aaaa(
+            "home.test.com",
+            &["2001:db8::2", "2001:db8::1", "2001:db8::2"],
+            300,
+        )];
+        assert_eq!(
+            vec![Action::Noop(desired[0].clone())],
+            diff(&desired, &reordered)
+        );
+
+        let subset = [aaaa("home.test.com", &["2001:db8::1"], 300)];
+        assert!(matches!(
+            diff(&desired, &subset)[..],
+            [Action::Update { .. }]
+        ));
+
+        let superset = [aaaa(
+            "home.test.com",
+            &["2001:db8::1", "2001:db8::2", "2001:db8::3"],
+            300,
+        )];
+        assert!(matches!(
+            diff(&desired, &superset)[..],
+            [Action::Update { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_diff_ttl_drift() {
+        let desired = [
+            a("home.test.com", &["1.2.3.4"], 300),
+            cname("www.test.com", "home.test.com", 300),
+        ];
+        let current = [
+            a("home.test.com", &["1.2.3.4"], 3600),
+            cname("www.test.com", "home.test.com", 60),
+        ];
+
+        assert_eq!(
+            vec![
+                Action::Update {
+                    current: current[0].clone(),
+                    desired: desired[0].clone()
+                },
+                Action::Update {
+                    current: current[1].clone(),
+                    desired: desired[1].clone()
+                }
+            ],
+            diff(&desired, &current)
+        );
+    }
+}
@@ -0,0 +1,211 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::net::IpAddr;
+use std::thread;
+use std::time::Duration;
+
+use log::{debug, warn};
+
+use crate::provider::Record;
+use crate::resolver::{query, Answer, PUBLIC_RESOLVERS};
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) fn verify(records: &[Record]) {
+    for record in records {
+        let (ipv6, addresses): (bool, Vec<IpAddr>) = match record {
+            Record::A { values, .. } => (false, values.iter().map(|&a| a.into()).collect()),
+            Record::AAAA { values, .. } => (true, values.iter().map(|&a| a.into()).collect()),
+            _ => continue,
+        };
+
+        for resolver in PUBLIC_RESOLVERS {
+            if let Some(answer) = current_answer(resolver, record, ipv6, &addresses) {
+                if let Some(problem) = problem(&answer) {
+                    warn!(
+                        "DNSSEC verification of {} {} failed at resolver {}: {}",
+                        record.name(),
+                        record.record_type(),
+                        resolver,
+                        problem
+                    );
+                }
+            }
+        }
+    }
+}
+
+// The resolver may still cache the previous addresses, whose signatures say nothing about the changed record, so the
+// lookup is repeated once the cached answer expired
+fn current_answer(
+    resolver: IpAddr,
+    record: &Record,
+    ipv6: bool,
+    addresses: &[IpAddr],
+) -> Option<Answer> {
+    let mut expired = false;
+
+    loop {
+        let answer = match query(resolver, record.name(), ipv6, true, QUERY_TIMEOUT) {
+            Ok(answer) => answer,
+            Err(err) => {
+                debug!(
+                    "Failed to query resolver {} for {}: {:#}",
+                    resolver,
+                    record.name(),
+                    err
+                );
+                return None;
+            }
+        };
+
+        if answer.server_failure || is_current(&answer, addresses) {
+            return Some(answer);
+        }
+
+        match answer.ttl {
+            Some(ttl) if !expired => {
+                debug!(
+                    "Resolver {} returns the previous addresses of {}, retrying in {} seconds",
+                    resolver,
+                    record.name(),
+                    ttl
+                );
+                thread::sleep(Duration::from_secs(ttl.min(record.ttl()) as u64 + 1));
+                expired = true;
+            }
+            _ => {
+                debug!(
+                    "Not verifying {} at resolver {}: It doesn't return the current addresses",
+                    record.name(),
+                    resolver
+                );
+                return None;
+            }
+        }
+    }
+}
+
+fn is_current(answer: &Answer, addresses: &[IpAddr]) -> bool {
+    let mut returned = answer.addresses.clone();
+    returned.sort();
+    returned.dedup();
+
+    let mut expected = addresses.to_vec();
+    expected.sort();
+    expected.dedup();
+
+    returned == expected
+}
+
+fn problem(answer: &Answer) -> Option<&'static str> {
+    if answer.server_failure {
+        // validating resolvers answer SERVFAIL for bogus signatures
+        Some("the signatures are missing, expired or invalid")
+    } else if answer.nxdomain || answer.addresses.is_empty() {
+        // a new record may not be visible to the resolver yet
+        None
+    } else if !answer.signed {
+        Some("no RRSIG returned")
+    } else if !answer.authenticated {
+        Some("the answer isn't authenticated")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn test_is_current() {
+        let address = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 10));
+        let previous = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 20));
+
+        let answer = Answer {
+            addresses: vec![address],
+            ..Default::default()
+        };
+
+        assert!(is_current(&answer, &[address]));
+        assert!(!is_current(&answer, &[previous]));
+        assert!(!is_current(&answer, &[address, previous]));
+    }
+
+    #[test]
+    fn test_problem() {
+        let addresses = vec![IpAddr::V4(Ipv4Addr::new(203, 0, 113, 10))];
+
+        assert_eq!(
+            None,
+            problem(&Answer {
+                addresses: addresses.clone(),
+                authenticated: true,
+                signed: true,
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(
+            None,
+            problem(&Answer {
+                nxdomain: true,
+                ..Default::default()
+            })
+        );
+
+        assert!(problem(&Answer {
+            server_failure: true,
+            ..Default::default()
+        })
+        .is_some());
+
+        assert!(problem(&Answer {
+            addresses: addresses.clone(),
+            authenticated: true,
+            ..Default::default()
+        })
+        .is_some());
+
+        assert!(problem(&Answer {
+            addresses,
+            signed: true,
+            ..Default::default()
+        })
+        .is_some());
+    }
+}
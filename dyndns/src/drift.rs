@@ -0,0 +1,161 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+use crate::provider::join_values;
+use crate::resolver::{query, PUBLIC_RESOLVERS};
+use crate::ttl::Ttl;
+
+const DEFAULT_CHECK_INTERVAL: u64 = 3600;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DriftDetectionConfig {
+    #[serde(default = "default_resolvers")]
+    pub resolvers: Vec<IpAddr>,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_check_interval")]
+    pub interval: Duration,
+}
+
+fn default_resolvers() -> Vec<IpAddr> {
+    PUBLIC_RESOLVERS.to_vec()
+}
+
+fn default_check_interval() -> Duration {
+    Duration::from_secs(DEFAULT_CHECK_INTERVAL)
+}
+
+pub(crate) struct PublishedRecord {
+    pub name: String,
+    pub ipv6: bool,
+    pub addresses: Vec<IpAddr>,
+    pub ttl: Ttl,
+}
+
+type MismatchKey = (String, bool, IpAddr);
+
+#[derive(Default)]
+pub(crate) struct DriftDetector {
+    last_check: Cell<Option<Instant>>,
+    mismatches: RefCell<HashMap<MismatchKey, (Vec<IpAddr>, Instant)>>,
+}
+
+impl DriftDetector {
+    pub(crate) fn check(&self, config: &DriftDetectionConfig, published: &[PublishedRecord]) {
+        if let Some(last_check) = self.last_check.get() {
+            if last_check.elapsed() < config.interval {
+                return;
+            }
+        }
+
+        self.last_check.set(Some(Instant::now()));
+
+        let mut mismatches = self.mismatches.borrow_mut();
+        mismatches.retain(|(name, ipv6, _), _| {
+            published
+                .iter()
+                .any(|record| &record.name == name && record.ipv6 == *ipv6)
+        });
+
+        for record in published {
+            let mut addresses = record.addresses.clone();
+            addresses.sort();
+
+            for resolver in &config.resolvers {
+                let answer = match query(*resolver, &record.name, record.ipv6, false, QUERY_TIMEOUT)
+                {
+                    Ok(answer) => answer,
+                    Err(err) => {
+                        debug!(
+                            "Failed to query resolver {} for {}: {:#}",
+                            resolver, record.name, err
+                        );
+                        continue;
+                    }
+                };
+
+                let key = (record.name.clone(), record.ipv6, *resolver);
+
+                let mut answered = answer.addresses;
+                answered.sort();
+
+                if answered == addresses {
+                    mismatches.remove(&key);
+                    continue;
+                }
+
+                let (_, since) = mismatches
+                    .entry(key)
+                    .and_modify(|(published, since)| {
+                        if published != &addresses {
+                            *published = addresses.clone();
+                            *since = Instant::now();
+                        }
+                    })
+                    .or_insert_with(|| (addresses.clone(), Instant::now()));
+
+                let answered = if answered.is_empty() {
+                    "no addresses".to_string()
+                } else {
+                    join_values(&answered)
+                };
+
+                // resolvers may serve the previous answer from their cache until the TTL expired
+                if since.elapsed() > Duration::from_secs(record.ttl.get().into()) {
+                    warn!(
+                        "Resolver {} returns {} for {} instead of {} for longer than its TTL",
+                        resolver,
+                        answered,
+                        record.name,
+                        join_values(&addresses)
+                    );
+                } else {
+                    debug!(
+                        "Resolver {} still returns {} for {}",
+                        resolver, answered, record.name
+                    );
+                }
+            }
+        }
+    }
+}
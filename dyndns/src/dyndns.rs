@@ -31,223 +31,2165 @@
  *
  */
 
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::process::exit;
 use std::rc::Rc;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use anyhow::Context;
-use chrono::Local;
+use anyhow::{Context, Error};
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
 
-use crate::config::Config;
-use crate::ip::{get_ip, Ip};
-use crate::job::start_job;
-use crate::provider::{DnsProvider, DnsZones, Record, Zone};
+use crate::api::{serve, Control};
+use crate::approval::Approver;
+use crate::config::{is_reverse_zone, Config, DomainRecord, ZoneConfig, MIN_INTERVAL};
+use crate::daemon::{handle_pause_signal, take_pause_toggle};
+use crate::diff::{diff, same_name, same_target};
+use crate::dnssec;
+use crate::drift::{DriftDetector, PublishedRecord};
+use crate::error::{describe, from_anyhow, ProviderErrorKind};
+use crate::failover::FailoverConfig;
+use crate::filter::RecordFilter;
+use crate::flap::{detect_flapping, hold_back, RunReport};
+use crate::guard::check_mail_exchanges;
+use crate::healthcheck::ping;
+use crate::host::{host, set_host};
+use crate::http;
+use crate::ip::{
+    get_ip, reverse_name, ExternalIp, HttpIpSource, InterfaceIpSource, IpSource, IpVersion,
+    StaticIpSource,
+};
+use crate::job::{start_job, Wakeup};
+use crate::logging::timestamp;
+use crate::name::DnsName;
+use crate::provider::{join_values, Action, DnsProvider, DnsZones, Record, Zone, ZoneResults};
 use crate::result::DynResult;
+use crate::state::{LoggedChange, RunLog, State, StateKey};
+use crate::summary::{Change, Outcome, Proposal, Summary};
+use crate::telemetry::{self, span};
+use crate::template::{render_ipv4, render_ipv6};
+use crate::ttl::Ttl;
+use crate::upnp::{check_cgnat, refresh_port_mappings};
+
+type Addresses = (Vec<Ipv4Addr>, Vec<Ipv6Addr>);
+
+const MAX_RUN_LOGS: usize = 10;
+
+const RUN_ID_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+pub struct Updater<'a, P: DnsProvider> {
+    config: &'a Config,
+    provider: &'a P,
+    ip_source: Box<dyn IpSource + 'a>,
+    dry_run: bool,
+    interactive: bool,
+    force: bool,
+    filter: RecordFilter,
+    only_if_stale: bool,
+    zone_failures: RefCell<HashMap<Zone, Instant>>,
+    last_listing: RefCell<Option<(Addresses, Instant)>>,
+    listing_cache: RefCell<Option<(DnsZones, Instant)>>,
+    drift_detector: DriftDetector,
+    snapshots: RefCell<BTreeMap<String, String>>,
+    state: RefCell<State>,
+    flapping: Cell<bool>,
+    control: Option<Arc<Control>>,
+    serving: Cell<bool>,
+    paused: Cell<bool>,
+}
+
+impl<'a, P: DnsProvider> Updater<'a, P> {
+    pub fn new(config: &'a Config, provider: &'a P) -> Self {
+        http::configure(config.proxy.as_deref(), config.user_agent.as_deref());
+        set_host(config.host.clone());
+
+        let state = match &config.state_file {
+            Some(state_file) => State::load(state_file, &state_key(config)).unwrap_or_else(|err| {
+                warn!("{:?}", Error::from(err));
+                State::default()
+            }),
+            None => State::default(),
+        };
+
+        if let (Some(state_file), Some(state_host)) = (&config.state_file, &state.host) {
+            if state_host != &host() {
+                warn!(
+                    "State file {} was written by host {}, machines sharing it mix up their runs",
+                    state_file.display(),
+                    state_host
+                );
+            }
+        }
+
+        Updater {
+            config,
+            provider,
+            ip_source: ip_source(config),
+            dry_run: false,
+            interactive: false,
+            force: false,
+            filter: RecordFilter::default(),
+            only_if_stale: false,
+            zone_failures: RefCell::new(HashMap::new()),
+            last_listing: RefCell::new(None),
+            listing_cache: RefCell::new(None),
+            drift_detector: DriftDetector::default(),
+            snapshots: RefCell::new(BTreeMap::new()),
+            state: RefCell::new(state),
+            flapping: Cell::new(false),
+            control: config
+                .api
+                .as_ref()
+                .map(|_| Arc::new(Control::new(config, provider.ttl_range()))),
+            serving: Cell::new(false),
+            paused: Cell::new(false),
+        }
+    }
+
+    pub fn with_ip_source<S: IpSource + 'a>(mut self, ip_source: S) -> Self {
+        self.ip_source = Box::new(ip_source);
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: RecordFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_run_on_start_only_if_stale(mut self, only_if_stale: bool) -> Self {
+        self.only_if_stale = only_if_stale;
+        self
+    }
+
+    pub fn run(&self) {
+        let failure_count = Rc::new(Mutex::new(0));
+
+        let initial_delay = if self.only_if_stale {
+            self.remaining_interval()
+        } else {
+            None
+        };
+
+        if let Err(err) = self.serve_api() {
+            error!("{:?}", err.context("failed to start the HTTP API"));
+            exit(1);
+        }
+
+        let wakeup = Arc::new(Wakeup::default());
+        let handled = match &self.control {
+            Some(control) => {
+                let control = control.clone();
+                handle_pause_signal(move || control.wake())
+            }
+            None => {
+                let wakeup = wakeup.clone();
+                handle_pause_signal(move || wakeup.wake())
+            }
+        };
+        if let Err(err) = handled {
+            warn!("{:?}", err.context("pausing via SIGUSR2 is unavailable"));
+        }
+
+        let job = || {
+            if take_pause_toggle() {
+                let paused = !self.is_paused();
+                self.set_paused(paused);
+                info!("{} via SIGUSR2", if paused { "Paused" } else { "Resumed" });
+            }
+
+            if self.is_paused() {
+                info!("Paused: Not updating DNS records");
+                return;
+            }
+
+            let mut failure_count = failure_count.lock().unwrap();
+
+            if let Err(err) = self.run_once() {
+                error!("{:?}", Error::from(err));
+                *failure_count += 1;
+            } else {
+                *failure_count = 0;
+            }
+
+            if *failure_count >= 3 {
+                error!("Too many errors in sequence: Aborting!");
+                telemetry::shutdown();
+                exit(1);
+            }
+        };
+
+        match &self.control {
+            Some(control) => start_job(self.config, initial_delay, control.as_ref(), job),
+            None => start_job(self.config, initial_delay, wakeup.as_ref(), job),
+        }
+    }
+
+    // The API holds the state if it's configured, so its status and the signal agree
+    fn is_paused(&self) -> bool {
+        match &self.control {
+            Some(control) => control.is_paused(),
+            None => self.paused.get(),
+        }
+    }
+
+    fn set_paused(&self, paused: bool) {
+        match &self.control {
+            Some(control) => {
+                control.set_paused(paused);
+            }
+            None => self.paused.set(paused),
+        }
+    }
+
+    pub fn run_once(&self) -> crate::Result<()> {
+        // changes can only be approved through the HTTP API
+        if self.config.approval.is_some() && !self.dry_run {
+            self.serve_api()
+                .context("failed to start the HTTP API")
+                .map_err(from_anyhow)?;
+        }
+
+        if self.dry_run {
+            info!("Dry run: DNS records won't be modified");
+            return self
+                .update_records(&DryRun(self.provider))
+                .map_err(from_anyhow);
+        }
+
+        self.snapshots.take();
+        let provider = Snapshotting::new(self.provider, self.config, &self.snapshots);
+        let provider = Cached {
+            provider: &provider,
+            ttl: self.config.listing_cache_ttl,
+            cache: &self.listing_cache,
+        };
+
+        let result = span("run", &[("provider", self.provider.name().into())], || {
+            self.update_records(&provider)
+        })
+        .map_err(from_anyhow);
+
+        if let Some(upnp) = &self.config.upnp {
+            refresh_port_mappings(upnp);
+        }
+
+        if let Some(healthcheck) = &self.config.healthcheck {
+            ping(healthcheck, result.is_ok());
+        }
+
+        if let Some(control) = &self.control {
+            control.finished(result.as_ref().err().map(|err| describe(err)));
+        }
+
+        if let Some(state_file) = &self.config.state_file {
+            let mut state = self.state.borrow_mut();
+
+            if result.is_ok() {
+                state.last_success = Some(Utc::now());
+            }
+
+            state.host = Some(host());
+
+            if let Err(err) = state.save(state_file, &state_key(self.config)) {
+                warn!("{:?}", Error::from(err));
+            }
+        }
+
+        result
+    }
+
+    pub(crate) fn serve_api(&self) -> DynResult<()> {
+        if let (Some(api), Some(control), false) =
+            (&self.config.api, &self.control, self.serving.get())
+        {
+            serve(api, control.clone())?;
+            self.serving.set(true);
+        }
+
+        Ok(())
+    }
+
+    fn ipv6_addresses(&self, current_ip: &ExternalIp) -> DynResult<Vec<Ipv6Addr>> {
+        let all_ipv6 = self
+            .config
+            .interface
+            .as_ref()
+            .is_some_and(|interface| interface.all_ipv6);
+
+        if current_ip.ipv6.is_none() || !all_ipv6 || self.config.override_ip.is_some() {
+            return Ok(current_ip.ipv6.into_iter().collect());
+        }
+
+        let addresses = self
+            .ip_source
+            .all_ipv6()
+            .context("failed to retrieve IPv6 addresses")?;
+
+        Ok(addresses
+            .into_iter()
+            .filter(|address| self.config.ip_filter.accepts(&IpAddr::V6(*address)))
+            .collect())
+    }
+
+    fn remaining_interval(&self) -> Option<Duration> {
+        let state = self.state.borrow();
+        let remaining = state.remaining_interval(self.config.interval, Utc::now())?;
+
+        info!(
+            "Last successful update at {}, skipping the run on start",
+            timestamp(state.last_success?)
+        );
+
+        Some(remaining)
+    }
+
+    // Below the minimum interval the records are only listed if the address changed or the listing is outdated
+    fn is_listing_fresh(&self, addresses: &Addresses) -> bool {
+        let has_failover = self
+            .config
+            .zones
+            .values()
+            .flat_map(|zone_config| &zone_config.records)
+            .any(|record| record.failover.is_some() || record.bridged);
+
+        if self.config.interval >= MIN_INTERVAL || has_failover {
+            return false;
+        }
+
+        match &*self.last_listing.borrow() {
+            Some((listed_addresses, listed_at)) => {
+                listed_addresses == addresses && listed_at.elapsed() < MIN_INTERVAL
+            }
+            None => false,
+        }
+    }
+
+    fn is_force_due(&self, now: DateTime<Utc>) -> bool {
+        if self.force
+            || self
+                .control
+                .as_ref()
+                .is_some_and(|control| control.take_force())
+        {
+            return true;
+        }
+
+        match self.config.force_update_interval {
+            Some(force_update_interval) => {
+                self.state.borrow().is_force_due(force_update_interval, now)
+            }
+            None => false,
+        }
+    }
+
+    fn report_run(&self, current_ip: &ExternalIp, now: DateTime<Utc>) -> bool {
+        let flap_detection = self.config.flap_detection();
+        let mut state = self.state.borrow_mut();
+
+        if let Some(previous) = state.reports.last() {
+            if previous.ip != *current_ip {
+                info!(
+                    "External IP address changed since {}: {} -> {}",
+                    timestamp(previous.time),
+                    previous.ip,
+                    current_ip
+                );
+            }
+        }
+
+        let mut report = RunReport {
+            time: now,
+            ip: *current_ip,
+            held_back: false,
+        };
+
+        let mut reports = state.reports.clone();
+        reports.push(report.clone());
+
+        let flapping = detect_flapping(&reports, flap_detection.threshold);
+
+        if flapping.is_empty() {
+            if self.flapping.replace(false) {
+                info!("External IP address is stable again");
+            }
+        } else {
+            if !self.flapping.replace(true) {
+                for flapping in &flapping {
+                    error!(
+                        "!!! External IP address is flapping between {} and {}",
+                        flapping.0, flapping.1
+                    );
+                }
+            }
+
+            report.held_back = flap_detection
+                .cooldown
+                .is_some_and(|cooldown| hold_back(&state.reports, current_ip, cooldown, now));
+        }
+
+        let held_back = report.held_back;
+
+        state.reports.push(report);
+
+        let excess = state.reports.len().saturating_sub(flap_detection.history);
+        state.reports.drain(..excess);
+
+        held_back
+    }
+
+    fn detect_ip(&self, ipv4: bool, ipv6: bool) -> DynResult<ExternalIp> {
+        let config = self.config;
+
+        let mut current_ip = span("detect_ip", &[], || {
+            get_ip(self.ip_source.as_ref(), ipv4, ipv6)
+        })?;
+
+        if config.override_ip.is_none() {
+            current_ip = config.ip_filter.apply(current_ip)?;
+
+            if let (Some(upnp), Some(ipv4)) = (&config.upnp, &current_ip.ipv4) {
+                if upnp.check_cgnat {
+                    check_cgnat(upnp, ipv4);
+                }
+            }
+        }
+
+        info!("External IP address: {}", current_ip);
+
+        Ok(current_ip)
+    }
+
+    fn update_records<Q: DnsProvider>(&self, provider: &Q) -> DynResult<()> {
+        let config = self.config;
+
+        let (ipv4, ipv6) = required_ip_versions(config, provider);
+        let (ipv4, ipv6) = (
+            ipv4 && config.ip_version.ipv4(),
+            ipv6 && config.ip_version.ipv6(),
+        );
+        let current_ip = if ipv4 || ipv6 {
+            let current_ip = self.detect_ip(ipv4, ipv6)?;
+
+            if self.report_run(&current_ip, Utc::now()) {
+                info!("Holding back the changed IP address while it is flapping");
+                return Ok(());
+            }
+
+            current_ip
+        } else if config.ip_version.is_default() {
+            debug!("Not detecting the external IP address: Only failover and bridged records configured");
+            ExternalIp::default()
+        } else {
+            warn!(
+                "Not detecting the external IP address: No {} records configured",
+                if config.ip_version.ipv4() {
+                    "A"
+                } else {
+                    "AAAA"
+                }
+            );
+            ExternalIp::default()
+        };
+
+        let ipv4_addresses = current_ip.ipv4.into_iter().collect::<Vec<_>>();
+        let ipv6_addresses = self.ipv6_addresses(&current_ip)?;
+
+        if ipv6_addresses.len() > 1 {
+            info!(
+                "Publishing IPv6 addresses: {}",
+                join_values(&ipv6_addresses)
+            );
+        }
+
+        let now = Utc::now();
+        let force = self.is_force_due(now);
+        if force {
+            info!("Refreshing unchanged A and AAAA records");
+        }
+
+        let addresses = (ipv4_addresses.clone(), ipv6_addresses.clone());
+        if !force && self.is_listing_fresh(&addresses) {
+            debug!("Not fetching current DNS records: External IP address unchanged");
+            return Ok(());
+        }
+
+        info!("Fetching current DNS records");
+
+        let current_results = span("provider.current", &[], || provider.current(config))
+            .context("failed to retrieve current DNS data")?;
+
+        let mut current_zones = DnsZones::new();
+        let mut failed_zones = Vec::new();
+        for (zone, records) in current_results {
+            match records {
+                Ok(records) => {
+                    current_zones.insert(zone, records);
+                }
+                Err(err) => failed_zones.push((zone, Error::from(err))),
+            }
+        }
+
+        self.check_conflicts(&current_zones);
+
+        let skipped_zones = self.sticky_zones(&failed_zones);
+        let summary = match (&config.approval, &self.control) {
+            _ if self.interactive => Summary::interactive(),
+            (Some(approval), Some(control)) if !self.dry_run => {
+                Summary::approval(Approver::new(approval, control.approvals()))
+            }
+            _ => Summary::default(),
+        };
+
+        info!("Updating DNS records at {}", timestamp(now));
+
+        let published = RefCell::new(Vec::new());
+
+        let ptr_records = self.ptr_records(provider, &ipv4_addresses, &ipv6_addresses);
+        let ptr_names = ptr_records
+            .iter()
+            .map(|(address, _, _)| reverse_name(address))
+            .collect::<Vec<_>>();
+
+        let mut unpruned_zones = Vec::new();
+
+        config
+            .zones
+            .iter()
+            .filter(|(_, zone_config)| zone_config.is_handled_by(provider.name()))
+            .for_each(|(zone, zone_config)| {
+                debug!("---");
+                debug!("Zone: {}", zone);
+
+                let zone = current_zones.find_or_create(zone, zone_config);
+
+                if skipped_zones.contains(&zone) {
+                    warn!(
+                        "Not updating zone {}: Current records unavailable",
+                        zone.name
+                    );
+                    return;
+                }
+
+                zone_config
+                    .records_for(provider.name())
+                    .filter(|record| self.filter.matches(&zone.name, record))
+                    .filter(|record| !dependency_failed(record, &zone, &summary))
+                    .for_each(|record| {
+                        let record = &*self.with_ttl_override(record);
+
+                        let addresses = if record.bridged {
+                            match self.pushed_addresses(record) {
+                                Some((ipv4_pushed, ipv6_pushed)) => {
+                                    record_addresses(record, &ipv4_pushed, &ipv6_pushed)
+                                }
+                                None => {
+                                    debug!(
+                                        "Not updating bridged record {}: No address received yet",
+                                        record.names().collect::<Vec<_>>().join(", ")
+                                    );
+                                    return;
+                                }
+                            }
+                        } else {
+                            record_addresses(record, &ipv4_addresses, &ipv6_addresses)
+                        };
+                        let (ipv4_addresses, ipv6_addresses) =
+                            restrict_addresses(addresses, config.ip_version);
+
+                        published.borrow_mut().extend(published_records(
+                            record,
+                            &ipv4_addresses,
+                            &ipv6_addresses,
+                        ));
+
+                        let ttl = record.ttl().get();
+
+                        let a_name = record.a.as_ref().filter(|_| !ipv4_addresses.is_empty());
+                        if let Some(name) = a_name {
+                            let desired = Record::A {
+                                name: name.clone(),
+                                values: ipv4_addresses,
+                                ttl,
+                            };
+                            update_record(provider, &zone, desired, &current_zones, &summary, force)
+                        }
+
+                        let aaaa_name = record.aaaa.as_ref().filter(|_| !ipv6_addresses.is_empty());
+                        if let Some(name) = aaaa_name {
+                            let desired = Record::AAAA {
+                                name: name.clone(),
+                                values: ipv6_addresses,
+                                ttl,
+                            };
+                            update_record(provider, &zone, desired, &current_zones, &summary, force)
+                        }
+
+                        if let Some(cname) = &record.cname {
+                            let desired = Record::CNAME {
+                                name: cname.name.clone(),
+                                target: cname.target.clone(),
+                                ttl,
+                            };
+                            update_record(provider, &zone, desired, &current_zones, &summary, false)
+                        }
+
+                        for desired in record.service_records() {
+                            update_record(provider, &zone, desired, &current_zones, &summary, false)
+                        }
+                    });
+
+                if !zone_config.is_owned_by(provider.name()) {
+                    return;
+                }
+
+                if !self.filter.is_empty() {
+                    debug!("Not pruning or applying options: Records are filtered");
+                    return;
+                }
+
+                if zone_config.prune {
+                    if let Err(err) = check_mail_exchanges(&zone.name, zone_config) {
+                        error!("Refusing to prune zone {}: {:#}", zone.name, err);
+                        unpruned_zones.push(zone.name.clone());
+                    } else {
+                        prune_records(
+                            provider,
+                            &zone,
+                            zone_config,
+                            config.ip_version,
+                            &ptr_names,
+                            &current_zones,
+                            &summary,
+                        );
+                    }
+                }
+
+                if !zone_config.options.is_empty() {
+                    apply_options(provider, &zone, zone_config, &summary);
+                }
+            });
+
+        self.update_ptr_records(
+            provider,
+            ptr_records,
+            &current_zones,
+            &skipped_zones,
+            &summary,
+        );
+
+        execute_approved(provider, config, &summary);
+
+        summary.log();
+        info!("Done updating DNS records at {}", timestamp(Utc::now()));
+
+        if let (Some(control), false) = (&self.control, self.dry_run) {
+            control.published(&current_ip, &published.borrow());
+        }
+
+        if let (Some(drift_detection), false) = (&config.drift_detection, self.dry_run) {
+            self.drift_detector
+                .check(drift_detection, &published.into_inner());
+        }
+
+        if !self.dry_run {
+            self.remember_written(&summary);
+            verify_signatures(config, &summary);
+
+            if config.state_file.is_some() {
+                self.log_run(&summary, &current_zones, now);
+            }
+        }
+
+        if failed_zones.is_empty() && unpruned_zones.is_empty() && !summary.has_failures() {
+            if force && !self.dry_run {
+                self.state.borrow_mut().last_forced_update = Some(now);
+            }
+
+            *self.last_listing.borrow_mut() = Some((addresses, Instant::now()));
+        }
+
+        if !failed_zones.is_empty() {
+            Err(Error::msg(format!(
+                "failed to retrieve current DNS data of zones: {}",
+                failed_zones
+                    .iter()
+                    .map(|(zone, _)| zone.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )))
+        } else if !unpruned_zones.is_empty() {
+            Err(Error::msg(format!(
+                "refused to prune zones with unprotected mail exchanges: {}",
+                unpruned_zones.join(", ")
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn pushed_addresses(&self, record: &DomainRecord) -> Option<Addresses> {
+        let pushed = self.control.as_ref()?.pushed(record)?;
+
+        Some((
+            pushed.ipv4.into_iter().collect(),
+            pushed.ipv6.into_iter().collect(),
+        ))
+    }
+
+    fn with_ttl_override<'r>(&self, record: &'r DomainRecord) -> Cow<'r, DomainRecord> {
+        let ttl = self
+            .control
+            .as_ref()
+            .and_then(|control| record.names().find_map(|name| control.ttl(name)));
+
+        match ttl {
+            Some(ttl) => Cow::Owned(DomainRecord {
+                ttl: Some(ttl),
+                ..record.clone()
+            }),
+            None => Cow::Borrowed(record),
+        }
+    }
+
+    // Records changed since this host wrote them are most likely updated by another host as well
+    fn check_conflicts(&self, current_zones: &DnsZones) {
+        self.state.borrow_mut().written.retain(|written| {
+            let current = current_zones
+                .values()
+                .flatten()
+                .find(|current| same_name(current, written));
+
+            match current {
+                Some(current) if !same_target(current, written) => {
+                    warn!(
+                        "Write conflict: {} {} was changed to {} after host {} set it to {}, is another host updating it?",
+                        written.record_type(),
+                        written.name(),
+                        current.values().join(", "),
+                        host(),
+                        written.values().join(", ")
+                    );
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn remember_written(&self, summary: &Summary) {
+        let mut state = self.state.borrow_mut();
+
+        for (_, change, record) in summary.applied() {
+            state.written.retain(|written| !same_name(written, &record));
+
+            if !matches!(change, Change::Delete) {
+                state.written.push(record);
+            }
+        }
+    }
+
+    fn log_run(&self, summary: &Summary, current_zones: &DnsZones, now: DateTime<Utc>) {
+        let snapshots = self.snapshots.take();
+
+        let changes = summary
+            .applied()
+            .into_iter()
+            .filter(|(_, change, _)| !matches!(change, Change::Refresh))
+            .map(|(zone, change, record)| logged_change(zone, change, record, current_zones))
+            .collect::<Vec<_>>();
+
+        if changes.is_empty() && snapshots.is_empty() {
+            return;
+        }
+
+        let mut state = self.state.borrow_mut();
+        state.sequence += 1;
+        let sequence = state.sequence;
+
+        // After the clock was set back the time alone may repeat
+        let mut id = now.format(RUN_ID_FORMAT).to_string();
+        if state.runs.iter().any(|run| run.id == id) {
+            id = format!("{}-{}", id, sequence);
+        }
+
+        info!(
+            "Recorded the changes as run {}, rollback {} reverts them",
+            id, id
+        );
+
+        state.runs.push(RunLog {
+            id,
+            sequence,
+            time: now,
+            host: Some(host()),
+            changes,
+            snapshots,
+        });
+
+        let excess = state.runs.len().saturating_sub(MAX_RUN_LOGS);
+        state.runs.drain(..excess);
+    }
+
+    // The PTR records of the records with `ptr`, as (address, target, TTL)
+    fn ptr_records<Q: DnsProvider>(
+        &self,
+        provider: &Q,
+        ipv4_addresses: &[Ipv4Addr],
+        ipv6_addresses: &[Ipv6Addr],
+    ) -> Vec<(IpAddr, String, Ttl)> {
+        self.config
+            .zones
+            .iter()
+            .filter(|(_, zone_config)| zone_config.is_handled_by(provider.name()))
+            .flat_map(|(zone, zone_config)| {
+                zone_config
+                    .records_for(provider.name())
+                    .filter(move |record| self.filter.matches(zone, record))
+            })
+            .filter(|record| record.ptr && record.failover.is_none())
+            .flat_map(|record| {
+                let ttl = record.ttl();
+                let (ipv4_addresses, ipv6_addresses) =
+                    templated_addresses(record, ipv4_addresses, ipv6_addresses);
+
+                let ipv4_targets = record.a.iter().flat_map(|target| {
+                    ipv4_addresses
+                        .iter()
+                        .map(move |address| (IpAddr::V4(*address), target.clone(), ttl))
+                });
+
+                let ipv6_targets = record.aaaa.iter().flat_map(|target| {
+                    ipv6_addresses
+                        .iter()
+                        .map(move |address| (IpAddr::V6(*address), target.clone(), ttl))
+                });
+
+                ipv4_targets.chain(ipv6_targets).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    fn update_ptr_records<Q: DnsProvider>(
+        &self,
+        provider: &Q,
+        ptr_records: Vec<(IpAddr, String, Ttl)>,
+        current_zones: &DnsZones,
+        skipped_zones: &HashSet<Zone>,
+        summary: &Summary,
+    ) {
+        if ptr_records.is_empty() {
+            return;
+        }
+
+        let handled_zones = self
+            .config
+            .zones
+            .iter()
+            .filter(|(_, zone_config)| zone_config.is_handled_by(provider.name()))
+            .collect::<Vec<_>>();
+
+        debug!("---");
+        debug!("Reverse zones");
+
+        for (address, target, ttl) in ptr_records {
+            let ptr_name = reverse_name(&address);
+
+            let reverse_zone = handled_zones
+                .iter()
+                .filter(|(zone, _)| {
+                    is_reverse_zone(zone)
+                        && DnsName::from(&ptr_name).is_in_zone(&DnsName::from(*zone))
+                })
+                .max_by_key(|(zone, _)| zone.len());
+
+            let (zone, zone_config) = if let Some(reverse_zone) = reverse_zone {
+                reverse_zone
+            } else {
+                warn!(
+                    "No reverse zone configured for {}, skipping PTR record",
+                    ptr_name
+                );
+                continue;
+            };
+
+            let zone = current_zones.find_or_create(zone, zone_config);
+
+            if skipped_zones.contains(&zone) {
+                warn!(
+                    "Not updating PTR record {}: Current records of zone {} unavailable",
+                    ptr_name, zone.name
+                );
+                continue;
+            }
+
+            let desired = Record::PTR {
+                name: ptr_name,
+                target,
+                ttl: ttl.get(),
+            };
+
+            update_record(provider, &zone, desired, current_zones, summary, false)
+        }
+    }
+
+    fn sticky_zones(&self, failed_zones: &[(Zone, Error)]) -> HashSet<Zone> {
+        let mut zone_failures = self.zone_failures.borrow_mut();
+        zone_failures.retain(|zone, _| failed_zones.iter().any(|(failed, _)| failed == zone));
+
+        let grace_period = self.config.sticky_grace_period();
+
+        failed_zones
+            .iter()
+            .filter(|(zone, err)| {
+                error!(
+                    "Failed to retrieve current records of zone {}: {:?}",
+                    zone.name, err
+                );
+
+                let failing_since = *zone_failures
+                    .entry(zone.clone())
+                    .or_insert_with(Instant::now);
+
+                if failing_since.elapsed() < grace_period {
+                    true
+                } else {
+                    warn!(
+                        "Records of zone {} unavailable for more than {} seconds, treating them as missing",
+                        zone.name,
+                        grace_period.as_secs()
+                    );
+                    false
+                }
+            })
+            .map(|(zone, _)| zone.clone())
+            .collect()
+    }
+}
 
 pub fn run<P: DnsProvider>(config: &Config, provider: &P) {
-    let failure_count = Rc::new(Mutex::new(0));
+    Updater::new(config, provider).run()
+}
+
+pub fn run_once<P: DnsProvider>(config: &Config, provider: &P) -> crate::Result<()> {
+    Updater::new(config, provider).run_once()
+}
+
+pub(crate) fn ip_source(config: &Config) -> Box<dyn IpSource> {
+    match (config.override_ip, &config.interface) {
+        (Some(override_ip), _) => Box::new(StaticIpSource(override_ip)),
+        (None, Some(interface)) => Box::new(InterfaceIpSource::new(&interface.name)),
+        (None, None) => Box::new(HttpIpSource::from_config(
+            &config.ip_sources.clone().unwrap_or_default(),
+            config.user_agent.as_deref(),
+        )),
+    }
+}
+
+pub(crate) fn state_key(config: &Config) -> StateKey {
+    StateKey::new(config.state_key.as_deref())
+}
+
+fn required_ip_versions<P: DnsProvider>(config: &Config, provider: &P) -> (bool, bool) {
+    config
+        .zones
+        .values()
+        .flat_map(|zone_config| zone_config.records_for(provider.name()))
+        .filter(|record| record.failover.is_none() && !record.bridged)
+        .fold((false, false), |(ipv4, ipv6), record| {
+            (ipv4 || record.a.is_some(), ipv6 || record.aaaa.is_some())
+        })
+}
+
+// the other address family is left alone entirely, just as if it wasn't configured
+fn restrict_addresses((ipv4, ipv6): Addresses, ip_version: IpVersion) -> Addresses {
+    (
+        if ip_version.ipv4() { ipv4 } else { Vec::new() },
+        if ip_version.ipv6() { ipv6 } else { Vec::new() },
+    )
+}
+
+fn record_addresses(
+    record: &DomainRecord,
+    ipv4_addresses: &[Ipv4Addr],
+    ipv6_addresses: &[Ipv6Addr],
+) -> Addresses {
+    let (mut ipv4_addresses, mut ipv6_addresses) = match &record.failover {
+        Some(failover) => {
+            let (ipv4_addresses, ipv6_addresses) = healthy_addresses(record, failover);
+            templated_addresses(record, &ipv4_addresses, &ipv6_addresses)
+        }
+        None => templated_addresses(record, ipv4_addresses, ipv6_addresses),
+    };
+
+    // additional addresses are only merged in, an address family without a current address is left alone
+    for address in &record.additional_addresses {
+        match address {
+            IpAddr::V4(address)
+                if !ipv4_addresses.is_empty() && !ipv4_addresses.contains(address) =>
+            {
+                ipv4_addresses.push(*address)
+            }
+            IpAddr::V6(address)
+                if !ipv6_addresses.is_empty() && !ipv6_addresses.contains(address) =>
+            {
+                ipv6_addresses.push(*address)
+            }
+            _ => {}
+        }
+    }
+
+    (ipv4_addresses, ipv6_addresses)
+}
+
+fn templated_addresses(
+    record: &DomainRecord,
+    ipv4_addresses: &[Ipv4Addr],
+    ipv6_addresses: &[Ipv6Addr],
+) -> Addresses {
+    match &record.value {
+        Some(value) => (
+            rendered_addresses(ipv4_addresses, |address| {
+                render_ipv4(value, &record.vars, address)
+            }),
+            rendered_addresses(ipv6_addresses, |address| {
+                render_ipv6(value, &record.vars, address)
+            }),
+        ),
+        None => (ipv4_addresses.to_vec(), ipv6_addresses.to_vec()),
+    }
+}
+
+fn rendered_addresses<T, F>(addresses: &[T], render: F) -> Vec<T>
+where
+    T: PartialEq,
+    F: Fn(&T) -> DynResult<T>,
+{
+    addresses
+        .iter()
+        .filter_map(|address| match render(address) {
+            Ok(rendered) => Some(rendered),
+            Err(err) => {
+                error!("{:?}", err.context("failed to render record value"));
+                None
+            }
+        })
+        .fold(Vec::new(), |mut rendered, address| {
+            if !rendered.contains(&address) {
+                rendered.push(address);
+            }
+
+            rendered
+        })
+}
+
+fn logged_change(
+    zone: String,
+    change: Change,
+    record: Record,
+    current_zones: &DnsZones,
+) -> LoggedChange {
+    let (previous, current) = match change {
+        Change::Create => (None, Some(record)),
+        Change::Delete => (Some(record), None),
+        Change::Update(_) | Change::Refresh => {
+            let name = DnsName::from(record.name());
+
+            let previous = current_zones
+                .get(&Zone::new(zone.clone()))
+                .and_then(|records| {
+                    records.iter().find(|current| {
+                        current.record_type() == record.record_type()
+                            && DnsName::from(current.name()) == name
+                    })
+                })
+                .cloned();
+
+            (previous, Some(record))
+        }
+    };
+
+    LoggedChange {
+        zone,
+        previous,
+        current,
+    }
+}
+
+fn verify_signatures(config: &Config, summary: &Summary) {
+    let signed_zones = config
+        .zones
+        .iter()
+        .filter(|(_, zone_config)| zone_config.dnssec)
+        .map(|(zone, _)| DnsName::from(zone))
+        .collect::<Vec<_>>();
+
+    if signed_zones.is_empty() {
+        return;
+    }
+
+    let records = summary
+        .applied()
+        .into_iter()
+        .filter(|(_, change, _)| !matches!(change, Change::Delete))
+        .map(|(_, _, record)| record)
+        .filter(|record| {
+            let name = DnsName::from(record.name());
+            signed_zones.iter().any(|zone| name.is_in_zone(zone))
+        })
+        .collect::<Vec<_>>();
+
+    dnssec::verify(&records);
+}
 
-    start_job(config, || {
-        let mut failure_count = failure_count.lock().unwrap();
+fn published_records(
+    record: &DomainRecord,
+    ipv4_addresses: &[Ipv4Addr],
+    ipv6_addresses: &[Ipv6Addr],
+) -> Vec<PublishedRecord> {
+    let ipv4_record = record
+        .a
+        .as_ref()
+        .filter(|_| !ipv4_addresses.is_empty())
+        .map(|name| PublishedRecord {
+            name: name.clone(),
+            ipv6: false,
+            addresses: ipv4_addresses.iter().copied().map(IpAddr::V4).collect(),
+            ttl: record.ttl(),
+        });
 
-        if let Err(err) = run_once(config, provider) {
-            eprintln!("{:?}", err);
-            *failure_count += 1;
-        } else {
-            *failure_count = 0;
-        }
+    let ipv6_record = record
+        .aaaa
+        .as_ref()
+        .filter(|_| !ipv6_addresses.is_empty())
+        .map(|name| PublishedRecord {
+            name: name.clone(),
+            ipv6: true,
+            addresses: ipv6_addresses.iter().copied().map(IpAddr::V6).collect(),
+            ttl: record.ttl(),
+        });
 
-        if *failure_count >= 3 {
-            eprintln!("Too many errors in sequence: Aborting!");
-            exit(1);
-        }
-    });
+    ipv4_record.into_iter().chain(ipv6_record).collect()
 }
 
-pub fn run_once<P: DnsProvider>(config: &Config, provider: &P) -> DynResult<()> {
-    let current_ip = get_ip().context("failed to retrieve external IP address")?;
+fn healthy_addresses(record: &DomainRecord, failover: &FailoverConfig) -> Addresses {
+    let ipv4_addresses = record
+        .a
+        .iter()
+        .filter_map(|name| healthy_address(name, failover, false))
+        .filter_map(|address| match address {
+            IpAddr::V4(address) => Some(address),
+            IpAddr::V6(_) => None,
+        })
+        .collect();
 
-    println!("External IP address: {}", current_ip);
+    let ipv6_addresses = record
+        .aaaa
+        .iter()
+        .filter_map(|name| healthy_address(name, failover, true))
+        .filter_map(|address| match address {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(address) => Some(address),
+        })
+        .collect();
 
-    println!("Fetching current DNS records");
+    (ipv4_addresses, ipv6_addresses)
+}
 
-    let current_zones = provider
-        .current(config)
-        .context("failed to retrieve current DNS data")?;
+fn healthy_address(name: &str, failover: &FailoverConfig, ipv6: bool) -> Option<IpAddr> {
+    let candidate = failover.first_healthy(ipv6);
 
-    println!("Updating DNS records at {}", Local::now());
+    match (failover.candidates(ipv6).next(), candidate) {
+        (Some(primary), Some(candidate)) if primary != candidate => warn!(
+            "Primary candidate {} of {} is unhealthy, failing over to {}",
+            primary, name, candidate
+        ),
+        (_, None) => warn!(
+            "No healthy failover candidate for {}, not updating it",
+            name
+        ),
+        _ => {}
+    }
 
-    config.zones.iter().for_each(|(zone, records)| {
-        println!("---");
-        println!("Zone: {}", zone);
+    candidate
+}
 
-        records.iter().for_each(|record| match &current_ip {
-            Ip::V4(ipv4) => update_a_record(
-                provider,
-                zone.as_str(),
-                record.a.as_deref(),
-                ipv4,
-                record.ttl,
-                &current_zones,
-            ),
-            Ip::V6(ipv6) => update_aaaa_record(
-                provider,
-                zone.as_str(),
-                record.aaaa.as_deref(),
-                ipv6,
-                record.ttl,
-                &current_zones,
-            ),
-        });
-    });
+fn dependency_failed(record: &DomainRecord, zone: &Zone, summary: &Summary) -> bool {
+    match record
+        .after
+        .iter()
+        .find(|name| summary.has_failed(zone, name))
+    {
+        Some(name) => {
+            warn!(
+                "Not updating record {}: Record {} it depends on failed to update",
+                record.names().collect::<Vec<_>>().join(", "),
+                name
+            );
+            true
+        }
+        None => false,
+    }
+}
+
+// Approved changes only run after all of them were proposed, so dependencies are checked again here
+fn execute_approved<P: DnsProvider>(provider: &P, config: &Config, summary: &Summary) {
+    let zone_config = |zone: &Zone| {
+        config
+            .zones
+            .iter()
+            .find(|(name, _)| DnsName::from(*name) == DnsName::from(&zone.name))
+            .map(|(_, zone_config)| zone_config)
+    };
 
-    println!("---");
-    println!("Done updating DNS records at {}", Local::now());
+    for proposal in summary.approved() {
+        match proposal {
+            Proposal::Action(zone, action, change) => {
+                let record = zone_config(&zone).and_then(|zone_config| {
+                    zone_config
+                        .records
+                        .iter()
+                        .find(|record| record.has_name(action.record().name()))
+                });
 
-    Ok(())
+                if record.is_some_and(|record| dependency_failed(record, &zone, summary)) {
+                    summary.add(&zone, change, action.record().clone(), Outcome::Skipped);
+                } else {
+                    execute_action(provider, &zone, action, change, summary)
+                }
+            }
+            Proposal::Options(zone, changes) => {
+                if let Some(zone_config) = zone_config(&zone) {
+                    execute_options(provider, &zone, zone_config, changes, summary)
+                }
+            }
+        }
+    }
 }
 
-fn update_a_record<P: DnsProvider>(
+fn update_record<P: DnsProvider>(
     provider: &P,
-    zone: &str,
-    a_record: Option<&str>,
-    address: &Ipv4Addr,
-    ttl: u32,
+    zone: &Zone,
+    desired: Record,
     current_zones: &DnsZones,
+    summary: &Summary,
+    force: bool,
 ) {
-    let a_record = if let Some(a_record) = a_record {
-        a_record
-    } else {
-        return;
+    let current = current_zones
+        .get(zone)
+        .map(|records| {
+            records
+                .iter()
+                .filter(|current| same_name(current, &desired))
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    for action in diff(&[desired], &current) {
+        match action {
+            Action::Create(record) => {
+                debug!(
+                    "Creating {} record {}: {}",
+                    record.record_type(),
+                    record.name(),
+                    join_values(&record.values())
+                );
+                apply_action(
+                    provider,
+                    zone,
+                    Action::Create(record),
+                    Change::Create,
+                    summary,
+                )
+            }
+            Action::Update { current, desired } => {
+                let ttl = if current.ttl() != desired.ttl() {
+                    format!(" (TTL {} => {})", current.ttl(), desired.ttl())
+                } else {
+                    String::new()
+                };
+
+                debug!(
+                    "Updating {} record {}: {} => {}{}",
+                    desired.record_type(),
+                    desired.name(),
+                    join_values(&current.values()),
+                    join_values(&desired.values()),
+                    ttl
+                );
+                let change = Change::Update(join_values(&current.values()));
+                apply_action(
+                    provider,
+                    zone,
+                    Action::Update { current, desired },
+                    change,
+                    summary,
+                )
+            }
+            Action::Noop(record) if force => {
+                debug!(
+                    "Refreshing {} record {}: Unchanged",
+                    record.record_type(),
+                    record.name()
+                );
+                // refreshing rewrites the unchanged record
+                let action = Action::Update {
+                    current: record.clone(),
+                    desired: record,
+                };
+                apply_action(provider, zone, action, Change::Refresh, summary)
+            }
+            Action::Noop(record) => {
+                debug!(
+                    "Not updating {} record {}: Unchanged",
+                    record.record_type(),
+                    record.name()
+                );
+                summary.unchanged();
+            }
+            // duplicates of the record returned by the provider are left alone
+            Action::Delete(_) => {}
+        }
+    }
+}
+
+fn apply_options<P: DnsProvider>(
+    provider: &P,
+    zone: &Zone,
+    zone_config: &ZoneConfig,
+    summary: &Summary,
+) {
+    let changes = match provider.option_changes(zone, zone_config) {
+        Ok(changes) => changes,
+        Err(err) => {
+            error!(
+                "{:?}",
+                Error::from(err).context(format!(
+                    "failed to check provider options of zone {}",
+                    zone.name
+                ))
+            );
+            return;
+        }
     };
 
-    let zone = current_zones.find_or_create(zone);
+    if changes.is_empty() {
+        debug!(
+            "Not applying provider options of zone {}: Unchanged",
+            zone.name
+        );
+        return;
+    }
 
-    let current_record = current_zones
-        .iter()
-        .find(|(zone_id, _)| zone_id == &&zone)
-        .map(|(_, zone_content)| {
-            zone_content.iter().find(|&record| {
-                if let Record::A { name, .. } = record {
-                    name == a_record
-                } else {
-                    false
-                }
-            })
-        });
+    if summary.needs_approval() {
+        summary.propose(Proposal::Options(zone.clone(), changes));
+        return;
+    }
+
+    if !summary.confirm_options(&changes) {
+        summary.add_options(changes, Outcome::Skipped);
+        return;
+    }
+
+    execute_options(provider, zone, zone_config, changes, summary)
+}
+
+fn execute_options<P: DnsProvider>(
+    provider: &P,
+    zone: &Zone,
+    zone_config: &ZoneConfig,
+    changes: Vec<String>,
+    summary: &Summary,
+) {
+    let result = span(
+        "provider.apply_options",
+        &[("zone", zone.name.clone())],
+        || provider.apply_options(zone, zone_config),
+    );
 
-    let current_value = if let Some(Some(record)) = current_record {
-        match record {
-            Record::A { value, .. } => Some(value),
-            _ => panic!(),
+    let outcome = match result {
+        Ok(()) => Outcome::Applied,
+        Err(err) => {
+            error!(
+                "{:?}",
+                Error::from(err).context(format!(
+                    "failed to apply provider options of zone {}",
+                    zone.name
+                ))
+            );
+            Outcome::Failed
         }
-    } else {
-        None
     };
 
-    let new_record = Record::A {
-        name: a_record.to_string(),
-        value: *address,
-        ttl,
+    summary.add_options(changes, outcome);
+}
+
+fn apply_action<P: DnsProvider>(
+    provider: &P,
+    zone: &Zone,
+    action: Action,
+    change: Change,
+    summary: &Summary,
+) {
+    if summary.needs_approval() {
+        summary.propose(Proposal::Action(zone.clone(), action, change));
+        return;
+    }
+
+    if !summary.confirm(&change, action.record()) {
+        summary.add(zone, change, action.record().clone(), Outcome::Skipped);
+        return;
+    }
+
+    execute_action(provider, zone, action, change, summary)
+}
+
+fn execute_action<P: DnsProvider>(
+    provider: &P,
+    zone: &Zone,
+    action: Action,
+    change: Change,
+    summary: &Summary,
+) {
+    let record = action.record().clone();
+
+    let (span_name, verb) = match action {
+        Action::Delete(_) => ("provider.delete", "delete"),
+        _ => ("provider.update", "update"),
     };
 
-    if let Some(current_value) = current_value {
-        if current_value != address {
-            println!(
-                "Updating A record {}: {} => {}",
-                a_record, current_value, address
+    let result = span(
+        span_name,
+        &[("zone", zone.name.clone()), ("record", record.to_string())],
+        || provider.apply(zone, action),
+    );
+
+    let outcome = match result {
+        Ok(()) => Outcome::Applied,
+        Err(err) => {
+            error!(
+                "{:?}",
+                Error::from(err).context(format!("failed to {} record {}", verb, record))
             );
-            wrap_update(provider, &zone, new_record)
-        } else {
-            println!("Not updating A record {}: Unchanged", a_record);
+            Outcome::Failed
         }
-    } else {
-        println!("Creating A record {}: {}", a_record, address);
-        wrap_update(provider, &zone, new_record)
-    }
+    };
+
+    summary.add(zone, change, record, outcome);
 }
 
-fn update_aaaa_record<P: DnsProvider>(
+fn prune_records<P: DnsProvider>(
     provider: &P,
-    zone: &str,
-    aaaa_record: Option<&str>,
-    address: &Ipv6Addr,
-    ttl: u32,
+    zone: &Zone,
+    zone_config: &ZoneConfig,
+    ip_version: IpVersion,
+    ptr_names: &[String],
     current_zones: &DnsZones,
+    summary: &Summary,
 ) {
-    let aaaa_record = if let Some(aaaa_record) = aaaa_record {
-        aaaa_record
+    let current_records = if let Some(current_records) = current_zones.get(zone) {
+        current_records
     } else {
         return;
     };
 
-    let zone = current_zones.find_or_create(zone);
-
-    let current_record = current_zones
+    current_records
         .iter()
-        .find(|(zone_id, _)| zone_id == &&zone)
-        .map(|(_, zone_content)| {
-            zone_content.iter().find(|&record| {
-                if let Record::AAAA { name, .. } = record {
-                    name == aaaa_record
-                } else {
-                    false
-                }
-            })
+        .filter(|&record| match record {
+            Record::A { .. } => ip_version.ipv4(),
+            Record::AAAA { .. } => ip_version.ipv6(),
+            Record::PTR { .. }
+            | Record::CNAME { .. }
+            | Record::SRV { .. }
+            | Record::HTTPS { .. } => true,
+        })
+        .filter(|&record| {
+            let name = DnsName::from(record.name());
+            !zone_config
+                .keep
+                .iter()
+                .any(|kept| DnsName::from(kept) == name)
+        })
+        .filter(|&record| match record {
+            // derived from the records with `ptr`, which are usually configured in another zone
+            Record::PTR { name, .. } => !ptr_names
+                .iter()
+                .any(|ptr_name| DnsName::from(ptr_name) == DnsName::from(name)),
+            Record::A { name, .. } => !zone_config
+                .records
+                .iter()
+                .any(|configured| configured.a.as_ref().map(DnsName::from) == Some(name.into())),
+            Record::AAAA { name, .. } => !zone_config
+                .records
+                .iter()
+                .any(|configured| configured.aaaa.as_ref().map(DnsName::from) == Some(name.into())),
+            Record::CNAME { name, .. } => !zone_config.records.iter().any(|configured| {
+                configured
+                    .cname
+                    .as_ref()
+                    .map(|cname| DnsName::from(&cname.name))
+                    == Some(name.into())
+            }),
+            Record::SRV { name, .. } | Record::HTTPS { name, .. } => {
+                !zone_config.records.iter().any(|configured| {
+                    configured.service_records().iter().any(|service| {
+                        service.record_type() == record.record_type()
+                            && DnsName::from(service.name()) == DnsName::from(name)
+                    })
+                })
+            }
+        })
+        .for_each(|record| {
+            debug!("Pruning record {}", record);
+
+            let action = Action::Delete(record.clone());
+            apply_action(provider, zone, action, Change::Delete, summary)
         });
+}
+
+struct DryRun<'a, P: DnsProvider>(&'a P);
+
+impl<'a, P: DnsProvider> DnsProvider for DryRun<'a, P> {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn current(&self, config: &Config) -> crate::Result<ZoneResults> {
+        self.0.current(config)
+    }
+
+    fn update(&self, _zone: &Zone, _record: Record) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn delete(&self, _zone: &Zone, _record: Record) -> crate::Result<()> {
+        Ok(())
+    }
 
-    let current_value = if let Some(Some(record)) = current_record {
-        match record {
-            Record::AAAA { value, .. } => Some(value),
-            _ => panic!(),
+    fn apply(&self, _zone: &Zone, _action: Action) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn option_changes(&self, zone: &Zone, zone_config: &ZoneConfig) -> crate::Result<Vec<String>> {
+        self.0.option_changes(zone, zone_config)
+    }
+}
+
+struct Snapshotting<'a, P: DnsProvider> {
+    provider: &'a P,
+    pending: RefCell<HashSet<Zone>>,
+    snapshots: &'a RefCell<BTreeMap<String, String>>,
+}
+
+impl<'a, P: DnsProvider> Snapshotting<'a, P> {
+    fn new(
+        provider: &'a P,
+        config: &Config,
+        snapshots: &'a RefCell<BTreeMap<String, String>>,
+    ) -> Self {
+        let pending = config
+            .zones
+            .iter()
+            .filter(|(_, zone_config)| zone_config.snapshot)
+            .map(|(zone, _)| Zone::new(zone.clone()))
+            .collect();
+
+        Snapshotting {
+            provider,
+            pending: RefCell::new(pending),
+            snapshots,
         }
-    } else {
-        None
-    };
+    }
 
-    let new_record = Record::AAAA {
-        name: aaaa_record.to_string(),
-        value: *address,
-        ttl,
-    };
+    // Only the first change of a zone in each run takes a snapshot
+    fn snapshot_before_change(&self, zone: &Zone) -> crate::Result<()> {
+        if !self.pending.borrow().contains(zone) {
+            return Ok(());
+        }
 
-    if let Some(current_value) = current_value {
-        if current_value != address {
-            println!(
-                "Updating AAAA record {}: {} => {}",
-                aaaa_record, current_value, address
-            );
-            wrap_update(provider, &zone, new_record)
-        } else {
-            println!("Not updating AAAA record {}: Unchanged", aaaa_record);
+        let result = span("provider.snapshot", &[("zone", zone.name.clone())], || {
+            self.provider.snapshot(zone)
+        });
+
+        match result {
+            Ok(snapshot) => {
+                info!("Created snapshot {} of zone {}", snapshot, zone.name);
+                self.snapshots
+                    .borrow_mut()
+                    .insert(zone.name.clone(), snapshot);
+            }
+            Err(err) if err.provider_kind() == Some(ProviderErrorKind::Unsupported) => warn!(
+                "{:?}",
+                Error::from(err).context(format!("not taking a snapshot of zone {}", zone.name))
+            ),
+            Err(err) => return Err(err),
         }
-    } else {
-        println!("Creating AAAA record {}: {}", aaaa_record, address);
-        wrap_update(provider, &zone, new_record)
+
+        self.pending.borrow_mut().remove(zone);
+        Ok(())
     }
 }
 
-fn wrap_update<P: DnsProvider>(provider: &P, zone: &Zone, record: Record) {
-    let result = provider.update(zone, record.clone());
+impl<'a, P: DnsProvider> DnsProvider for Snapshotting<'a, P> {
+    fn name(&self) -> &str {
+        self.provider.name()
+    }
 
-    if let Err(err) = result {
-        eprintln!(
-            "{:?}",
-            err.context(format!("failed to update record {}", record))
-        )
+    fn current(&self, config: &Config) -> crate::Result<ZoneResults> {
+        self.provider.current(config)
+    }
+
+    fn update(&self, zone: &Zone, record: Record) -> crate::Result<()> {
+        self.snapshot_before_change(zone)?;
+        self.provider.update(zone, record)
+    }
+
+    fn delete(&self, zone: &Zone, record: Record) -> crate::Result<()> {
+        self.snapshot_before_change(zone)?;
+        self.provider.delete(zone, record)
+    }
+
+    fn apply(&self, zone: &Zone, action: Action) -> crate::Result<()> {
+        if !matches!(action, Action::Noop(_)) {
+            self.snapshot_before_change(zone)?;
+        }
+
+        self.provider.apply(zone, action)
+    }
+
+    fn option_changes(&self, zone: &Zone, zone_config: &ZoneConfig) -> crate::Result<Vec<String>> {
+        self.provider.option_changes(zone, zone_config)
+    }
+
+    fn apply_options(&self, zone: &Zone, zone_config: &ZoneConfig) -> crate::Result<()> {
+        self.provider.apply_options(zone, zone_config)
+    }
+}
+
+// Lets runs shortly after each other, e.g. triggered through the API, reuse the records listed by the previous one
+struct Cached<'a, P: DnsProvider> {
+    provider: &'a P,
+    ttl: Option<Duration>,
+    cache: &'a RefCell<Option<(DnsZones, Instant)>>,
+}
+
+impl<'a, P: DnsProvider> DnsProvider for Cached<'a, P> {
+    fn name(&self) -> &str {
+        self.provider.name()
+    }
+
+    fn current(&self, config: &Config) -> crate::Result<ZoneResults> {
+        let ttl = match self.ttl {
+            Some(ttl) => ttl,
+            None => return self.provider.current(config),
+        };
+
+        if let Some((zones, listed_at)) = &*self.cache.borrow() {
+            if listed_at.elapsed() < ttl {
+                debug!(
+                    "Using the DNS records listed {} seconds ago",
+                    listed_at.elapsed().as_secs()
+                );
+                return Ok(zones
+                    .iter()
+                    .map(|(zone, records)| (zone.clone(), Ok(records.clone())))
+                    .collect());
+            }
+        }
+
+        let results = self.provider.current(config)?;
+
+        // Only complete listings are kept, so failed zones are listed again by the next run
+        *self.cache.borrow_mut() = results
+            .iter()
+            .map(|(zone, records)| Some((zone.clone(), records.as_ref().ok()?.clone())))
+            .collect::<Option<DnsZones>>()
+            .map(|zones| (zones, Instant::now()));
+
+        Ok(results)
+    }
+
+    fn update(&self, zone: &Zone, record: Record) -> crate::Result<()> {
+        self.cache.take();
+        self.provider.update(zone, record)
+    }
+
+    fn delete(&self, zone: &Zone, record: Record) -> crate::Result<()> {
+        self.cache.take();
+        self.provider.delete(zone, record)
+    }
+
+    fn apply(&self, zone: &Zone, action: Action) -> crate::Result<()> {
+        self.cache.take();
+        self.provider.apply(zone, action)
+    }
+
+    fn option_changes(&self, zone: &Zone, zone_config: &ZoneConfig) -> crate::Result<Vec<String>> {
+        self.provider.option_changes(zone, zone_config)
+    }
+
+    fn apply_options(&self, zone: &Zone, zone_config: &ZoneConfig) -> crate::Result<()> {
+        self.provider.apply_options(zone, zone_config)
     }
 }
 
 trait FindOrCreateZone {
-    fn find_or_create(&self, zone: &str) -> Zone;
+    fn find_or_create(&self, zone: &str, zone_config: &ZoneConfig) -> Zone;
 }
 
 impl FindOrCreateZone for DnsZones {
-    fn find_or_create(&self, zone: &str) -> Zone {
-        self.keys()
-            .find(|key| key.name == zone)
+    fn find_or_create(&self, zone: &str, zone_config: &ZoneConfig) -> Zone {
+        let mut zone = self
+            .keys()
+            .find(|key| DnsName::from(&key.name) == DnsName::from(zone))
             .cloned()
-            .unwrap_or_else(|| Zone::new(zone.into()))
+            .unwrap_or_else(|| Zone::new(zone.into()));
+
+        if zone.id.is_none() {
+            zone.id = zone_config.id.clone();
+        }
+
+        zone.credentials = zone_config.credentials.clone();
+
+        zone
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_addresses() {
+        let record: DomainRecord = serde_yaml::from_str(
+            "{ a: www.example.com, aaaa: www.example.com, additional_addresses: [198.51.100.5, 203.0.113.10] }",
+        )
+        .unwrap();
+
+        let ipv4_addresses = ["203.0.113.10".parse().unwrap()];
+
+        assert_eq!(
+            (
+                vec![
+                    "203.0.113.10".parse::<Ipv4Addr>().unwrap(),
+                    "198.51.100.5".parse().unwrap()
+                ],
+                Vec::new()
+            ),
+            record_addresses(&record, &ipv4_addresses, &[])
+        );
+
+        assert_eq!(
+            (Vec::new(), Vec::new()),
+            record_addresses(&record, &[], &[])
+        );
+
+        let record: DomainRecord = serde_yaml::from_str(
+            "{ aaaa: nas.example.com, value: '${ipv6_prefix}${suffix}', vars: { suffix: '::10' } }",
+        )
+        .unwrap();
+
+        let ipv6_addresses = [
+            "2001:db8:0:5::1".parse().unwrap(),
+            "2001:db8:0:5::2".parse().unwrap(),
+        ];
+
+        assert_eq!(
+            (
+                Vec::new(),
+                vec!["2001:db8:0:5::10".parse::<Ipv6Addr>().unwrap()]
+            ),
+            record_addresses(&record, &[], &ipv6_addresses)
+        );
+    }
+
+    struct Counting(Cell<usize>);
+
+    impl DnsProvider for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn current(&self, _config: &Config) -> crate::Result<ZoneResults> {
+            self.0.set(self.0.get() + 1);
+            Ok(HashMap::from([(
+                Zone::new("example.com".into()),
+                Ok(Vec::new()),
+            )]))
+        }
+
+        fn update(&self, _zone: &Zone, _record: Record) -> crate::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_restrict_addresses() {
+        let addresses: Addresses = (
+            vec!["203.0.113.10".parse().unwrap()],
+            vec!["2001:db8::10".parse().unwrap()],
+        );
+
+        assert_eq!(
+            addresses,
+            restrict_addresses(addresses.clone(), IpVersion::Both)
+        );
+        assert_eq!(
+            (addresses.0.clone(), Vec::new()),
+            restrict_addresses(addresses.clone(), IpVersion::Ipv4)
+        );
+        assert_eq!(
+            (Vec::new(), addresses.1.clone()),
+            restrict_addresses(addresses, IpVersion::Ipv6)
+        );
+    }
+
+    #[test]
+    fn test_cached_listing() {
+        let config: Config =
+            serde_yaml::from_str("zones: { example.com: [{ a: home.example.com }] }").unwrap();
+
+        let provider = Counting(Cell::new(0));
+        let cache = RefCell::new(None);
+        let cached = Cached {
+            provider: &provider,
+            ttl: Some(Duration::from_secs(60)),
+            cache: &cache,
+        };
+
+        cached.current(&config).unwrap();
+        cached.current(&config).unwrap();
+        assert_eq!(1, provider.0.get());
+
+        cached
+            .update(
+                &Zone::new("example.com".into()),
+                Record::A {
+                    name: "home.example.com".into(),
+                    values: vec![Ipv4Addr::new(203, 0, 113, 10)],
+                    ttl: 300,
+                },
+            )
+            .unwrap();
+
+        cached.current(&config).unwrap();
+        assert_eq!(2, provider.0.get());
+    }
+
+    #[test]
+    fn test_sticky_zones() {
+        let config: Config = serde_yaml::from_str(
+            "{ sticky_grace_period: 60, zones: { example.com: [{ a: home.example.com }] } }",
+        )
+        .unwrap();
+
+        let provider = Counting(Cell::new(0));
+        let updater = Updater::new(&config, &provider);
+
+        let zone = Zone::new("example.com".into());
+        let failed_zones = [(zone.clone(), Error::msg("timeout"))];
+
+        assert!(updater.sticky_zones(&failed_zones).contains(&zone));
+        assert!(updater.sticky_zones(&failed_zones).contains(&zone));
+
+        // Failing for longer than the grace period
+        updater
+            .zone_failures
+            .borrow_mut()
+            .insert(zone.clone(), Instant::now() - Duration::from_secs(120));
+        assert!(updater.sticky_zones(&failed_zones).is_empty());
+
+        // A successful listing starts the grace period over
+        assert!(updater.sticky_zones(&[]).is_empty());
+        assert!(updater.sticky_zones(&failed_zones).contains(&zone));
+    }
+
+    struct Recording(RefCell<Vec<Action>>);
+
+    impl DnsProvider for Recording {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn current(&self, _config: &Config) -> crate::Result<ZoneResults> {
+            Ok(HashMap::new())
+        }
+
+        fn update(&self, _zone: &Zone, _record: Record) -> crate::Result<()> {
+            panic!("records must be changed through apply")
+        }
+
+        fn apply(&self, _zone: &Zone, action: Action) -> crate::Result<()> {
+            self.0.borrow_mut().push(action);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_update_record_actions() {
+        let zone = Zone::new("example.com".into());
+        let a = |name: &str, value| Record::A {
+            name: name.into(),
+            values: vec![value],
+            ttl: 300,
+        };
+
+        let current = a("home.example.com", Ipv4Addr::new(203, 0, 113, 10));
+        let current_zones = HashMap::from([(
+            zone.clone(),
+            vec![
+                current.clone(),
+                a("nas.example.com", Ipv4Addr::new(203, 0, 113, 10)),
+            ],
+        )]);
+
+        let provider = Recording(RefCell::new(Vec::new()));
+        let summary = Summary::default();
+
+        let updated = a("home.example.com", Ipv4Addr::new(203, 0, 113, 20));
+        let created = a("vpn.example.com", Ipv4Addr::new(203, 0, 113, 20));
+        let unchanged = a("nas.example.com", Ipv4Addr::new(203, 0, 113, 10));
+
+        for desired in [updated.clone(), created.clone(), unchanged] {
+            update_record(&provider, &zone, desired, &current_zones, &summary, false);
+        }
+
+        assert_eq!(
+            vec![
+                Action::Update {
+                    current,
+                    desired: updated
+                },
+                Action::Create(created)
+            ],
+            provider.0.into_inner()
+        );
+    }
+
+    struct FailingVpn(RefCell<Vec<Action>>);
+
+    impl DnsProvider for FailingVpn {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn current(&self, _config: &Config) -> crate::Result<ZoneResults> {
+            Ok(HashMap::new())
+        }
+
+        fn update(&self, _zone: &Zone, _record: Record) -> crate::Result<()> {
+            panic!("records must be changed through apply")
+        }
+
+        fn apply(&self, _zone: &Zone, action: Action) -> crate::Result<()> {
+            if action.record().name() == "vpn.example.com" {
+                return Err(crate::Error::provider(
+                    ProviderErrorKind::Unavailable,
+                    "timeout",
+                ));
+            }
+
+            self.0.borrow_mut().push(action);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_approved_dependency_failed() {
+        let config: Config = serde_yaml::from_str(
+            "zones: { example.com: [{ a: vpn.example.com }, { a: home.example.com, after: vpn.example.com }, { a: nas.example.com }] }",
+        )
+        .unwrap();
+
+        let zone = Zone::new("example.com".into());
+        let a = |name: &str| Record::A {
+            name: name.into(),
+            values: vec![Ipv4Addr::new(203, 0, 113, 10)],
+            ttl: 300,
+        };
+
+        let provider = FailingVpn(RefCell::new(Vec::new()));
+        let summary = Summary::default();
+        for name in ["vpn.example.com", "home.example.com", "nas.example.com"] {
+            summary.propose(Proposal::Action(
+                zone.clone(),
+                Action::Create(a(name)),
+                Change::Create,
+            ));
+        }
+
+        execute_approved(&provider, &config, &summary);
+
+        assert_eq!(
+            vec![Action::Create(a("nas.example.com"))],
+            provider.0.into_inner()
+        );
+        assert!(summary.has_failed(&zone, "vpn.example.com"));
+        assert_eq!(1, summary.applied().len());
+    }
+
+    #[test]
+    fn test_prune_ptr_records() {
+        let zone = Zone::new("113.0.203.in-addr.arpa".into());
+        let ptr = |name: &str| Record::PTR {
+            name: name.into(),
+            target: "home.example.com".into(),
+            ttl: 300,
+        };
+
+        let derived = ptr("10.113.0.203.in-addr.arpa");
+        let stale = ptr("20.113.0.203.in-addr.arpa");
+        let current_zones = HashMap::from([(zone.clone(), vec![derived, stale.clone()])]);
+
+        let provider = Recording(RefCell::new(Vec::new()));
+        let ptr_names = vec![reverse_name(&IpAddr::V4(Ipv4Addr::new(203, 0, 113, 10)))];
+
+        prune_records(
+            &provider,
+            &zone,
+            &ZoneConfig::default(),
+            IpVersion::default(),
+            &ptr_names,
+            &current_zones,
+            &Summary::default(),
+        );
+
+        assert_eq!(vec![Action::Delete(stale)], provider.0.into_inner());
+    }
+
+    #[test]
+    fn test_prune_by_name() {
+        let zone_config: ZoneConfig = serde_yaml::from_str(
+            r#"
+prune: true
+records:
+  - a: home.example.com
+    srv:
+      - name: _sip._tcp.example.com
+        port: 5060
+  - cname: www.example.com -> home.example.com
+"#,
+        )
+        .unwrap();
+        let zone = Zone::new("example.com".into());
+        let srv = |name: &str| Record::SRV {
+            name: name.into(),
+            priority: 0,
+            weight: 0,
+            port: 5060,
+            target: "home.example.com".into(),
+            ttl: 300,
+        };
+        let cname = |name: &str| Record::CNAME {
+            name: name.into(),
+            target: "home.example.com".into(),
+            ttl: 300,
+        };
+
+        let current_zones = HashMap::from([(
+            zone.clone(),
+            vec![
+                srv("_sip._tcp.example.com"),
+                srv("_xmpp._tcp.example.com"),
+                cname("www.example.com"),
+                cname("old.example.com"),
+            ],
+        )]);
+        let provider = Recording(RefCell::new(Vec::new()));
+
+        prune_records(
+            &provider,
+            &zone,
+            &zone_config,
+            IpVersion::default(),
+            &[],
+            &current_zones,
+            &Summary::default(),
+        );
+
+        assert_eq!(
+            vec![
+                Action::Delete(srv("_xmpp._tcp.example.com")),
+                Action::Delete(cname("old.example.com"))
+            ],
+            provider.0.into_inner()
+        );
+    }
+
+    #[test]
+    fn test_templated_ptr_records() {
+        let config: Config = serde_yaml::from_str(
+            "zones: { example.com: [{ aaaa: nas.example.com, ptr: true, value: '${ipv6_prefix}::10' }] }",
+        )
+        .unwrap();
+        let provider = Recording(RefCell::new(Vec::new()));
+        let updater = Updater::new(&config, &provider);
+
+        let ptr_records = updater.ptr_records(&provider, &[], &["2001:db8::1".parse().unwrap()]);
+
+        assert_eq!(
+            vec![(
+                "2001:db8::10".parse::<IpAddr>().unwrap(),
+                "nas.example.com".to_string(),
+                crate::config::DEFAULT_TTL
+            )],
+            ptr_records
+        );
+    }
+
+    #[test]
+    fn test_write_conflicts() {
+        let config: Config =
+            serde_yaml::from_str("zones: { example.com: [{ a: home.example.com }] }").unwrap();
+        let provider = Recording(RefCell::new(Vec::new()));
+        let updater = Updater::new(&config, &provider);
+
+        let zone = Zone::new("example.com".into());
+        let a = |name: &str, value, ttl| Record::A {
+            name: name.into(),
+            values: vec![value],
+            ttl,
+        };
+
+        let summary = Summary::default();
+        for name in ["home.example.com", "nas.example.com"] {
+            let record = a(name, Ipv4Addr::new(203, 0, 113, 10), 300);
+            summary.add(&zone, Change::Create, record, Outcome::Applied);
+        }
+        updater.remember_written(&summary);
+
+        // the provider reporting another TTL isn't a conflict, another address is
+        let current_zones = DnsZones::from([(
+            zone,
+            vec![
+                a("home.example.com", Ipv4Addr::new(203, 0, 113, 10), 3600),
+                a("nas.example.com", Ipv4Addr::new(198, 51, 100, 7), 300),
+            ],
+        )]);
+        updater.check_conflicts(&current_zones);
+
+        assert_eq!(
+            vec![a("home.example.com", Ipv4Addr::new(203, 0, 113, 10), 300)],
+            updater.state.borrow().written
+        );
     }
 }
@@ -0,0 +1,119 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::env;
+use std::ffi::OsString;
+use std::sync::RwLock;
+
+pub const DEFAULT_PREFIX: &str = "EZDD";
+
+static PREFIX: RwLock<Option<String>> = RwLock::new(None);
+
+/// Changes the prefix of all environment variables, an empty prefix drops it entirely
+pub fn set_prefix(prefix: &str) {
+    *PREFIX.write().unwrap() = Some(prefix.trim_end_matches('_').to_uppercase());
+}
+
+/// The prefixed name of the environment variable `name`, e.g. `EZDD_LIVEDNS_API_KEY`
+pub fn name(name: &str) -> String {
+    match PREFIX.read().unwrap().as_deref().unwrap_or(DEFAULT_PREFIX) {
+        "" => name.into(),
+        prefix => format!("{}_{}", prefix, name),
+    }
+}
+
+pub fn var(name: &str) -> Option<String> {
+    env::var(self::name(name)).ok()
+}
+
+pub fn var_os(name: &str) -> Option<OsString> {
+    env::var_os(self::name(name))
+}
+
+/// Like `var`, but falls back to the unprefixed name used before the prefix was introduced
+pub fn legacy_var(name: &str) -> Option<String> {
+    var(name).or_else(|| env::var(name).ok())
+}
+
+pub fn legacy_var_os(name: &str) -> Option<OsString> {
+    var_os(name).or_else(|| env::var_os(name))
+}
+
+// `--env-prefix` has to be known before the arguments are parsed, the names of the variables
+// backing arguments depend on it
+pub(crate) fn prefix_arg(args: &[OsString]) -> Option<String> {
+    let mut args = args.iter().filter_map(|arg| arg.to_str());
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--env-prefix" {
+            return args.next().map(String::from);
+        } else if let Some(prefix) = arg.strip_prefix("--env-prefix=") {
+            return Some(prefix.into());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_arg() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            Some("HOME".to_string()),
+            prefix_arg(&args(&[
+                "dyndns",
+                "once",
+                "--env-prefix",
+                "HOME",
+                "-c",
+                "x.yml"
+            ]))
+        );
+        assert_eq!(
+            Some("HOME".to_string()),
+            prefix_arg(&args(&["dyndns", "--env-prefix=HOME", "once"]))
+        );
+        assert_eq!(
+            None,
+            prefix_arg(&args(&["dyndns", "once", "--", "--env-prefix"]))
+        );
+        assert_eq!(None, prefix_arg(&args(&["dyndns", "once"])));
+    }
+}
@@ -0,0 +1,228 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use thiserror::Error;
+
+use crate::http::HttpError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub type Source = Box<dyn StdError + Send + Sync + 'static>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid configuration")]
+    Config(#[source] Source),
+    #[error("failed to detect the external IP address")]
+    IpDetection(#[source] Source),
+    #[error("DNS provider error ({kind})")]
+    Provider {
+        kind: ProviderErrorKind,
+        #[source]
+        source: Source,
+    },
+    #[error(transparent)]
+    Http(#[from] HttpError),
+    #[cfg(feature = "blocking")]
+    #[error(transparent)]
+    Transport(#[from] Box<ureq::Transport>),
+    #[cfg(feature = "async")]
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProviderErrorKind {
+    Authentication,
+    NotFound,
+    RateLimited,
+    Unavailable,
+    Unsupported,
+    Other,
+}
+
+impl Display for ProviderErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ProviderErrorKind::Authentication => "authentication",
+                ProviderErrorKind::NotFound => "not found",
+                ProviderErrorKind::RateLimited => "rate limited",
+                ProviderErrorKind::Unavailable => "unavailable",
+                ProviderErrorKind::Unsupported => "unsupported",
+                ProviderErrorKind::Other => "other",
+            }
+        )
+    }
+}
+
+impl ProviderErrorKind {
+    pub fn from_chain<'a, I>(chain: I) -> ProviderErrorKind
+    where
+        I: IntoIterator<Item = &'a (dyn StdError + 'static)>,
+    {
+        chain
+            .into_iter()
+            .find_map(|err| err.downcast_ref::<Error>().and_then(Error::provider_kind))
+            .unwrap_or(ProviderErrorKind::Other)
+    }
+
+    fn from_status(status: u16) -> ProviderErrorKind {
+        match status {
+            401 | 403 => ProviderErrorKind::Authentication,
+            404 => ProviderErrorKind::NotFound,
+            429 => ProviderErrorKind::RateLimited,
+            500.. => ProviderErrorKind::Unavailable,
+            _ => ProviderErrorKind::Other,
+        }
+    }
+}
+
+impl Error {
+    pub fn provider<E: Into<Source>>(kind: ProviderErrorKind, source: E) -> Error {
+        Error::Provider {
+            kind,
+            source: source.into(),
+        }
+    }
+
+    pub fn from_provider<E: Into<Source>>(err: E) -> Error {
+        match err.into().downcast::<Error>() {
+            Ok(err) => *err,
+            Err(source) => Error::Provider {
+                kind: ProviderErrorKind::from_chain(chain(&*source)),
+                source,
+            },
+        }
+    }
+
+    pub fn provider_kind(&self) -> Option<ProviderErrorKind> {
+        match self {
+            Error::Provider { kind, .. } => Some(*kind),
+            Error::Http(http_error) => Some(ProviderErrorKind::from_status(http_error.status)),
+            #[cfg(feature = "blocking")]
+            Error::Transport(_) => Some(ProviderErrorKind::Unavailable),
+            #[cfg(feature = "async")]
+            Error::Request(_) => Some(ProviderErrorKind::Unavailable),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn chain<'a>(
+    err: &'a (dyn StdError + 'static),
+) -> impl Iterator<Item = &'a (dyn StdError + 'static)> {
+    std::iter::successors(Some(err), |err| (*err).source())
+}
+
+// The error and all of its sources on one line, like the alternate format of anyhow
+pub fn describe(err: &(dyn StdError + 'static)) -> String {
+    chain(err)
+        .map(|err| err.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+pub(crate) fn from_anyhow(err: anyhow::Error) -> Error {
+    let is_unwrappable = err.downcast_ref::<Error>().is_some_and(|inner| {
+        inner.provider_kind().is_none() || chain(inner).count() == err.chain().count()
+    });
+
+    let kind = ProviderErrorKind::from_chain(err.chain());
+
+    if !is_unwrappable {
+        return Error::provider(kind, err);
+    }
+
+    err.downcast::<Error>()
+        .unwrap_or_else(|err| Error::provider(kind, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Context;
+
+    #[test]
+    fn test_provider_kind() {
+        let http_error = HttpError {
+            url: "https://api.example.com".into(),
+            status: 404,
+            body: String::new(),
+            retry_after: None,
+        };
+
+        let err = Err::<(), _>(Error::from(http_error))
+            .context("failed to fetch records")
+            .unwrap_err();
+
+        assert_eq!(
+            ProviderErrorKind::NotFound,
+            ProviderErrorKind::from_chain(err.chain())
+        );
+
+        let err = from_anyhow(err);
+        assert_eq!(Some(ProviderErrorKind::NotFound), err.provider_kind());
+        assert_eq!("failed to fetch records", err.source().unwrap().to_string());
+
+        let err = from_anyhow(anyhow::Error::msg("something went wrong"));
+        assert_eq!(Some(ProviderErrorKind::Other), err.provider_kind());
+
+        let err = from_anyhow(anyhow::Error::from(Error::Config("invalid".into())));
+        assert!(matches!(err, Error::Config(_)));
+
+        let err = Error::from_provider(Error::provider(
+            ProviderErrorKind::Unsupported,
+            "PTR records are not supported",
+        ));
+        assert_eq!(Some(ProviderErrorKind::Unsupported), err.provider_kind());
+        assert_eq!(
+            "PTR records are not supported",
+            err.source().unwrap().to_string()
+        );
+
+        let err = Error::from_provider(io::Error::other("connection reset"));
+        assert_eq!(Some(ProviderErrorKind::Other), err.provider_kind());
+    }
+}
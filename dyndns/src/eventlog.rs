@@ -0,0 +1,124 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use log::Log;
+
+use crate::result::DynResult;
+
+#[cfg(windows)]
+pub(crate) fn event_logger(source: &str) -> DynResult<Box<dyn Log>> {
+    use std::ffi::OsStr;
+    use std::io;
+    use std::iter::once;
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+
+    use anyhow::Context;
+    use log::{Level, Metadata, Record};
+    use winapi::um::winbase::{DeregisterEventSource, RegisterEventSourceW, ReportEventW};
+    use winapi::um::winnt::{
+        EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE, HANDLE,
+    };
+
+    const EVENT_ID: u32 = 1;
+
+    struct EventLogger {
+        handle: HANDLE,
+    }
+
+    // Event log handles may be used from any thread
+    unsafe impl Send for EventLogger {}
+    unsafe impl Sync for EventLogger {}
+
+    impl Log for EventLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= log::max_level()
+        }
+
+        fn log(&self, record: &Record) {
+            let event_type = match record.level() {
+                Level::Error => EVENTLOG_ERROR_TYPE,
+                Level::Warn => EVENTLOG_WARNING_TYPE,
+                _ => EVENTLOG_INFORMATION_TYPE,
+            };
+
+            let message = to_wide(&record.args().to_string());
+            let mut strings = [message.as_ptr()];
+
+            unsafe {
+                ReportEventW(
+                    self.handle,
+                    event_type,
+                    0,
+                    EVENT_ID,
+                    ptr::null_mut(),
+                    1,
+                    0,
+                    strings.as_mut_ptr(),
+                    ptr::null_mut(),
+                );
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    impl Drop for EventLogger {
+        fn drop(&mut self) {
+            unsafe {
+                DeregisterEventSource(self.handle);
+            }
+        }
+    }
+
+    fn to_wide(value: &str) -> Vec<u16> {
+        OsStr::new(value).encode_wide().chain(once(0)).collect()
+    }
+
+    let source_name = to_wide(source);
+    let handle = unsafe { RegisterEventSourceW(ptr::null(), source_name.as_ptr()) };
+
+    if handle.is_null() {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("failed to register event source {}", source));
+    }
+
+    Ok(Box::new(EventLogger { handle }))
+}
+
+#[cfg(not(windows))]
+pub(crate) fn event_logger(_source: &str) -> DynResult<Box<dyn Log>> {
+    Err(anyhow::Error::msg(
+        "the Windows Event Log is not supported on this platform",
+    ))
+}
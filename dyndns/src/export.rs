@@ -0,0 +1,212 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::config::{Config, ZoneConfig};
+use crate::name::DnsName;
+use crate::provider::{DnsRecords, Record};
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct ExportedRecord {
+    pub zone: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub r#type: &'static str,
+    pub ttl: u32,
+    pub values: Vec<String>,
+}
+
+pub(crate) fn managed_records(
+    config: &Config,
+    zone: &str,
+    zone_config: &ZoneConfig,
+    provider: &str,
+    current_records: &DnsRecords,
+) -> Vec<ExportedRecord> {
+    let mut records = current_records
+        .iter()
+        .filter(|record| is_managed(config, zone_config, provider, record))
+        .map(|record| ExportedRecord {
+            zone: zone.into(),
+            name: record.name().into(),
+            r#type: record.record_type(),
+            ttl: record.ttl(),
+            values: record.values(),
+        })
+        .collect::<Vec<_>>();
+
+    records.sort_by(|a, b| (&a.name, a.r#type).cmp(&(&b.name, b.r#type)));
+    records
+}
+
+fn is_managed(config: &Config, zone_config: &ZoneConfig, provider: &str, record: &Record) -> bool {
+    let name = DnsName::from(record.name());
+
+    match record {
+        Record::A { .. } => zone_config
+            .records_for(provider)
+            .any(|configured| configured.a.as_ref().map(DnsName::from) == Some(name.clone())),
+        Record::AAAA { .. } => zone_config
+            .records_for(provider)
+            .any(|configured| configured.aaaa.as_ref().map(DnsName::from) == Some(name.clone())),
+        Record::CNAME { .. } => zone_config.records_for(provider).any(|configured| {
+            configured
+                .cname
+                .as_ref()
+                .map(|cname| DnsName::from(&cname.name))
+                == Some(name.clone())
+        }),
+        Record::SRV { .. } => zone_config.records_for(provider).any(|configured| {
+            configured
+                .srv
+                .iter()
+                .any(|srv| DnsName::from(&srv.name) == name)
+        }),
+        Record::HTTPS { .. } => zone_config.records_for(provider).any(|configured| {
+            configured
+                .https
+                .iter()
+                .any(|https| DnsName::from(&https.name) == name)
+        }),
+        Record::PTR { target, .. } => {
+            let target = DnsName::from(target);
+
+            config
+                .zones
+                .values()
+                .flat_map(|zone_config| zone_config.records_for(provider))
+                .filter(|configured| configured.ptr)
+                .any(|configured| {
+                    configured
+                        .a
+                        .iter()
+                        .chain(configured.aaaa.iter())
+                        .any(|name| DnsName::from(name) == target)
+                })
+        }
+    }
+}
+
+pub(crate) fn zone_file(records: &[ExportedRecord]) -> String {
+    let mut zone_file = String::new();
+    let mut current_zone = None;
+
+    for record in records {
+        if current_zone != Some(&record.zone) {
+            if current_zone.is_some() {
+                zone_file.push('\n');
+            }
+
+            writeln!(zone_file, "; {}", record.zone).unwrap();
+            current_zone = Some(&record.zone);
+        }
+
+        for value in &record.values {
+            let value = match record.r#type {
+                "CNAME" | "PTR" => absolute(value),
+                _ => value.clone(),
+            };
+
+            writeln!(
+                zone_file,
+                "{}\t{}\tIN\t{}\t{}",
+                absolute(&record.name),
+                record.ttl,
+                record.r#type,
+                value
+            )
+            .unwrap();
+        }
+    }
+
+    zone_file
+}
+
+fn absolute(name: &str) -> String {
+    format!("{}.", DnsName::from(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_zone_file() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+zones:
+  example.com:
+    - a: home.example.com
+      aaaa: home.example.com
+    - cname: www.example.com -> home.example.com
+"#,
+        )
+        .unwrap();
+
+        let current_records = vec![
+            Record::A {
+                name: "home.example.com".into(),
+                values: vec!["203.0.113.10".parse().unwrap()],
+                ttl: 300,
+            },
+            Record::A {
+                name: "other.example.com".into(),
+                values: vec!["198.51.100.1".parse().unwrap()],
+                ttl: 300,
+            },
+            Record::CNAME {
+                name: "www.example.com".into(),
+                target: "home.example.com".into(),
+                ttl: 3600,
+            },
+        ];
+
+        let records = managed_records(
+            &config,
+            "example.com",
+            &config.zones["example.com"],
+            "gandi-livedns",
+            &current_records,
+        );
+
+        assert_eq!(
+            "; example.com\n\
+             home.example.com.\t300\tIN\tA\t203.0.113.10\n\
+             www.example.com.\t3600\tIN\tCNAME\thome.example.com.\n",
+            zone_file(&records)
+        );
+    }
+}
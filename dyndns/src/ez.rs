@@ -31,13 +31,504 @@
  *
  */
 
+use std::env;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::str::FromStr;
 
-use anyhow::Error;
+use anyhow::{Context, Error};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use log::{error, info, warn, LevelFilter};
 
-use crate::config::load_config;
-use crate::provider::DnsProvider;
+use crate::admin::{add_record, default_ttl, remove_record, set_ttl};
+use crate::api::{call, ApiConfig};
+use crate::config::{check_ttls, load_config, migrate_config, Config};
+use crate::convert::{from_ddclient, from_ddns_go, from_inadyn, Conversion};
+use crate::credentials::store_credential;
+use crate::daemon::{daemonize, drop_privileges};
+use crate::diagnose::Diagnosis;
+use crate::environment;
+use crate::error::describe;
+use crate::export::{managed_records, zone_file, ExportedRecord};
+use crate::filter::{RecordFilter, Selector};
+use crate::guard::check_mail_exchanges;
+use crate::host::set_host;
+use crate::http;
+use crate::idn::to_ascii;
+use crate::import::{imported_records, ImportedConfig};
+use crate::ip::{get_ip, ExternalIp, IpVersion};
+use crate::lint::lint_config;
+use crate::logging;
+use crate::name::DnsName;
+use crate::provider::{DnsProvider, Record, Zone};
+use crate::result::DynResult;
+use crate::rollback::{list_runs, load_runs, rollback};
+use crate::secrets::{decrypt_secrets, encrypt_secrets, secrets_key};
+use crate::service::{current_user, Service, ServiceManager};
+use crate::telemetry;
+use crate::ttl::Ttl;
+use crate::{ip_source, Updater};
+
+#[derive(Parser)]
+#[command(
+    about = "Updates DNS entries to match your external IP address",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    // The top-level form of `run`, e.g. `dyndns config.yml` or `dyndns --once config.yml`
+    #[command(flatten)]
+    run: RunArgs,
+    #[arg(long, hide = true, conflicts_with = "daemon")]
+    once: bool,
+    #[arg(long, global = true, help = "Disables colored output")]
+    no_color: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "PREFIX",
+        help = "Prefix of the environment variables, EZDD per default"
+    )]
+    env_prefix: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Keeps updating the DNS records per the configured interval")]
+    Run(RunArgs),
+    #[command(about = "Updates the DNS records once and then quits")]
+    Once(OnceArgs),
+    #[command(about = "Checks the configuration file and quits")]
+    Validate(ConfigArgs),
+    #[command(about = "Prints the configuration with all defaults applied and secrets redacted")]
+    PrintConfig(PrintConfigArgs),
+    #[command(about = "Lists the current DNS records of the configured zones")]
+    List(ConfigArgs),
+    #[command(about = "Shows the changes an update would make without applying them")]
+    Plan(UpdateArgs),
+    #[command(
+        about = "Prints the records of ZONE pointing to the external IP address as configuration"
+    )]
+    Import(ImportArgs),
+    #[command(about = "Prints the current values of the managed records as zone file or JSON")]
+    Export(ExportArgs),
+    #[command(about = "Reverts the changes of RUN, or lists the recorded runs if RUN is omitted")]
+    Rollback(RollbackArgs),
+    #[command(about = "Changes single records of the configured zones through the provider")]
+    Record {
+        #[command(subcommand)]
+        command: RecordCommand,
+    },
+    #[command(
+        about = "Prints a redacted report of the configuration and connectivity for bug reports"
+    )]
+    Diagnose(DiagnoseArgs),
+    #[command(about = "Approves or rejects a change waiting for approval in the running updater")]
+    Approve(ApproveArgs),
+    #[command(about = "Pauses updating the DNS records in the running updater")]
+    Pause(ConfigArgs),
+    #[command(about = "Resumes updating the DNS records in the running updater")]
+    Resume(ConfigArgs),
+    #[command(about = "Prints the version")]
+    Version,
+    #[command(
+        about = "Upgrades the configuration file to the latest version and writes it to OUTPUT (or stdout)"
+    )]
+    MigrateConfig {
+        #[arg(value_name = "CONFIG")]
+        config: PathBuf,
+        #[arg(value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+    },
+    #[command(
+        about = "Converts a ddclient configuration file to ez-dyndns and writes it to OUTPUT (or stdout)"
+    )]
+    ConvertFromDdclient {
+        #[arg(value_name = "CONFIG")]
+        config: PathBuf,
+        #[arg(value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+    },
+    #[command(
+        about = "Converts an inadyn configuration file to ez-dyndns and writes it to OUTPUT (or stdout)"
+    )]
+    ConvertFromInadyn {
+        #[arg(value_name = "CONFIG")]
+        config: PathBuf,
+        #[arg(value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+    },
+    #[command(
+        about = "Converts a ddns-go configuration file to ez-dyndns and writes it to OUTPUT (or stdout)"
+    )]
+    ConvertFromDdnsGo {
+        #[arg(value_name = "CONFIG")]
+        config: PathBuf,
+        #[arg(value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+    },
+    #[command(
+        about = "Reads a secret from stdin and stores it in the OS keyring under NAME, e.g. gandi-livedns/api_key"
+    )]
+    StoreCredential {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    #[command(
+        about = "Encrypts a secrets file with the passphrase in EZDD_SECRETS_KEY and writes it to OUTPUT (or stdout)"
+    )]
+    EncryptSecrets {
+        #[arg(value_name = "SECRETS")]
+        secrets: PathBuf,
+        #[arg(value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+    },
+    #[command(
+        about = "Decrypts a secrets file with the passphrase in EZDD_SECRETS_KEY and writes it to OUTPUT (or stdout)"
+    )]
+    DecryptSecrets {
+        #[arg(value_name = "SECRETS")]
+        secrets: PathBuf,
+        #[arg(value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Installs a systemd unit or OpenRC script running this executable")]
+    Install(InstallArgs),
+    #[command(about = "Prints the shell completion script for SHELL")]
+    Completions {
+        #[arg(value_name = "SHELL")]
+        shell: Shell,
+    },
+    #[command(about = "Prints the man page")]
+    Man,
+}
+
+#[derive(Args)]
+#[group(id = "config_source", required = true, multiple = false)]
+struct ConfigArgs {
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        help = "Path to the configuration file, - reads it from stdin"
+    )]
+    config: Option<PathBuf>,
+    #[arg(value_name = "CONFIG", hide = true)]
+    config_path: Option<PathBuf>,
+}
+
+impl ConfigArgs {
+    fn path(&self) -> &Path {
+        // `config` may only hold the default from the environment
+        self.config_path
+            .as_deref()
+            .or(self.config.as_deref())
+            .expect("config path is required")
+    }
+}
+
+#[derive(Args)]
+struct UpdateArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(
+        long = "ip",
+        value_name = "ADDRESS",
+        help = "Uses the given address instead of detecting it, may be specified once for IPv4 and once for IPv6"
+    )]
+    ip: Vec<IpAddr>,
+    #[arg(
+        long,
+        value_name = "SELECTOR",
+        help = "Only updates records matching tag=<tag>, zone=<zone> or record=<name>, may be specified multiple times"
+    )]
+    only: Vec<Selector>,
+    #[arg(
+        long,
+        value_name = "SELECTOR",
+        help = "Doesn't update records matching tag=<tag>, zone=<zone> or record=<name>, may be specified multiple times"
+    )]
+    exclude: Vec<Selector>,
+    #[arg(
+        long,
+        conflicts_with = "ipv6_only",
+        help = "Only detects IPv4 addresses and only updates A records"
+    )]
+    ipv4_only: bool,
+    #[arg(
+        long,
+        help = "Only detects IPv6 addresses and only updates AAAA records"
+    )]
+    ipv6_only: bool,
+    #[arg(
+        long,
+        value_name = "USER",
+        help = "Switches to USER after loading the configuration and starting the API (Unix only)"
+    )]
+    user: Option<String>,
+    #[arg(
+        long,
+        value_name = "GROUP",
+        help = "Switches to GROUP instead of the primary group of --user (Unix only)"
+    )]
+    group: Option<String>,
+}
+
+impl UpdateArgs {
+    fn filter(&self) -> RecordFilter {
+        RecordFilter {
+            only: self.only.clone(),
+            exclude: self.exclude.clone(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    #[arg(value_name = "ZONE", required = true)]
+    zones: Vec<String>,
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        help = "Configuration file providing credentials and IP address sources"
+    )]
+    config: Option<PathBuf>,
+    #[arg(
+        long = "ip",
+        value_name = "ADDRESS",
+        help = "Uses the given address instead of detecting it, may be specified once for IPv4 and once for IPv6"
+    )]
+    ip: Vec<IpAddr>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(long, value_enum, default_value = "zone-file", help = "Output format")]
+    format: ExportFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    ZoneFile,
+    Json,
+}
+
+#[derive(Args)]
+struct RollbackArgs {
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        required = true,
+        help = "Path to the configuration file"
+    )]
+    config: PathBuf,
+    #[arg(value_name = "RUN")]
+    run: Option<String>,
+    #[arg(
+        long,
+        help = "Restores the zone snapshots taken during RUN instead of reverting single records"
+    )]
+    snapshot: bool,
+}
+
+#[derive(Subcommand)]
+enum RecordCommand {
+    #[command(about = "Creates the record NAME with the given VALUES")]
+    Add(RecordAddArgs),
+    #[command(about = "Deletes the record NAME")]
+    Rm(RecordArgs),
+    #[command(about = "Changes the TTL of the record NAME")]
+    SetTtl(RecordSetTtlArgs),
+}
+
+#[derive(Args)]
+struct RecordArgs {
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        required = true,
+        help = "Path to the configuration file"
+    )]
+    config: PathBuf,
+    #[arg(value_name = "NAME")]
+    name: String,
+    #[arg(value_name = "TYPE", value_enum, ignore_case = true)]
+    record_type: RecordType,
+}
+
+#[derive(Args)]
+struct RecordAddArgs {
+    #[command(flatten)]
+    record: RecordArgs,
+    #[arg(
+        value_name = "VALUES",
+        required = true,
+        help = "IP addresses of A and AAAA records, the target of CNAME and PTR records"
+    )]
+    values: Vec<String>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "TTL of the record, defaults to the default_ttl of the zone or 300 seconds"
+    )]
+    ttl: Option<Ttl>,
+}
+
+#[derive(Args)]
+struct RecordSetTtlArgs {
+    #[command(flatten)]
+    record: RecordArgs,
+    #[arg(value_name = "SECONDS")]
+    ttl: Ttl,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+    Ptr,
+}
+
+impl RecordType {
+    fn as_str(self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Cname => "CNAME",
+            RecordType::Ptr => "PTR",
+        }
+    }
+}
+
+#[derive(Args)]
+struct DiagnoseArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Log file to include the last lines of, e.g. the --log-file of the daemon"
+    )]
+    log_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value_t = 100,
+        help = "Number of log lines to include"
+    )]
+    lines: usize,
+}
+
+#[derive(Args)]
+struct ApproveArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        help = "Token of the change, as logged and sent to the webhook"
+    )]
+    token: String,
+    #[arg(long, help = "Rejects the change instead")]
+    reject: bool,
+}
+
+#[derive(Args)]
+struct InstallArgs {
+    #[command(flatten)]
+    manager: ServiceManagerArgs,
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        required = true,
+        help = "Path to the configuration file used by the service"
+    )]
+    config: PathBuf,
+    #[arg(
+        long,
+        value_name = "USER",
+        help = "User the service runs as, defaults to the user invoking the command (or sudo)"
+    )]
+    user: Option<String>,
+    #[arg(long, help = "Prints the service file instead of installing it")]
+    print: bool,
+}
+
+#[derive(Args)]
+#[group(id = "service_manager", required = true, multiple = false)]
+struct ServiceManagerArgs {
+    #[arg(long, help = "Installs a systemd unit")]
+    systemd: bool,
+    #[arg(long, help = "Installs an OpenRC init script")]
+    openrc: bool,
+}
+
+#[derive(Args)]
+struct PrintConfigArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(long, value_enum, default_value = "yaml", help = "Output format")]
+    format: ConfigFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ConfigFormat {
+    Yaml,
+    Json,
+}
+
+#[derive(Args)]
+struct OnceArgs {
+    #[command(flatten)]
+    update: UpdateArgs,
+    #[arg(long, help = "Asks for confirmation before applying each change")]
+    interactive: bool,
+    #[arg(
+        long,
+        help = "Pushes the A and AAAA records to the provider even if they are unchanged"
+    )]
+    force: bool,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[command(flatten)]
+    update: UpdateArgs,
+    #[arg(long, help = "Forks to the background and keeps running (Unix only)")]
+    daemon: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "daemon",
+        help = "Writes the process ID of the daemon to PATH"
+    )]
+    pid_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "daemon",
+        help = "Appends the output of the daemon to PATH"
+    )]
+    log_file: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Skips the first run if the state file shows a successful update within the interval"
+    )]
+    run_on_start_only_if_stale: bool,
+}
 
 pub fn cli<F, D: DnsProvider>(name: &str, version: &str, provider: F)
 where
@@ -45,81 +536,982 @@ where
 {
     let version = &version[..version.find('.').unwrap()];
 
-    let mut pargs = pico_args::Arguments::from_env();
+    logging::init();
+
+    let args = env::args_os().collect::<Vec<_>>();
+    if let Some(prefix) = environment::prefix_arg(&args) {
+        environment::set_prefix(&prefix);
+    }
+
+    let mut command = command(name, version);
+    let matches = command
+        .try_get_matches_from_mut(args)
+        .unwrap_or_else(|err| err.exit());
+    let cli = match Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(err) => err.format(&mut command).exit(),
+    };
+
+    logging::set_color(!cli.no_color);
+
+    if let Some(level) = environment::var("LOG_LEVEL") {
+        match LevelFilter::from_str(&level) {
+            Ok(level) => log::set_max_level(level),
+            Err(_) => {
+                eprintln!(
+                    "Invalid log level in {}: {}",
+                    environment::name("LOG_LEVEL"),
+                    level
+                );
+                exit(1);
+            }
+        }
+    }
+
+    let subcommand = match cli.command {
+        Some(subcommand) => subcommand,
+        None if cli.once => Command::Once(OnceArgs {
+            update: cli.run.update,
+            interactive: false,
+            force: false,
+        }),
+        None => Command::Run(cli.run),
+    };
+
+    match subcommand {
+        Command::Run(args) => run_cli(name, args, provider),
+        Command::Once(args) => once_cli(name, args, provider),
+        Command::Validate(args) => validate_cli(args, provider),
+        Command::PrintConfig(args) => print_config_cli(args),
+        Command::List(args) => list_cli(args, provider),
+        Command::Plan(args) => plan_cli(args, provider),
+        Command::Import(args) => import_cli(args, provider),
+        Command::Export(args) => export_cli(args, provider),
+        Command::Rollback(args) => rollback_cli(args, provider),
+        Command::Record { command } => record_cli(command, provider),
+        Command::Diagnose(args) => diagnose_cli(name, version, args, provider),
+        Command::Approve(args) => approve_cli(args),
+        Command::Pause(args) => pause_cli(args, true),
+        Command::Resume(args) => pause_cli(args, false),
+        Command::Version => println!("{} r{}", name, version),
+        Command::MigrateConfig { config, output } => migrate_config_cli(config, output),
+        Command::ConvertFromDdclient { config, output } => {
+            convert_cli(config, output, from_ddclient)
+        }
+        Command::ConvertFromInadyn { config, output } => convert_cli(config, output, from_inadyn),
+        Command::ConvertFromDdnsGo { config, output } => convert_cli(config, output, from_ddns_go),
+        Command::StoreCredential { name } => store_credential_cli(&name),
+        Command::EncryptSecrets { secrets, output } => {
+            secrets_cli(secrets, output, encrypt_secrets)
+        }
+        Command::DecryptSecrets { secrets, output } => {
+            secrets_cli(secrets, output, decrypt_secrets)
+        }
+        Command::Install(args) => install_cli(name, args),
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut command, name, &mut io::stdout())
+        }
+        Command::Man => man_cli(command),
+    }
+}
+
+fn command(name: &str, version: &str) -> clap::Command {
+    with_config_env(
+        Cli::command()
+            .name(name.to_string())
+            .bin_name(name.to_string())
+            .version(format!("r{}", version)),
+    )
+}
+
+// `--config` falls back to the environment, e.g. `EZDD_CONFIG`. It's a default instead of `env`, which would count as
+// passed explicitly and conflict with the positional config.
+fn with_config_env(command: clap::Command) -> clap::Command {
+    let command = match environment::var_os("CONFIG") {
+        Some(path) if has_config_source(&command) => command
+            .mut_arg("config", |arg| arg.default_value(path))
+            .mut_group("config_source", |group| group.required(false)),
+        _ => command,
+    };
+
+    let subcommands = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect::<Vec<_>>();
+
+    subcommands
+        .into_iter()
+        .fold(command, |command, subcommand| {
+            command.mut_subcommand(subcommand, with_config_env)
+        })
+}
+
+fn has_config_source(command: &clap::Command) -> bool {
+    command
+        .get_groups()
+        .any(|group| group.get_id() == "config_source")
+}
+
+fn man_cli(command: clap::Command) {
+    let result = clap_mangen::Man::new(command)
+        .render(&mut io::stdout())
+        .context("failed to render man page");
 
-    if pargs.contains(["-h", "--help"]) {
-        print_help(name, version);
-        exit(0);
+    if let Err(err) = result {
+        eprintln!("{:?}", err);
+        exit(1);
     }
+}
 
-    if pargs.contains("--version") {
-        println!("{} r{}", name, version);
-        exit(0);
+fn run_cli<F, D: DnsProvider>(name: &str, args: RunArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    let config = load_update_config_or_exit(&args.update);
+
+    if args.run_on_start_only_if_stale && config.state_file.is_none() {
+        eprintln!("--run-on-start-only-if-stale requires state_file to be configured");
+        exit(1);
     }
 
-    let once = pargs.contains("--once");
-    let config_path = match pargs.free_from_str::<PathBuf>() {
-        Ok(path) => path,
+    if args.daemon {
+        if let Err(err) = daemonize(args.pid_file.as_deref(), args.log_file.as_deref()) {
+            eprintln!("{:?}", err);
+            exit(1);
+        }
+    }
+
+    configure_output_or_exit(&config, name);
+
+    let provider = provider();
+    check_ttls_or_exit(&config, &provider);
+
+    let updater = Updater::new(&config, &provider)
+        .with_filter(args.update.filter())
+        .with_run_on_start_only_if_stale(args.run_on_start_only_if_stale);
+
+    drop_privileges_or_exit(&updater, &args.update, true);
+    updater.run();
+}
+
+fn once_cli<F, D: DnsProvider>(name: &str, args: OnceArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    if args.interactive && args.update.config.path() == Path::new("-") {
+        eprintln!("--interactive can't be used when reading the configuration from stdin");
+        exit(1);
+    }
+
+    let config = load_update_config_or_exit(&args.update);
+
+    configure_output_or_exit(&config, name);
+
+    let provider = provider();
+    check_ttls_or_exit(&config, &provider);
+
+    let updater = Updater::new(&config, &provider)
+        .with_interactive(args.interactive)
+        .with_force(args.force)
+        .with_filter(args.update.filter());
+
+    drop_privileges_or_exit(&updater, &args.update, config.approval.is_some());
+    finish_run_once(updater.run_once())
+}
+
+fn validate_cli<F, D: DnsProvider>(args: ConfigArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    let config = load_config_or_exit(args.path());
+    let provider = provider();
+    check_ttls_or_exit(&config, &provider);
+    check_mail_exchanges_or_exit(&config, &provider);
+
+    let handled_zones = config
+        .zones
+        .values()
+        .filter(|zone_config| zone_config.is_handled_by(provider.name()))
+        .count();
+
+    let lints = lint_config(&config);
+    for lint in &lints {
+        println!("Warning: {}", lint);
+    }
+
+    println!(
+        "Configuration is valid{}, {} of {} zones are handled by {}",
+        match lints.len() {
+            0 => String::new(),
+            1 => " with 1 warning".to_string(),
+            count => format!(" with {} warnings", count),
+        },
+        handled_zones,
+        config.zones.len(),
+        provider.name()
+    );
+}
+
+fn print_config_cli(args: PrintConfigArgs) {
+    let result = load_config_or_exit(args.config.path())
+        .redacted()
+        .and_then(|config| match args.format {
+            ConfigFormat::Yaml => serde_yaml::to_string(&config).map_err(Error::from),
+            ConfigFormat::Json => serde_json::to_string_pretty(&config)
+                .map(|config| config + "\n")
+                .map_err(Error::from),
+        });
+
+    match result {
+        Ok(printed) => print!("{}", printed),
         Err(err) => {
-            eprintln!("{:?}\n", Error::from(err).context("invalid config path"));
-            print_help(name, version);
+            error!("{:?}", err);
+            exit(1);
+        }
+    }
+}
+
+fn list_cli<F, D: DnsProvider>(args: ConfigArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    let config = load_config_or_exit(args.path());
+
+    let current_zones = match provider().current(&config) {
+        Ok(current_zones) => current_zones,
+        Err(err) => {
+            error!(
+                "{:?}",
+                Error::from(err).context("failed to retrieve current DNS data")
+            );
             exit(1);
         }
     };
 
-    if once {
-        run_once(&config_path, provider())
+    let mut current_zones = current_zones.into_iter().collect::<Vec<_>>();
+    current_zones.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+
+    for (zone, records) in current_zones {
+        println!("{}", zone.name);
+
+        match records {
+            Ok(mut records) => {
+                records.sort_by_key(|record| record.to_string());
+                for record in records {
+                    println!("  {}", record);
+                }
+            }
+            Err(err) => println!("  Error: {:#}", Error::from(err)),
+        }
+    }
+}
+
+fn plan_cli<F, D: DnsProvider>(args: UpdateArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    let config = load_update_config_or_exit(&args);
+    let provider = provider();
+    check_ttls_or_exit(&config, &provider);
+
+    let updater = Updater::new(&config, &provider)
+        .with_dry_run(true)
+        .with_filter(args.filter());
+
+    drop_privileges_or_exit(&updater, &args, false);
+    if let Err(err) = updater.run_once() {
+        error!("{:?}", Error::from(err));
+        exit(1);
+    }
+}
+
+// The API may listen on a port only root can bind, so it's started before switching the user
+fn drop_privileges_or_exit<D: DnsProvider>(
+    updater: &Updater<D>,
+    args: &UpdateArgs,
+    serve_api: bool,
+) {
+    if args.user.is_none() && args.group.is_none() {
+        return;
+    }
+
+    let result = if serve_api {
+        updater.serve_api()
     } else {
-        run(&config_path, provider())
+        Ok(())
+    };
+
+    if let Err(err) = result
+        .context("failed to start the HTTP API")
+        .and_then(|_| drop_privileges(args.user.as_deref(), args.group.as_deref()))
+    {
+        eprintln!("{:?}", err);
+        exit(1);
     }
 }
 
-fn print_help(name: &str, version: &str) {
-    println!(
-        "\
-{name} r{}
-Updates DNS entries to match your external IP address
+fn import_cli<F, D: DnsProvider>(args: ImportArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    let result = parse_override_ip(&args.ip)
+        .context("invalid IP address")
+        .and_then(|override_ip| {
+            let mut config = match &args.config {
+                Some(config_path) => load_config_or_exit(config_path),
+                None => serde_yaml::from_str::<Config>("{}")?,
+            };
 
-USAGE:
-  {name} [FLAGS] <CONFIG>
+            if override_ip.is_some() {
+                config.override_ip = override_ip;
+            }
 
-FLAGS:
-  --once                Runs the DNS update once and then quits
+            let mut zones = Vec::new();
+            for zone in &args.zones {
+                let zone = to_ascii(zone)?;
+                config.zones.entry(zone.clone()).or_default();
+                zones.push(zone);
+            }
 
-  -h, --h               Prints help information
-  --version             Prints the version
+            import(&config, &provider(), &zones)
+        });
 
-ARGS:
-  <CONFIG>              Path to the configuration file
-",
-        version,
-        name = name
-    )
+    match result {
+        Ok(imported) => print!("{}", imported),
+        Err(err) => {
+            error!("{:?}", err);
+            exit(1);
+        }
+    }
 }
 
-pub fn run<D: DnsProvider, P: AsRef<Path>>(config_path: P, provider: D) {
-    let config = match load_config(config_path) {
-        Ok(config) => config,
+fn import<D: DnsProvider>(config: &Config, provider: &D, zones: &[String]) -> DynResult<String> {
+    let current_ip = get_ip(ip_source(config).as_ref(), true, true)
+        .context("failed to detect the external IP address")?;
+
+    info!("External IP address: {}", current_ip);
+
+    let mut current_zones = provider
+        .current(config)
+        .context("failed to retrieve current DNS data")?;
+
+    let mut imported = ImportedConfig::default();
+    for zone in zones {
+        let records = match current_zones.remove(&Zone::new(zone.clone())) {
+            Some(Ok(records)) => records,
+            Some(Err(err)) => {
+                return Err(Error::from(err).context(format!(
+                    "failed to retrieve current records of zone {}",
+                    zone
+                )))
+            }
+            None => return Err(Error::msg(format!("zone {} not found", zone))),
+        };
+
+        let records = imported_records(&records, &current_ip);
+        if records.is_empty() {
+            warn!("No records of zone {} point to {}", zone, current_ip);
+        }
+
+        imported.zones.insert(zone.clone(), records);
+    }
+
+    Ok(serde_yaml::to_string(&imported)?)
+}
+
+fn export_cli<F, D: DnsProvider>(args: ExportArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    let config = load_config_or_exit(args.config.path());
+
+    let result = export(&config, &provider()).and_then(|records| match args.format {
+        ExportFormat::ZoneFile => Ok(zone_file(&records)),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(&records)? + "\n"),
+    });
+
+    match result {
+        Ok(exported) => print!("{}", exported),
+        Err(err) => {
+            error!("{:?}", err);
+            exit(1);
+        }
+    }
+}
+
+fn export<D: DnsProvider>(config: &Config, provider: &D) -> DynResult<Vec<ExportedRecord>> {
+    let current_zones = provider
+        .current(config)
+        .context("failed to retrieve current DNS data")?;
+
+    let mut zones = config
+        .zones
+        .iter()
+        .filter(|(_, zone_config)| zone_config.is_handled_by(provider.name()))
+        .collect::<Vec<_>>();
+    zones.sort_by_key(|(zone, _)| DnsName::from(*zone));
+
+    let mut records = Vec::new();
+    for (zone, zone_config) in zones {
+        match current_zones.get(&Zone::new(zone.clone())) {
+            Some(Ok(current_records)) => records.extend(managed_records(
+                config,
+                zone,
+                zone_config,
+                provider.name(),
+                current_records,
+            )),
+            Some(Err(err)) => {
+                return Err(Error::msg(describe(err)).context(format!(
+                    "failed to retrieve current records of zone {}",
+                    zone
+                )))
+            }
+            None => warn!("Zone {} not found", zone),
+        }
+    }
+
+    Ok(records)
+}
+
+fn rollback_cli<F, D: DnsProvider>(args: RollbackArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    let config = load_config_or_exit(&args.config);
+
+    let result = load_runs(&config).and_then(|runs| match &args.run {
+        Some(id) => match runs.iter().find(|run| &run.id == id) {
+            Some(run) => rollback(&config, &provider(), run, args.snapshot),
+            None => Err(Error::msg(format!("run {} not found", id))),
+        },
+        None => {
+            print!("{}", list_runs(&runs));
+            Ok(())
+        }
+    });
+
+    if let Err(err) = result {
+        error!("{:?}", err);
+        exit(1);
+    }
+}
+
+fn record_cli<F, D: DnsProvider>(command: RecordCommand, provider: F)
+where
+    F: Fn() -> D,
+{
+    let record_args = match &command {
+        RecordCommand::Add(args) => &args.record,
+        RecordCommand::Rm(args) => args,
+        RecordCommand::SetTtl(args) => &args.record,
+    };
+    let config = load_config_or_exit(&record_args.config);
+    let provider = provider();
+
+    let result =
+        match command {
+            RecordCommand::Add(args) => parse_record(&config, &provider, &args)
+                .and_then(|record| add_record(&config, &provider, record)),
+            RecordCommand::Rm(args) => to_ascii(&args.name).map_err(Error::from).and_then(|name| {
+                remove_record(&config, &provider, args.record_type.as_str(), &name)
+            }),
+            RecordCommand::SetTtl(args) => to_ascii(&args.record.name)
+                .map_err(Error::from)
+                .and_then(|name| {
+                    let record_type = args.record.record_type.as_str();
+                    set_ttl(&config, &provider, record_type, &name, args.ttl)
+                }),
+        };
+
+    if let Err(err) = result {
+        error!("{:?}", err);
+        exit(1);
+    }
+}
+
+fn parse_record<D: DnsProvider>(
+    config: &Config,
+    provider: &D,
+    args: &RecordAddArgs,
+) -> DynResult<Record> {
+    let name = to_ascii(&args.record.name)?;
+
+    let ttl = match args.ttl {
+        Some(ttl) => ttl,
+        None => default_ttl(config, provider, &name)?,
+    }
+    .get();
+
+    let target = || match &args.values[..] {
+        [target] => Ok(to_ascii(target)?),
+        _ => Err(Error::msg(format!(
+            "{} records have exactly one target",
+            args.record.record_type.as_str()
+        ))),
+    };
+
+    Ok(match args.record.record_type {
+        RecordType::A => Record::A {
+            name,
+            values: parse_addresses(&args.values)?,
+            ttl,
+        },
+        RecordType::Aaaa => Record::AAAA {
+            name,
+            values: parse_addresses(&args.values)?,
+            ttl,
+        },
+        RecordType::Cname => Record::CNAME {
+            name,
+            target: target()?,
+            ttl,
+        },
+        RecordType::Ptr => Record::PTR {
+            name,
+            target: target()?,
+            ttl,
+        },
+    })
+}
+
+fn parse_addresses<T: FromStr>(values: &[String]) -> DynResult<Vec<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    values
+        .iter()
+        .map(|value| {
+            value
+                .parse()
+                .with_context(|| format!("invalid IP address {}", value))
+        })
+        .collect()
+}
+
+fn load_update_config_or_exit(args: &UpdateArgs) -> Config {
+    let override_ip = match parse_override_ip(&args.ip) {
+        Ok(override_ip) => override_ip,
+        Err(err) => {
+            eprintln!("{:?}", err.context("invalid IP address"));
+            exit(1);
+        }
+    };
+
+    let mut config = load_config_or_exit(args.config.path());
+    if override_ip.is_some() {
+        config.override_ip = override_ip;
+    }
+    if args.ipv4_only {
+        config.ip_version = IpVersion::Ipv4;
+    } else if args.ipv6_only {
+        config.ip_version = IpVersion::Ipv6;
+    }
+
+    config
+}
+
+fn configure_output_or_exit(config: &Config, name: &str) {
+    if let Some(logging_config) = &config.logging {
+        if let Err(err) = logging::configure(logging_config, name) {
+            eprintln!(
+                "{:?}",
+                Error::from(err).context("failed to configure logging")
+            );
+            exit(1);
+        }
+    }
+
+    if let Some(telemetry_config) = &config.telemetry {
+        if let Err(err) = telemetry::init(telemetry_config, name) {
+            error!(
+                "{:?}",
+                Error::from(err).context("failed to configure OpenTelemetry")
+            );
+            exit(1);
+        }
+    }
+}
+
+fn parse_override_ip(addresses: &[IpAddr]) -> DynResult<Option<ExternalIp>> {
+    if addresses.is_empty() {
+        return Ok(None);
+    }
+
+    let mut override_ip = ExternalIp::default();
+    for address in addresses {
+        match address {
+            IpAddr::V4(ipv4) if override_ip.ipv4.is_none() => override_ip.ipv4 = Some(*ipv4),
+            IpAddr::V6(ipv6) if override_ip.ipv6.is_none() => override_ip.ipv6 = Some(*ipv6),
+            _ => {
+                return Err(Error::msg(
+                    "--ip accepts at most one IPv4 and one IPv6 address",
+                ))
+            }
+        }
+    }
+
+    Ok(Some(override_ip))
+}
+
+fn migrate_config_cli(config_path: PathBuf, output_path: Option<PathBuf>) {
+    let result = migrate_config(config_path)
+        .map_err(Error::from)
+        .and_then(|migrated| match output_path {
+            Some(output_path) => {
+                fs::write(&output_path, migrated)
+                    .context("failed to write migrated config file")?;
+                println!(
+                    "Migrated configuration written to {}",
+                    output_path.display()
+                );
+                Ok(())
+            }
+            None => {
+                print!("{}", migrated);
+                Ok(())
+            }
+        });
+
+    if let Err(err) = result {
+        eprintln!("{:?}", err);
+        exit(1);
+    }
+}
+
+fn convert_cli(
+    config_path: PathBuf,
+    output_path: Option<PathBuf>,
+    convert: fn(&str) -> DynResult<Conversion>,
+) {
+    let result = fs::read_to_string(&config_path)
+        .with_context(|| format!("failed to read {}", config_path.display()))
+        .and_then(|source| {
+            let conversion = convert(&source).context("failed to parse config file")?;
+
+            for warning in &conversion.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+
+            conversion.to_yaml()
+        })
+        .and_then(|converted| match output_path {
+            Some(output_path) => {
+                fs::write(&output_path, converted)
+                    .context("failed to write converted config file")?;
+                println!(
+                    "Converted configuration written to {}",
+                    output_path.display()
+                );
+                Ok(())
+            }
+            None => {
+                print!("{}", converted);
+                Ok(())
+            }
+        });
+
+    if let Err(err) = result {
+        eprintln!("{:?}", err);
+        exit(1);
+    }
+}
+
+fn store_credential_cli(credential_name: &str) {
+    eprintln!("Enter the secret for {}:", credential_name);
+
+    let mut secret = String::new();
+    let result = io::stdin()
+        .read_line(&mut secret)
+        .context("failed to read secret")
+        .and_then(|_| store_credential(credential_name, secret.trim()).map_err(Error::from));
+
+    match result {
+        Ok(_) => println!("Stored credential {} in keyring", credential_name),
         Err(err) => {
             eprintln!("{:?}", err);
             exit(1);
         }
+    }
+}
+
+fn secrets_cli<F>(secrets_path: PathBuf, output_path: Option<PathBuf>, transform: F)
+where
+    F: Fn(&str, &str) -> DynResult<String>,
+{
+    let result = secrets_key().and_then(|passphrase| {
+        let content = fs::read_to_string(&secrets_path)
+            .with_context(|| format!("failed to read secrets file {}", secrets_path.display()))?;
+        let transformed = transform(&content, &passphrase)?;
+
+        match output_path {
+            Some(output_path) => {
+                fs::write(&output_path, transformed).context("failed to write secrets file")?;
+                println!("Secrets written to {}", output_path.display());
+            }
+            None => print!("{}", transformed),
+        }
+
+        Ok(())
+    });
+
+    if let Err(err) = result {
+        eprintln!("{:?}", err);
+        exit(1);
+    }
+}
+
+fn diagnose_cli<F, D: DnsProvider>(name: &str, version: &str, args: DiagnoseArgs, provider: F)
+where
+    F: Fn() -> D,
+{
+    // informational output would end up in the report on stdout, warnings go to stderr
+    log::set_max_level(log::LevelFilter::Warn);
+
+    let diagnosis = Diagnosis {
+        name,
+        version,
+        log_file: args.log_file.as_deref(),
+        log_lines: args.lines,
+    };
+
+    print!("{}", diagnosis.run(args.config.path(), &provider()));
+}
+
+fn approve_cli(args: ApproveArgs) {
+    let config = load_config_or_exit(args.config.path());
+    let api = api_or_exit(&config, "Changes can only be approved");
+
+    let (decision, decided) = if args.reject {
+        ("reject", "rejected")
+    } else {
+        ("approve", "approved")
+    };
+
+    let path = format!("/approvals/{}/{}", args.token, decision);
+    if let Err(err) = call(api, "POST", &path) {
+        eprintln!(
+            "{:?}",
+            err.context(format!("failed to {} the change", decision))
+        );
+        exit(1);
+    }
+
+    println!("Change {}", decided);
+}
+
+fn pause_cli(args: ConfigArgs, paused: bool) {
+    let config = load_config_or_exit(args.path());
+    let api = api_or_exit(&config, "The updater can only be paused and resumed");
+
+    let (path, done) = if paused {
+        ("/pause", "paused")
+    } else {
+        ("/resume", "resumed")
     };
 
+    if let Err(err) = call(api, "POST", path) {
+        eprintln!("{:?}", err.context("failed to reach the running updater"));
+        exit(1);
+    }
+
+    println!("Updater {}", done);
+}
+
+fn api_or_exit<'a>(config: &'a Config, action: &str) -> &'a ApiConfig {
+    match &config.api {
+        Some(api) => api,
+        None => {
+            eprintln!("{} if the api is configured", action);
+            exit(1);
+        }
+    }
+}
+
+fn install_cli(name: &str, args: InstallArgs) {
+    load_config_or_exit(&args.config);
+
+    let manager = if args.manager.systemd {
+        ServiceManager::Systemd
+    } else {
+        ServiceManager::Openrc
+    };
+
+    let result = env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("failed to determine the path of the executable")
+        .and_then(|exe| {
+            let config = fs::canonicalize(&args.config)
+                .context("failed to determine the path of the configuration file")?;
+            let user = args
+                .user
+                .or_else(current_user)
+                .context("failed to determine the current user, pass --user")?;
+
+            let service = Service {
+                name,
+                exe: &exe,
+                config: &config,
+                user: &user,
+            };
+
+            if args.print {
+                print!("{}", service.render(manager)?);
+            } else {
+                let path = service.install(manager)?;
+                println!(
+                    "Installed {}, start it with: {}",
+                    path.display(),
+                    manager.enable_command(name)
+                );
+            }
+
+            Ok(())
+        });
+
+    if let Err(err) = result {
+        error!("{:?}", err);
+        exit(1);
+    }
+}
+
+pub fn run<D: DnsProvider, P: AsRef<Path>>(config_path: P, provider: D) {
+    logging::init();
+
+    let config = load_config_or_exit(config_path);
+    check_ttls_or_exit(&config, &provider);
+
     crate::run(&config, &provider);
 }
 
 pub fn run_once<D: DnsProvider, P: AsRef<Path>>(config_path: P, provider: D) {
-    let config = match load_config(config_path) {
-        Ok(config) => config,
+    logging::init();
+
+    let config = load_config_or_exit(config_path);
+    check_ttls_or_exit(&config, &provider);
+
+    run_config_once(&config, provider)
+}
+
+fn run_config_once<D: DnsProvider>(config: &Config, provider: D) {
+    finish_run_once(crate::run_once(config, &provider))
+}
+
+fn finish_run_once(result: crate::Result<()>) {
+    telemetry::shutdown();
+
+    if let Err(err) = result {
+        error!("{:?}", Error::from(err));
+        exit(1);
+    }
+}
+
+fn load_config_or_exit<P: AsRef<Path>>(config_path: P) -> Config {
+    match load_config(config_path) {
+        Ok(config) => {
+            http::configure(config.proxy.as_deref(), config.user_agent.as_deref());
+            set_host(config.host.clone());
+            config
+        }
         Err(err) => {
-            eprintln!("{:?}", err);
+            error!("{:?}", Error::from(err));
             exit(1);
         }
-    };
+    }
+}
 
-    if let Err(err) = crate::run_once(&config, &provider) {
-        eprintln!("{:?}", err);
+fn check_ttls_or_exit<D: DnsProvider>(config: &Config, provider: &D) {
+    if let Err(err) = check_ttls(config, provider) {
+        error!("{:?}", Error::from(err));
         exit(1);
     }
 }
+
+// The same check runs before each prune, validating catches it before the first run
+fn check_mail_exchanges_or_exit<D: DnsProvider>(config: &Config, provider: &D) {
+    let mut zones = config
+        .zones
+        .iter()
+        .filter(|(_, zone_config)| zone_config.prune && zone_config.is_owned_by(provider.name()))
+        .collect::<Vec<_>>();
+    zones.sort_by_key(|(zone, _)| *zone);
+
+    let mut refused = false;
+    for (zone, zone_config) in zones {
+        if let Err(err) = check_mail_exchanges(zone, zone_config) {
+            error!("Refusing to prune zone {}: {:#}", zone, err);
+            refused = true;
+        }
+    }
+
+    if refused {
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        command("dyndns", "1").debug_assert();
+    }
+
+    fn parse(args: &[&str]) -> Cli {
+        let matches = command("dyndns", "1").try_get_matches_from(args).unwrap();
+        Cli::from_arg_matches(&matches).unwrap()
+    }
+
+    #[test]
+    fn test_legacy_invocations() {
+        let cli = parse(&["dyndns", "/tmp/c.yml"]);
+        assert!(cli.command.is_none());
+        assert!(!cli.once);
+        assert_eq!(Path::new("/tmp/c.yml"), cli.run.update.config.path());
+
+        let cli = parse(&["dyndns", "--once", "/tmp/c.yml"]);
+        assert!(cli.command.is_none());
+        assert!(cli.once);
+        assert_eq!(Path::new("/tmp/c.yml"), cli.run.update.config.path());
+
+        let cli = parse(&["dyndns", "--config", "/tmp/c.yml"]);
+        assert!(cli.command.is_none());
+        assert_eq!(Path::new("/tmp/c.yml"), cli.run.update.config.path());
+
+        let cli = parse(&["dyndns", "once", "-c", "/tmp/c.yml"]);
+        assert!(matches!(cli.command, Some(Command::Once(_))));
+
+        assert!(command("dyndns", "1")
+            .try_get_matches_from(["dyndns", "--config", "/tmp/c.yml", "--once", "--unknown"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_config_env() {
+        env::set_var(environment::name("CONFIG"), "/tmp/env.yml");
+        let env_only = parse(&["dyndns"]);
+        let positional = parse(&["dyndns", "/tmp/c.yml"]);
+        let subcommand = parse(&["dyndns", "validate"]);
+        env::remove_var(environment::name("CONFIG"));
+
+        assert_eq!(Path::new("/tmp/env.yml"), env_only.run.update.config.path());
+        assert_eq!(Path::new("/tmp/c.yml"), positional.run.update.config.path());
+        let Some(Command::Validate(args)) = subcommand.command else {
+            panic!("expected validate");
+        };
+        assert_eq!(Path::new("/tmp/env.yml"), args.path());
+    }
+
+    #[test]
+    fn test_approve_token() {
+        let matches = command("dyndns", "1")
+            .try_get_matches_from(["dyndns", "approve", "-c", "/tmp/c.yml", "--token", "abc"])
+            .unwrap();
+        let Some(Command::Approve(args)) = Cli::from_arg_matches(&matches).unwrap().command else {
+            panic!("expected approve");
+        };
+        assert_eq!("abc", args.token);
+        assert_eq!(Path::new("/tmp/c.yml"), args.config.path());
+    }
+}
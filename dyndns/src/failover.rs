@@ -0,0 +1,118 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+const DEFAULT_PORT: u16 = 443;
+const DEFAULT_TIMEOUT: u64 = 5;
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct FailoverConfig {
+    pub candidates: Vec<IpAddr>,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_timeout")]
+    pub timeout: Duration,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(DEFAULT_TIMEOUT)
+}
+
+impl FailoverConfig {
+    pub fn candidates(&self, ipv6: bool) -> impl Iterator<Item = IpAddr> + '_ {
+        self.candidates
+            .iter()
+            .copied()
+            .filter(move |candidate| candidate.is_ipv6() == ipv6)
+    }
+
+    pub fn first_healthy(&self, ipv6: bool) -> Option<IpAddr> {
+        self.candidates(ipv6)
+            .find(|candidate| self.is_healthy(*candidate))
+    }
+
+    fn is_healthy(&self, candidate: IpAddr) -> bool {
+        match TcpStream::connect_timeout(&SocketAddr::new(candidate, self.port), self.timeout) {
+            Ok(_) => true,
+            Err(err) => {
+                debug!(
+                    "Candidate {} port {} is unhealthy: {}",
+                    candidate, self.port, err
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_first_healthy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let failover = FailoverConfig {
+            candidates: vec![
+                "127.0.0.2".parse().unwrap(),
+                "::1".parse().unwrap(),
+                "127.0.0.1".parse().unwrap(),
+            ],
+            port: listener.local_addr().unwrap().port(),
+            timeout: Duration::from_secs(1),
+        };
+
+        assert_eq!(
+            Some("127.0.0.1".parse().unwrap()),
+            failover.first_healthy(false)
+        );
+
+        drop(listener);
+        assert_eq!(None, failover.first_healthy(false));
+    }
+}
@@ -0,0 +1,131 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::str::FromStr;
+
+use anyhow::Error;
+
+use crate::config::DomainRecord;
+use crate::name::DnsName;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selector {
+    Tag(String),
+    Zone(DnsName),
+    Record(DnsName),
+}
+
+impl Selector {
+    fn matches(&self, zone: &str, record: &DomainRecord) -> bool {
+        match self {
+            Selector::Tag(tag) => record.tags.iter().any(|record_tag| record_tag == tag),
+            Selector::Zone(selected_zone) => &DnsName::from(zone) == selected_zone,
+            Selector::Record(name) => record
+                .names()
+                .any(|record_name| &DnsName::from(record_name) == name),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once('=') {
+            Some(("tag", tag)) if !tag.is_empty() => Ok(Selector::Tag(tag.into())),
+            Some(("zone", zone)) if !zone.is_empty() => Ok(Selector::Zone(zone.into())),
+            Some(("record", name)) if !name.is_empty() => Ok(Selector::Record(name.into())),
+            _ => Err(Error::msg(format!(
+                "invalid selector {}, expected tag=<tag>, zone=<zone> or record=<name>",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordFilter {
+    pub only: Vec<Selector>,
+    pub exclude: Vec<Selector>,
+}
+
+impl RecordFilter {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, zone: &str, record: &DomainRecord) -> bool {
+        (self.only.is_empty()
+            || self
+                .only
+                .iter()
+                .any(|selector| selector.matches(zone, record)))
+            && !self
+                .exclude
+                .iter()
+                .any(|selector| selector.matches(zone, record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_filter() {
+        let record: DomainRecord =
+            serde_yaml::from_str("{ a: NAS.example.com, tags: [homelab, vpn] }").unwrap();
+
+        let filter = |only: &[&str], exclude: &[&str]| RecordFilter {
+            only: only
+                .iter()
+                .map(|selector| selector.parse().unwrap())
+                .collect(),
+            exclude: exclude
+                .iter()
+                .map(|selector| selector.parse().unwrap())
+                .collect(),
+        };
+
+        assert!(filter(&[], &[]).matches("example.com", &record));
+        assert!(filter(&["tag=vpn"], &[]).matches("example.com", &record));
+        assert!(filter(&["zone=Example.com."], &[]).matches("example.com", &record));
+        assert!(filter(&["record=nas.example.com"], &[]).matches("example.com", &record));
+        assert!(!filter(&["tag=media"], &[]).matches("example.com", &record));
+        assert!(!filter(&["zone=example.com"], &["tag=homelab"]).matches("example.com", &record));
+        assert!(!filter(&[], &["record=nas.example.com"]).matches("example.com", &record));
+
+        assert!("name=nas".parse::<Selector>().is_err());
+        assert!("tag=".parse::<Selector>().is_err());
+    }
+}
@@ -0,0 +1,226 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+use crate::ip::ExternalIp;
+
+const DEFAULT_HISTORY: usize = 10;
+const DEFAULT_THRESHOLD: usize = 3;
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct FlapDetectionConfig {
+    #[serde(default = "default_history")]
+    pub history: usize,
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown: Option<Duration>,
+}
+
+impl Default for FlapDetectionConfig {
+    fn default() -> Self {
+        FlapDetectionConfig {
+            history: DEFAULT_HISTORY,
+            threshold: DEFAULT_THRESHOLD,
+            cooldown: None,
+        }
+    }
+}
+
+fn default_history() -> usize {
+    DEFAULT_HISTORY
+}
+
+fn default_threshold() -> usize {
+    DEFAULT_THRESHOLD
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RunReport {
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub ip: ExternalIp,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub held_back: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Flapping(pub IpAddr, pub IpAddr);
+
+pub(crate) fn detect_flapping(reports: &[RunReport], threshold: usize) -> Vec<Flapping> {
+    let ipv4 = reports
+        .iter()
+        .filter_map(|report| report.ip.ipv4.map(IpAddr::V4))
+        .collect::<Vec<_>>();
+
+    let ipv6 = reports
+        .iter()
+        .filter_map(|report| report.ip.ipv6.map(IpAddr::V6))
+        .collect::<Vec<_>>();
+
+    [ipv4, ipv6]
+        .iter()
+        .filter_map(|addresses| flapping(addresses, threshold))
+        .collect()
+}
+
+fn flapping(addresses: &[IpAddr], threshold: usize) -> Option<Flapping> {
+    let alternations = addresses
+        .windows(3)
+        .filter(|window| window[0] == window[2] && window[0] != window[1])
+        .collect::<Vec<_>>();
+
+    if threshold == 0 || alternations.len() < threshold {
+        return None;
+    }
+
+    alternations
+        .last()
+        .map(|window| Flapping(window[1], window[2]))
+}
+
+pub(crate) fn hold_back(
+    reports: &[RunReport],
+    ip: &ExternalIp,
+    cooldown: Duration,
+    now: DateTime<Utc>,
+) -> bool {
+    let mut applied = reports.iter().rev().filter(|report| !report.held_back);
+
+    let last_applied = match applied.next() {
+        Some(last_applied) if last_applied.ip != *ip => last_applied,
+        _ => return false,
+    };
+
+    let applied_since = applied
+        .take_while(|report| report.ip == last_applied.ip)
+        .last()
+        .unwrap_or(last_applied)
+        .time;
+
+    (now - applied_since)
+        .to_std()
+        .is_ok_and(|elapsed| elapsed < cooldown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn report(minute: u32, ipv4: &str, held_back: bool) -> RunReport {
+        RunReport {
+            time: Utc.with_ymd_and_hms(2021, 11, 20, 12, minute, 0).unwrap(),
+            ip: ExternalIp {
+                ipv4: Some(ipv4.parse().unwrap()),
+                ipv6: None,
+            },
+            held_back,
+        }
+    }
+
+    #[test]
+    fn test_detect_flapping() {
+        let reports = vec![
+            report(0, "203.0.113.1", false),
+            report(5, "203.0.113.2", false),
+            report(10, "203.0.113.1", false),
+            report(15, "203.0.113.2", false),
+        ];
+
+        assert_eq!(Vec::<Flapping>::new(), detect_flapping(&reports, 3));
+        assert_eq!(
+            vec![Flapping(
+                "203.0.113.1".parse().unwrap(),
+                "203.0.113.2".parse().unwrap()
+            )],
+            detect_flapping(&reports, 2)
+        );
+
+        let reports = vec![
+            report(0, "203.0.113.1", false),
+            report(5, "203.0.113.2", false),
+            report(10, "203.0.113.3", false),
+            report(15, "203.0.113.4", false),
+        ];
+
+        assert_eq!(Vec::<Flapping>::new(), detect_flapping(&reports, 1));
+    }
+
+    #[test]
+    fn test_hold_back() {
+        let cooldown = Duration::from_secs(1800);
+        let now = Utc.with_ymd_and_hms(2021, 11, 20, 12, 20, 0).unwrap();
+
+        let reports = vec![
+            report(0, "203.0.113.1", false),
+            report(5, "203.0.113.2", false),
+            report(10, "203.0.113.2", false),
+            report(15, "203.0.113.1", true),
+        ];
+
+        assert!(hold_back(
+            &reports,
+            &report(20, "203.0.113.1", false).ip,
+            cooldown,
+            now
+        ));
+        assert!(!hold_back(
+            &reports,
+            &report(20, "203.0.113.2", false).ip,
+            cooldown,
+            now
+        ));
+        assert!(!hold_back(
+            &reports,
+            &report(20, "203.0.113.1", false).ip,
+            Duration::from_secs(600),
+            now
+        ));
+        assert!(!hold_back(
+            &[],
+            &report(20, "203.0.113.1", false).ip,
+            cooldown,
+            now
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use log::debug;
+
+use crate::config::ZoneConfig;
+use crate::name::DnsName;
+use crate::resolver::{mail_exchanges, PUBLIC_RESOLVERS};
+use crate::result::DynResult;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Pruning the address of a mail exchange silently breaks mail delivery, so each one hosted in the zone must be
+// either configured or listed in `keep`. Only the apex is looked up, subdomains receiving mail aren't known.
+pub(crate) fn check_mail_exchanges(zone: &str, zone_config: &ZoneConfig) -> DynResult<()> {
+    let exchanges = PUBLIC_RESOLVERS
+        .iter()
+        .find_map(
+            |resolver| match mail_exchanges(*resolver, zone, QUERY_TIMEOUT) {
+                Ok(exchanges) => Some(exchanges),
+                Err(err) => {
+                    debug!(
+                        "Failed to query resolver {} for MX records of {}: {:#}",
+                        resolver, zone, err
+                    );
+                    None
+                }
+            },
+        )
+        .ok_or_else(|| anyhow!("failed to look up the MX records of the zone"))?;
+
+    let unprotected = unprotected_exchanges(zone, zone_config, &exchanges);
+    if !unprotected.is_empty() {
+        bail!(
+            "mail exchange {} is neither configured nor listed in `keep`",
+            unprotected.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+fn unprotected_exchanges<'a>(
+    zone: &str,
+    zone_config: &ZoneConfig,
+    exchanges: &'a [String],
+) -> Vec<&'a str> {
+    let zone = DnsName::from(zone);
+
+    exchanges
+        .iter()
+        .filter(|exchange| DnsName::from(*exchange).is_in_zone(&zone))
+        .filter(|exchange| {
+            let exchange = DnsName::from(*exchange);
+
+            !zone_config
+                .keep
+                .iter()
+                .any(|kept| DnsName::from(kept) == exchange)
+                && !zone_config
+                    .records
+                    .iter()
+                    .any(|record| record.has_name(exchange.as_str()))
+        })
+        .map(String::as_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unprotected_exchanges() {
+        let zone_config = ZoneConfig {
+            records: vec![serde_yaml::from_str("a: mx1.example.com").unwrap()],
+            keep: vec!["MX2.example.com".into()],
+            ..Default::default()
+        };
+
+        let exchanges = vec![
+            "mx1.example.com".to_string(),
+            "mx2.example.com".to_string(),
+            "mx3.example.com".to_string(),
+            "mx.mail-provider.test".to_string(),
+        ];
+
+        assert_eq!(
+            vec!["mx3.example.com"],
+            unprotected_exchanges("example.com", &zone_config, &exchanges)
+        );
+    }
+}
@@ -0,0 +1,109 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+#[cfg(feature = "blocking")]
+use std::time::Duration;
+
+use anyhow::Context;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "blocking")]
+use crate::http::{self, handle_response};
+use crate::result::DynResult;
+
+#[cfg(feature = "blocking")]
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct HealthcheckConfig {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fail_url: Option<String>,
+}
+
+impl HealthcheckConfig {
+    fn fail_url(&self) -> String {
+        match &self.fail_url {
+            Some(fail_url) => fail_url.clone(),
+            None => format!("{}/fail", self.url.trim_end_matches('/')),
+        }
+    }
+}
+
+pub(crate) fn ping(config: &HealthcheckConfig, success: bool) {
+    let url = if success {
+        config.url.clone()
+    } else {
+        config.fail_url()
+    };
+
+    if let Err(err) = get(&url).context("failed to ping healthcheck") {
+        warn!("{:?}", err);
+    }
+}
+
+#[cfg(feature = "blocking")]
+fn get(url: &str) -> DynResult<()> {
+    handle_response(http::get(url).timeout(TIMEOUT).call())?;
+    Ok(())
+}
+
+#[cfg(not(feature = "blocking"))]
+fn get(_url: &str) -> DynResult<()> {
+    Err(anyhow::Error::msg(
+        "this executable was built without blocking HTTP support",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fail_url() {
+        let mut config = HealthcheckConfig {
+            url: "https://hc-ping.com/1234/".into(),
+            fail_url: None,
+        };
+
+        assert_eq!("https://hc-ping.com/1234/fail", config.fail_url());
+
+        config.fail_url = Some("https://kuma.example.com/api/push/abcd?status=down".into());
+
+        assert_eq!(
+            "https://kuma.example.com/api/push/abcd?status=down",
+            config.fail_url()
+        );
+    }
+}
@@ -0,0 +1,391 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use log::warn;
+
+use crate::error::{chain, describe, Error};
+use crate::result::DynResult;
+
+#[cfg(feature = "async")]
+pub mod nonblocking;
+
+const BODY_EXCERPT_LENGTH: usize = 256;
+
+const MAX_ATTEMPTS: u32 = 3;
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+const REPOSITORY_URL: &str = "https://github.com/v47-io/ez-dyndns-rs";
+
+static USER_AGENT: RwLock<Option<String>> = RwLock::new(None);
+
+#[cfg(feature = "blocking")]
+static AGENT: RwLock<Option<ureq::Agent>> = RwLock::new(None);
+
+#[derive(Debug)]
+pub struct HttpError {
+    pub url: String,
+    pub status: u16,
+    pub body: String,
+    pub retry_after: Option<Duration>,
+}
+
+impl HttpError {
+    pub fn body_excerpt(&self) -> &str {
+        let body = self.body.trim();
+
+        match body.char_indices().nth(BODY_EXCERPT_LENGTH) {
+            Some((i, _)) => &body[..i],
+            None => body,
+        }
+    }
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} responded with status {}", self.url, self.status)?;
+
+        if !self.body.trim().is_empty() {
+            write!(f, ": {}", self.body_excerpt())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl StdError for HttpError {}
+
+// Replaces the shared agent (and client) with one using the configured proxy and User-Agent
+#[cfg_attr(
+    not(any(feature = "blocking", feature = "async")),
+    allow(unused_variables)
+)]
+pub fn configure(proxy: Option<&str>, user_agent: Option<&str>) {
+    *USER_AGENT.write().unwrap() = user_agent.map(String::from);
+
+    #[cfg(feature = "blocking")]
+    {
+        *AGENT.write().unwrap() = Some(agent_builder(proxy, user_agent).build());
+    }
+
+    #[cfg(feature = "async")]
+    nonblocking::configure(proxy, user_agent);
+}
+
+// For clients outside of this layer, e.g. provider SDKs
+pub fn configured_user_agent() -> String {
+    user_agent(USER_AGENT.read().unwrap().as_deref())
+}
+
+fn user_agent(user_agent: Option<&str>) -> String {
+    user_agent.map(String::from).unwrap_or_else(|| {
+        format!(
+            "ez-dyndns/{} (+{})",
+            env!("CARGO_PKG_VERSION"),
+            REPOSITORY_URL
+        )
+    })
+}
+
+#[cfg_attr(
+    not(any(feature = "blocking", feature = "async")),
+    allow(unused_variables)
+)]
+pub(crate) fn parse_proxy(proxy: &str) -> DynResult<()> {
+    #[cfg(feature = "blocking")]
+    ureq::Proxy::new(proxy)?;
+    #[cfg(feature = "async")]
+    nonblocking::parse_proxy(proxy)?;
+
+    Ok(())
+}
+
+// Agent for requests through `proxy`, or directly without one
+#[cfg(feature = "blocking")]
+pub fn agent_builder(proxy: Option<&str>, user_agent: Option<&str>) -> ureq::AgentBuilder {
+    let builder = ureq::AgentBuilder::new().user_agent(&self::user_agent(user_agent));
+
+    match proxy.map(ureq::Proxy::new) {
+        Some(Ok(proxy)) => builder.proxy(proxy),
+        Some(Err(err)) => {
+            warn!("Ignoring invalid proxy: {}", err);
+            builder
+        }
+        None => builder,
+    }
+}
+
+// Shared by all requests through the configured proxy, so connections and TLS sessions are
+// reused across calls and runs instead of being established for every request
+#[cfg(feature = "blocking")]
+pub fn agent() -> ureq::Agent {
+    if let Some(agent) = AGENT.read().unwrap().as_ref() {
+        return agent.clone();
+    }
+
+    AGENT
+        .write()
+        .unwrap()
+        .get_or_insert_with(|| agent_builder(None, None).build())
+        .clone()
+}
+
+#[cfg(feature = "blocking")]
+pub fn request(method: &str, url: &str) -> ureq::Request {
+    agent().request(method, url)
+}
+
+#[cfg(feature = "blocking")]
+pub fn get(url: &str) -> ureq::Request {
+    agent().get(url)
+}
+
+#[cfg(feature = "blocking")]
+pub fn post(url: &str) -> ureq::Request {
+    agent().post(url)
+}
+
+#[cfg(feature = "blocking")]
+pub fn put(url: &str) -> ureq::Request {
+    agent().put(url)
+}
+
+#[cfg(feature = "blocking")]
+pub fn delete(url: &str) -> ureq::Request {
+    agent().delete(url)
+}
+
+#[cfg(feature = "blocking")]
+pub fn handle_response(
+    result: Result<ureq::Response, ureq::Error>,
+) -> crate::Result<ureq::Response> {
+    match result {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(status, response)) => {
+            let url = response.get_url().to_string();
+            let retry_after = retry_after(
+                response.header("Retry-After"),
+                response.header("X-RateLimit-Reset"),
+            );
+            let body = response.into_string().unwrap_or_default();
+
+            Err(Error::from(HttpError {
+                url,
+                status,
+                body,
+                retry_after,
+            }))
+        }
+        Err(ureq::Error::Transport(transport)) => Err(Error::from(Box::new(transport))),
+    }
+}
+
+pub fn with_retry<T, F>(mut request: F) -> crate::Result<T>
+where
+    F: FnMut() -> crate::Result<T>,
+{
+    let mut attempt = 1;
+
+    loop {
+        match request() {
+            Err(err) if attempt < MAX_ATTEMPTS => match retry_delay(&err, attempt) {
+                Some(delay) => {
+                    warn!(
+                        "{}, retrying in {} seconds",
+                        describe(&err),
+                        delay.as_secs()
+                    );
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                None => return Err(err),
+            },
+            result => return result,
+        }
+    }
+}
+
+fn retry_delay(err: &Error, attempt: u32) -> Option<Duration> {
+    let backoff = Duration::from_secs(1 << (attempt - 1));
+
+    let cause = chain(err).find_map(|err| match err.downcast_ref::<Error>() {
+        Some(err @ Error::Http(_)) => Some(err),
+        #[cfg(feature = "blocking")]
+        Some(err @ Error::Transport(_)) => Some(err),
+        #[cfg(feature = "async")]
+        Some(err @ Error::Request(_)) => Some(err),
+        _ => None,
+    });
+
+    match cause {
+        Some(Error::Http(http_error)) if http_error.status != 429 && http_error.status < 500 => {
+            None
+        }
+        Some(Error::Http(http_error)) => match http_error.retry_after {
+            Some(retry_after) if retry_after > MAX_RETRY_DELAY => None,
+            Some(retry_after) => Some(retry_after),
+            None => Some(backoff),
+        },
+        #[cfg(feature = "blocking")]
+        Some(Error::Transport(_)) => Some(backoff),
+        #[cfg(feature = "async")]
+        Some(Error::Request(_)) => Some(backoff),
+        _ => None,
+    }
+}
+
+#[cfg_attr(not(any(feature = "blocking", feature = "async")), allow(dead_code))]
+fn retry_after(retry_after: Option<&str>, rate_limit_reset: Option<&str>) -> Option<Duration> {
+    retry_after
+        .and_then(parse_retry_after)
+        .or_else(|| rate_limit_reset.and_then(parse_rate_limit_reset))
+}
+
+fn parse_rate_limit_reset(value: &str) -> Option<Duration> {
+    let value = value.trim().parse::<i64>().ok()?;
+
+    // some APIs send the number of seconds until the reset, others a UNIX timestamp
+    if value < 1_000_000_000 {
+        return Some(Duration::from_secs(value.max(0) as u64));
+    }
+
+    let reset_at = Utc.timestamp_opt(value, 0).single()?;
+    Some((reset_at - Utc::now()).to_std().unwrap_or_default())
+}
+
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    DateTime::parse_from_rfc2822(value).ok().map(|retry_at| {
+        (retry_at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_agent() {
+        assert!(user_agent(None).starts_with("ez-dyndns/"));
+        assert_eq!("my-updater/1.0", user_agent(Some("my-updater/1.0")));
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_connection_reuse() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", server.server_addr());
+
+        let handle = thread::spawn(move || {
+            (0..2)
+                .map(|_| {
+                    let request = server.recv().unwrap();
+                    let remote_addr = *request.remote_addr().unwrap();
+                    request
+                        .respond(tiny_http::Response::from_string("ok"))
+                        .unwrap();
+                    remote_addr
+                })
+                .collect::<Vec<_>>()
+        });
+
+        for _ in 0..2 {
+            assert_eq!("ok", get(&url).call().unwrap().into_string().unwrap());
+        }
+
+        let remote_addrs = handle.join().unwrap();
+        assert_eq!(remote_addrs[0], remote_addrs[1]);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(Some(Duration::from_secs(120)), parse_retry_after("120"));
+        assert_eq!(
+            Some(Duration::ZERO),
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+        assert_eq!(None, parse_retry_after("soon"));
+    }
+
+    #[test]
+    fn test_with_retry() {
+        let mut attempts = 0;
+
+        let result = with_retry(|| {
+            attempts += 1;
+
+            if attempts < 3 {
+                Err(Error::from(HttpError {
+                    url: "https://example.com".into(),
+                    status: 429,
+                    body: String::new(),
+                    retry_after: Some(Duration::ZERO),
+                }))
+            } else {
+                Ok(attempts)
+            }
+        });
+
+        assert_eq!(3, result.unwrap());
+
+        let mut attempts = 0;
+
+        let result: crate::Result<()> = with_retry(|| {
+            attempts += 1;
+
+            Err(Error::from(HttpError {
+                url: "https://example.com".into(),
+                status: 403,
+                body: String::new(),
+                retry_after: None,
+            }))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(1, attempts);
+    }
+}
@@ -0,0 +1,186 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::future::Future;
+use std::sync::RwLock;
+
+use anyhow::anyhow;
+use log::warn;
+use reqwest::{Client, RequestBuilder, Response};
+
+use super::{retry_after, retry_delay, HttpError, MAX_ATTEMPTS};
+use crate::error::{describe, Error};
+use crate::result::DynResult;
+
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks4", "socks4a", "socks5", "socks5h"];
+
+static CLIENT: RwLock<Option<Client>> = RwLock::new(None);
+
+pub(super) fn configure(proxy: Option<&str>, user_agent: Option<&str>) {
+    *CLIENT.write().unwrap() = Some(build_client(proxy, user_agent));
+}
+
+pub fn client() -> Client {
+    if let Some(client) = CLIENT.read().unwrap().as_ref() {
+        return client.clone();
+    }
+
+    CLIENT
+        .write()
+        .unwrap()
+        .get_or_insert_with(|| build_client(None, None))
+        .clone()
+}
+
+// reqwest only rejects unsupported schemes when connecting
+pub(super) fn parse_proxy(proxy: &str) -> DynResult<()> {
+    let scheme = proxy.split_once("://").map_or("http", |(scheme, _)| scheme);
+
+    if !PROXY_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) {
+        return Err(anyhow!("unsupported proxy scheme {}", scheme));
+    }
+
+    reqwest::Proxy::all(proxy)?;
+    Ok(())
+}
+
+fn build_client(proxy: Option<&str>, user_agent: Option<&str>) -> Client {
+    let builder = Client::builder().user_agent(super::user_agent(user_agent));
+
+    let builder = match proxy.map(reqwest::Proxy::all) {
+        Some(Ok(proxy)) => builder.proxy(proxy),
+        Some(Err(err)) => {
+            warn!("Ignoring invalid proxy: {}", err);
+            builder
+        }
+        None => builder,
+    };
+
+    builder.build().unwrap_or_default()
+}
+
+pub fn get(url: &str) -> RequestBuilder {
+    client().get(url)
+}
+
+pub fn post(url: &str) -> RequestBuilder {
+    client().post(url)
+}
+
+pub fn put(url: &str) -> RequestBuilder {
+    client().put(url)
+}
+
+pub fn delete(url: &str) -> RequestBuilder {
+    client().delete(url)
+}
+
+pub async fn handle_response(result: Result<Response, reqwest::Error>) -> crate::Result<Response> {
+    let response = result?;
+
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(response);
+    }
+
+    let url = response.url().to_string();
+    let retry_after = retry_after(
+        header(&response, "Retry-After"),
+        header(&response, "X-RateLimit-Reset"),
+    );
+    let body = response.text().await.unwrap_or_default();
+
+    Err(Error::from(HttpError {
+        url,
+        status: status.as_u16(),
+        body,
+        retry_after,
+    }))
+}
+
+pub async fn with_retry<T, F, Fut>(mut request: F) -> crate::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = crate::Result<T>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        match request().await {
+            Err(err) if attempt < MAX_ATTEMPTS => match retry_delay(&err, attempt) {
+                Some(delay) => {
+                    warn!(
+                        "{}, retrying in {} seconds",
+                        describe(&err),
+                        delay.as_secs()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                None => return Err(err),
+            },
+            result => return result,
+        }
+    }
+}
+
+fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response.headers().get(name)?.to_str().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn test_with_retry() {
+        let server = MockServer::start_async().await;
+
+        let unavailable = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/ip");
+                then.status(503).header("Retry-After", "0");
+            })
+            .await;
+
+        let err =
+            with_retry(|| async { handle_response(get(&server.url("/ip")).send().await).await })
+                .await
+                .unwrap_err();
+
+        assert!(matches!(err, Error::Http(HttpError { status: 503, .. })));
+        unavailable.assert_hits_async(MAX_ATTEMPTS as usize).await;
+    }
+}
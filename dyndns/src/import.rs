@@ -0,0 +1,150 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::config::{DomainRecord, DEFAULT_TTL};
+use crate::ip::ExternalIp;
+use crate::name::DnsName;
+use crate::provider::Record;
+use crate::ttl::Ttl;
+
+#[derive(Default, Serialize)]
+pub(crate) struct ImportedConfig {
+    pub zones: BTreeMap<String, Vec<DomainRecord>>,
+}
+
+pub(crate) fn imported_records(records: &[Record], current_ip: &ExternalIp) -> Vec<DomainRecord> {
+    let mut matching = records
+        .iter()
+        .filter_map(|record| match record {
+            Record::A { name, values, ttl }
+                if current_ip.ipv4.is_some_and(|ipv4| values.contains(&ipv4)) =>
+            {
+                Some((name, true, *ttl))
+            }
+            Record::AAAA { name, values, ttl }
+                if current_ip.ipv6.is_some_and(|ipv6| values.contains(&ipv6)) =>
+            {
+                Some((name, false, *ttl))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    matching.sort_by_key(|(name, is_ipv4, _)| (DnsName::from(*name).to_string(), !is_ipv4));
+
+    let mut imported: Vec<DomainRecord> = Vec::new();
+    for (name, is_ipv4, ttl) in matching {
+        let ttl = Ttl::try_from(ttl).ok().filter(|ttl| *ttl != DEFAULT_TTL);
+
+        let existing = imported.iter_mut().find(|record| {
+            record.ttl == ttl
+                && record.aaaa.is_none()
+                && !is_ipv4
+                && record
+                    .a
+                    .as_ref()
+                    .is_some_and(|a| DnsName::from(a) == DnsName::from(name))
+        });
+
+        match existing {
+            Some(record) => record.aaaa = Some(name.clone()),
+            None => imported.push(DomainRecord {
+                a: is_ipv4.then(|| name.clone()),
+                aaaa: (!is_ipv4).then(|| name.clone()),
+                cname: None,
+                srv: Vec::new(),
+                https: Vec::new(),
+                ttl,
+                ptr: false,
+                providers: Vec::new(),
+                tags: Vec::new(),
+                failover: None,
+                additional_addresses: Vec::new(),
+                value: None,
+                vars: BTreeMap::new(),
+                bridged: false,
+                bridge_token: None,
+                after: Vec::new(),
+            }),
+        }
+    }
+
+    imported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imported_records() {
+        let current_ip = ExternalIp {
+            ipv4: Some("203.0.113.10".parse().unwrap()),
+            ipv6: Some("2001:db8::10".parse().unwrap()),
+        };
+
+        let records = vec![
+            Record::AAAA {
+                name: "home.example.com".into(),
+                values: vec!["2001:db8::10".parse().unwrap()],
+                ttl: DEFAULT_TTL.get(),
+            },
+            Record::A {
+                name: "home.example.com".into(),
+                values: vec!["203.0.113.10".parse().unwrap()],
+                ttl: DEFAULT_TTL.get(),
+            },
+            Record::A {
+                name: "nas.example.com".into(),
+                values: vec!["203.0.113.10".parse().unwrap()],
+                ttl: 60,
+            },
+            Record::A {
+                name: "www.example.com".into(),
+                values: vec!["198.51.100.1".parse().unwrap()],
+                ttl: DEFAULT_TTL.get(),
+            },
+        ];
+
+        let yaml = serde_yaml::to_string(&imported_records(&records, &current_ip)).unwrap();
+
+        assert_eq!(
+            "---\n- a: home.example.com\n  aaaa: home.example.com\n- a: nas.example.com\n  ttl: 60\n",
+            yaml
+        );
+    }
+}
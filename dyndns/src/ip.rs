@@ -31,44 +31,625 @@
  *
  */
 
-use crate::result::DynResult;
-use anyhow::Context;
 use std::fmt::{Display, Formatter};
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 
-pub(crate) enum Ip {
-    V4(Ipv4Addr),
-    V6(Ipv6Addr),
+use anyhow::{anyhow, Context};
+use ipnet::IpNet;
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+#[cfg(feature = "blocking")]
+use crate::http::{self, handle_response, with_retry};
+use crate::result::DynResult;
+#[cfg(feature = "blocking")]
+use crate::tls::pinned_agent;
+use crate::tls::SpkiPin;
+
+pub trait IpSource {
+    fn ipv4(&self) -> Result<Option<Ipv4Addr>>;
+
+    fn ipv6(&self) -> Result<Option<Ipv6Addr>>;
+
+    fn all_ipv6(&self) -> Result<Vec<Ipv6Addr>> {
+        Ok(self.ipv6()?.into_iter().collect())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ExternalIp {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<Ipv4Addr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<Ipv6Addr>,
 }
 
-impl Display for Ip {
+impl Display for ExternalIp {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.ipv4, &self.ipv6) {
+            (Some(ipv4), Some(ipv6)) => write!(f, "(IPv4 {}) (IPv6 {})", ipv4, ipv6),
+            (Some(ipv4), None) => write!(f, "(IPv4 {})", ipv4),
+            (None, Some(ipv6)) => write!(f, "(IPv6 {})", ipv6),
+            (None, None) => write!(f, "(none)"),
+        }
+    }
+}
+
+// Restricts detection and updates to one address family
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpVersion {
+    #[default]
+    Both,
+    Ipv4,
+    Ipv6,
+}
+
+impl IpVersion {
+    pub fn is_default(&self) -> bool {
+        self == &IpVersion::default()
+    }
+
+    pub fn ipv4(self) -> bool {
+        self != IpVersion::Ipv6
+    }
+
+    pub fn ipv6(self) -> bool {
+        self != IpVersion::Ipv4
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct IpFilter {
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_non_public: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<IpNet>,
+}
+
+impl IpFilter {
+    pub fn is_default(&self) -> bool {
+        self == &IpFilter::default()
+    }
+
+    pub fn apply(&self, ip: ExternalIp) -> Result<ExternalIp> {
+        let filtered_ip = ExternalIp {
+            ipv4: ip.ipv4.filter(|ipv4| self.accepts(&IpAddr::V4(*ipv4))),
+            ipv6: ip.ipv6.filter(|ipv6| self.accepts(&IpAddr::V6(*ipv6))),
+        };
+
+        if filtered_ip.ipv4.is_none() && filtered_ip.ipv6.is_none() {
+            Err(Error::IpDetection(
+                "no usable external IP address detected".into(),
+            ))
+        } else {
+            Ok(filtered_ip)
+        }
+    }
+
+    pub fn accepts(&self, address: &IpAddr) -> bool {
+        if !self.allow_non_public && !is_public(address) {
+            warn!("Detected non-public address {}, skipping", address);
+            return false;
+        }
+
+        if let Some(denied) = self.deny.iter().find(|net| net.contains(address)) {
+            warn!(
+                "Detected address {} is denied by {}, skipping",
+                address, denied
+            );
+            return false;
+        }
+
+        true
+    }
+}
+
+fn is_public(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(ipv4) => {
+            let octets = ipv4.octets();
+            let is_shared = octets[0] == 100 && (octets[1] & 0xc0) == 64;
+
+            !(ipv4.is_private()
+                || is_shared
+                || ipv4.is_loopback()
+                || ipv4.is_link_local()
+                || ipv4.is_broadcast()
+                || ipv4.is_documentation()
+                || ipv4.is_unspecified())
+        }
+        IpAddr::V6(ipv6) => {
+            let first_segment = ipv6.segments()[0];
+            let is_unique_local = (first_segment & 0xfe00) == 0xfc00;
+            let is_link_local = (first_segment & 0xffc0) == 0xfe80;
+
+            !(is_unique_local || is_link_local || ipv6.is_loopback() || ipv6.is_unspecified())
+        }
+    }
+}
+
+pub struct HttpIpSource {
+    ipv4_url: String,
+    ipv6_url: String,
+    ipv4_parser: CompiledParser,
+    ipv6_parser: CompiledParser,
+    #[cfg(feature = "blocking")]
+    agent: ureq::Agent,
+}
+
+const DEFAULT_IPV4_SOURCE: &str = "https://ipv4.icanhazip.com";
+const DEFAULT_IPV6_SOURCE: &str = "https://ipv6.icanhazip.com";
+
+const MAX_EXCERPT_LENGTH: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct IpSourcesConfig {
+    #[serde(default = "default_ipv4_source")]
+    pub ipv4: String,
+    #[serde(default = "default_ipv6_source")]
+    pub ipv6: String,
+    #[serde(default, skip_serializing_if = "ResponseParser::is_plain")]
+    pub ipv4_parser: ResponseParser,
+    #[serde(default, skip_serializing_if = "ResponseParser::is_plain")]
+    pub ipv6_parser: ResponseParser,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_keys: Vec<SpkiPin>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+impl Default for IpSourcesConfig {
+    fn default() -> Self {
+        IpSourcesConfig {
+            ipv4: default_ipv4_source(),
+            ipv6: default_ipv6_source(),
+            ipv4_parser: ResponseParser::Plain,
+            ipv6_parser: ResponseParser::Plain,
+            pinned_keys: Vec::new(),
+            proxy: None,
+        }
+    }
+}
+
+// How the address is extracted from the response of an IP source
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseParser {
+    // The whole response is the address
+    #[default]
+    Plain,
+    // JSON pointer to a string, e.g. `/ip`
+    Json(String),
+    // The first capture group, or the whole match without groups
+    Regex(String),
+}
+
+impl ResponseParser {
+    pub fn is_plain(&self) -> bool {
+        *self == ResponseParser::Plain
+    }
+
+    pub(crate) fn check(&self) -> DynResult<()> {
+        match self {
+            ResponseParser::Plain => Ok(()),
+            ResponseParser::Json(pointer) if pointer.is_empty() || pointer.starts_with('/') => {
+                Ok(())
+            }
+            ResponseParser::Json(pointer) => Err(anyhow!(
+                "JSON pointer {} must be empty or start with /",
+                pointer
+            )),
+            ResponseParser::Regex(pattern) => Regex::new(pattern)
+                .map(|_| ())
+                .context(format!("invalid regex {}", pattern)),
+        }
+    }
+
+    fn compile(self) -> CompiledParser {
         match self {
-            Ip::V4(ipv4) => write!(f, "(IPv4 {})", ipv4),
-            Ip::V6(ipv6) => write!(f, "(IPv6 {})", ipv6),
+            ResponseParser::Plain => CompiledParser::Plain,
+            ResponseParser::Json(pointer) => CompiledParser::Json(pointer),
+            ResponseParser::Regex(pattern) => CompiledParser::Regex(Regex::new(&pattern)),
         }
     }
 }
 
-pub(crate) fn get_ip() -> DynResult<Ip> {
-    let raw_ip = ureq::get("https://icanhazip.com")
-        .call()
-        .context("failed to reach icanhazip.com")?
-        .into_string()
-        .context("failed to decode response")?;
+// The regex is compiled once when the source is built instead of for every response
+enum CompiledParser {
+    Plain,
+    Json(String),
+    Regex(std::result::Result<Regex, regex::Error>),
+}
+
+impl CompiledParser {
+    fn extract(&self, response: &str) -> DynResult<String> {
+        let raw_ip = match self {
+            CompiledParser::Plain => Some(response.to_string()),
+            CompiledParser::Json(pointer) => {
+                let value: serde_json::Value =
+                    serde_json::from_str(response).context("failed to parse JSON response")?;
+
+                value
+                    .pointer(pointer)
+                    .and_then(|value| value.as_str())
+                    .map(|raw_ip| raw_ip.to_string())
+            }
+            CompiledParser::Regex(regex) => {
+                let regex = regex
+                    .as_ref()
+                    .map_err(|err| anyhow!("invalid regex: {}", err))?;
+
+                regex.captures(response).and_then(|captures| {
+                    captures
+                        .get(1)
+                        .or_else(|| captures.get(0))
+                        .map(|capture| capture.as_str().to_string())
+                })
+            }
+        };
+
+        raw_ip
+            .map(|raw_ip| raw_ip.trim().to_string())
+            .ok_or_else(|| anyhow!("no address found in response: {}", excerpt(response)))
+    }
+}
+
+fn excerpt(response: &str) -> String {
+    let response = response.trim();
+
+    match response.char_indices().nth(MAX_EXCERPT_LENGTH) {
+        Some((end, _)) => format!("{}...", &response[..end]),
+        None => response.to_string(),
+    }
+}
+
+fn default_ipv4_source() -> String {
+    DEFAULT_IPV4_SOURCE.into()
+}
 
-    let trimmed_ip = raw_ip.trim();
+fn default_ipv6_source() -> String {
+    DEFAULT_IPV6_SOURCE.into()
+}
+
+impl Default for HttpIpSource {
+    fn default() -> Self {
+        HttpIpSource::new(DEFAULT_IPV4_SOURCE, DEFAULT_IPV6_SOURCE)
+    }
+}
+
+impl HttpIpSource {
+    #[cfg(feature = "blocking")]
+    pub fn from_config(config: &IpSourcesConfig, user_agent: Option<&str>) -> Self {
+        let source = HttpIpSource::new(&config.ipv4, &config.ipv6)
+            .with_parsers(config.ipv4_parser.clone(), config.ipv6_parser.clone());
+
+        let proxy = config.proxy.as_deref();
+        let agent = if config.pinned_keys.is_empty() {
+            http::agent_builder(proxy, user_agent).build()
+        } else {
+            pinned_agent(&config.pinned_keys, proxy, user_agent)
+        };
+
+        HttpIpSource { agent, ..source }
+    }
+
+    #[cfg(not(feature = "blocking"))]
+    pub fn from_config(config: &IpSourcesConfig, _user_agent: Option<&str>) -> Self {
+        HttpIpSource::new(&config.ipv4, &config.ipv6)
+            .with_parsers(config.ipv4_parser.clone(), config.ipv6_parser.clone())
+    }
 
-    Ok(if trimmed_ip.contains(':') {
-        Ip::V6(
-            Ipv6Addr::from_str(trimmed_ip)
-                .context(format!("failed to parse IPv6: {}", trimmed_ip))?,
-        )
+    pub fn new<S: Into<String>>(ipv4_url: S, ipv6_url: S) -> Self {
+        HttpIpSource {
+            ipv4_url: ipv4_url.into(),
+            ipv6_url: ipv6_url.into(),
+            ipv4_parser: CompiledParser::Plain,
+            ipv6_parser: CompiledParser::Plain,
+            #[cfg(feature = "blocking")]
+            agent: http::agent_builder(None, None).build(),
+        }
+    }
+
+    pub fn with_parsers(self, ipv4_parser: ResponseParser, ipv6_parser: ResponseParser) -> Self {
+        HttpIpSource {
+            ipv4_parser: ipv4_parser.compile(),
+            ipv6_parser: ipv6_parser.compile(),
+            ..self
+        }
+    }
+
+    fn fetch(&self, url: &str, parser: &CompiledParser) -> DynResult<String> {
+        let response = self.get(url).context(format!("failed to reach {}", url))?;
+
+        parser.extract(&response)
+    }
+
+    #[cfg(feature = "blocking")]
+    fn get(&self, url: &str) -> DynResult<String> {
+        with_retry(|| handle_response(self.agent.get(url).call()))?
+            .into_string()
+            .context("failed to decode response")
+    }
+
+    // The IP sources are queried from synchronous code, which needs the blocking client
+    #[cfg(not(feature = "blocking"))]
+    fn get(&self, _url: &str) -> DynResult<String> {
+        Err(anyhow!(
+            "this executable was built without blocking HTTP support"
+        ))
+    }
+}
+
+impl IpSource for HttpIpSource {
+    fn ipv4(&self) -> Result<Option<Ipv4Addr>> {
+        self.fetch(&self.ipv4_url, &self.ipv4_parser)
+            .and_then(|raw_ip| {
+                Ipv4Addr::from_str(&raw_ip)
+                    .map(Some)
+                    .context(format!("failed to parse IPv4: {}", excerpt(&raw_ip)))
+            })
+            .map_err(ip_detection_error)
+    }
+
+    fn ipv6(&self) -> Result<Option<Ipv6Addr>> {
+        self.fetch(&self.ipv6_url, &self.ipv6_parser)
+            .and_then(|raw_ip| {
+                Ipv6Addr::from_str(&raw_ip)
+                    .map(Some)
+                    .context(format!("failed to parse IPv6: {}", excerpt(&raw_ip)))
+            })
+            .map_err(ip_detection_error)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct InterfaceConfig {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub all_ipv6: bool,
+}
+
+pub struct InterfaceIpSource {
+    interface: String,
+}
+
+impl InterfaceIpSource {
+    pub fn new<S: Into<String>>(interface: S) -> Self {
+        InterfaceIpSource {
+            interface: interface.into(),
+        }
+    }
+
+    fn addresses(&self) -> DynResult<Vec<IpAddr>> {
+        let addresses = if_addrs::get_if_addrs()
+            .context("failed to list network interfaces")?
+            .into_iter()
+            .filter(|interface| interface.name == self.interface)
+            .map(|interface| interface.ip())
+            .collect::<Vec<_>>();
+
+        if addresses.is_empty() {
+            Err(anyhow::Error::msg(format!(
+                "no addresses found on interface {}",
+                self.interface
+            )))
+        } else {
+            Ok(addresses)
+        }
+    }
+}
+
+impl IpSource for InterfaceIpSource {
+    fn ipv4(&self) -> Result<Option<Ipv4Addr>> {
+        let addresses = self
+            .addresses()
+            .map_err(ip_detection_error)?
+            .into_iter()
+            .filter_map(|address| match address {
+                IpAddr::V4(ipv4) => Some(ipv4),
+                IpAddr::V6(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(addresses
+            .iter()
+            .find(|ipv4| is_public(&IpAddr::V4(**ipv4)))
+            .or_else(|| addresses.first())
+            .copied())
+    }
+
+    fn ipv6(&self) -> Result<Option<Ipv6Addr>> {
+        let addresses = self
+            .addresses()
+            .map_err(ip_detection_error)?
+            .into_iter()
+            .filter_map(|address| match address {
+                IpAddr::V4(_) => None,
+                IpAddr::V6(ipv6) => Some(ipv6),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(addresses
+            .iter()
+            .find(|ipv6| is_public(&IpAddr::V6(**ipv6)))
+            .or_else(|| addresses.first())
+            .copied())
+    }
+
+    fn all_ipv6(&self) -> Result<Vec<Ipv6Addr>> {
+        Ok(self
+            .addresses()
+            .map_err(ip_detection_error)?
+            .into_iter()
+            .filter(is_public)
+            .filter_map(|address| match address {
+                IpAddr::V4(_) => None,
+                IpAddr::V6(ipv6) => Some(ipv6),
+            })
+            .collect())
+    }
+}
+
+pub struct StaticIpSource(pub ExternalIp);
+
+impl IpSource for StaticIpSource {
+    fn ipv4(&self) -> Result<Option<Ipv4Addr>> {
+        Ok(self.0.ipv4)
+    }
+
+    fn ipv6(&self) -> Result<Option<Ipv6Addr>> {
+        Ok(self.0.ipv6)
+    }
+}
+
+pub fn reverse_name(address: &IpAddr) -> String {
+    match address {
+        IpAddr::V4(ipv4) => {
+            let [a, b, c, d] = ipv4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(ipv6) => {
+            let nibbles = ipv6
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|octet| [octet & 0x0f, octet >> 4])
+                .map(|nibble| format!("{:x}", nibble))
+                .collect::<Vec<_>>();
+
+            format!("{}.ip6.arpa", nibbles.join("."))
+        }
+    }
+}
+
+pub fn get_ip<S: IpSource + ?Sized>(source: &S, ipv4: bool, ipv6: bool) -> Result<ExternalIp> {
+    let mut errors = Vec::new();
+
+    let ipv4 = if ipv4 {
+        source.ipv4().unwrap_or_else(|err| {
+            errors.push(err);
+            None
+        })
     } else {
-        Ip::V4(
-            Ipv4Addr::from_str(trimmed_ip)
-                .context(format!("failed to parse IPv4: {}", trimmed_ip))?,
-        )
-    })
+        None
+    };
+
+    let ipv6 = if ipv6 {
+        source.ipv6().unwrap_or_else(|err| {
+            errors.push(err);
+            None
+        })
+    } else {
+        None
+    };
+
+    if ipv4.is_none() && ipv6.is_none() {
+        return Err(errors
+            .pop()
+            .unwrap_or_else(|| Error::IpDetection("no external IP address detected".into())));
+    }
+
+    errors
+        .into_iter()
+        .for_each(|err| warn!("{:#}", anyhow::Error::from(err)));
+
+    Ok(ExternalIp { ipv4, ipv6 })
+}
+
+fn ip_detection_error(err: anyhow::Error) -> Error {
+    Error::IpDetection(err.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ipv4OnlySource;
+
+    impl IpSource for Ipv4OnlySource {
+        fn ipv4(&self) -> Result<Option<Ipv4Addr>> {
+            Ok(Some(Ipv4Addr::new(192, 0, 2, 1)))
+        }
+
+        fn ipv6(&self) -> Result<Option<Ipv6Addr>> {
+            Err(Error::IpDetection("no IPv6 connectivity".into()))
+        }
+    }
+
+    #[test]
+    fn test_get_ip() {
+        assert_eq!(
+            ExternalIp {
+                ipv4: Some(Ipv4Addr::new(192, 0, 2, 1)),
+                ipv6: None
+            },
+            get_ip(&Ipv4OnlySource, true, true).unwrap()
+        );
+
+        assert!(get_ip(&Ipv4OnlySource, false, true).is_err());
+    }
+
+    #[test]
+    fn test_ip_filter() {
+        let filter = IpFilter {
+            allow_non_public: false,
+            deny: vec![IpNet::from_str("2001:db8:1::/48").unwrap()],
+        };
+
+        let ip = ExternalIp {
+            ipv4: Some(Ipv4Addr::new(100, 64, 12, 1)),
+            ipv6: Some(Ipv6Addr::from_str("2001:db8:2::1").unwrap()),
+        };
+
+        assert_eq!(
+            ExternalIp {
+                ipv4: None,
+                ipv6: ip.ipv6
+            },
+            filter.apply(ip).unwrap()
+        );
+
+        let ip = ExternalIp {
+            ipv4: Some(Ipv4Addr::new(192, 168, 1, 1)),
+            ipv6: Some(Ipv6Addr::from_str("2001:db8:1::1").unwrap()),
+        };
+
+        assert!(filter.apply(ip).is_err());
+    }
+
+    #[test]
+    fn test_extract_address() {
+        let parser = ResponseParser::Regex(r"Current IP Address: ([0-9.]+)".into()).compile();
+        assert_eq!(
+            "192.0.2.1",
+            parser
+                .extract("<body>Current IP Address: 192.0.2.1</body>")
+                .unwrap()
+        );
+
+        let err = parser
+            .extract(&"<p>maintenance</p>".repeat(50))
+            .unwrap_err();
+        assert!(err.to_string().ends_with("<p>mainten..."));
+        assert!(err.to_string().len() < 150);
+    }
+
+    #[test]
+    fn test_reverse_name() {
+        assert_eq!(
+            reverse_name(&IpAddr::V4(Ipv4Addr::new(203, 0, 113, 10))),
+            "10.113.0.203.in-addr.arpa"
+        );
+
+        assert_eq!(
+            reverse_name(&IpAddr::V6(
+                Ipv6Addr::from_str("2001:db8::567:89ab").unwrap()
+            )),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
 }
@@ -31,33 +31,301 @@
  *
  */
 
-use std::time::Instant;
+use std::mem;
+use std::ops::ControlFlow;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use log::info;
 
 use crate::config::Config;
 
-pub(crate) fn start_job<F>(config: &Config, job: F)
+// Long sleeps are split up so a suspend/resume is noticed soon after waking up
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+// Differences between the wall clock and the monotonic clock above this are treated as time jumps
+const TIME_JUMP_THRESHOLD: Duration = Duration::from_secs(30);
+
+trait Clock {
+    fn now(&self) -> Instant;
+
+    fn system_time(&self) -> SystemTime;
+}
+
+pub(crate) trait Sleeper {
+    // Returns true if woken up early to run the job right away
+    fn sleep(&self, duration: Duration) -> bool;
+}
+
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// Sleeps until the interval passed or it's woken up from another thread, e.g. after a signal
+#[derive(Default)]
+pub(crate) struct Wakeup {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Wakeup {
+    pub(crate) fn wake(&self) {
+        *self.woken.lock().unwrap() = true;
+        self.condvar.notify_all();
+    }
+}
+
+impl Sleeper for Wakeup {
+    fn sleep(&self, duration: Duration) -> bool {
+        let (mut woken, _) = self
+            .condvar
+            .wait_timeout_while(self.woken.lock().unwrap(), duration, |woken| !*woken)
+            .unwrap();
+
+        mem::take(&mut *woken)
+    }
+}
+
+pub(crate) fn start_job<S, F>(config: &Config, initial_delay: Option<Duration>, sleeper: &S, job: F)
 where
+    S: Sleeper,
     F: Fn(),
 {
-    job();
+    schedule(
+        &SystemClock,
+        sleeper,
+        config.interval,
+        initial_delay,
+        || {
+            job();
+            ControlFlow::Continue(())
+        },
+    );
+}
+
+fn schedule<C, S, F>(
+    clock: &C,
+    sleeper: &S,
+    interval: Duration,
+    initial_delay: Option<Duration>,
+    mut job: F,
+) where
+    C: Clock,
+    S: Sleeper,
+    F: FnMut() -> ControlFlow<()>,
+{
+    if let Some(initial_delay) = initial_delay {
+        sleeper.sleep(initial_delay);
+    }
+
+    let mut last_run_at = clock.now();
+    let mut last_check = (clock.now(), clock.system_time());
 
-    let mut next_sleep = config.interval;
-    let mut last_sleep_at = Instant::now();
+    if job().is_break() {
+        return;
+    }
 
     loop {
-        std::thread::sleep(next_sleep);
+        let remaining = interval.saturating_sub(clock.now() - last_run_at);
+        let triggered = sleeper.sleep(remaining.min(MAX_SLEEP));
 
-        let after_sleep = Instant::now();
-        let total_sleep_dur = after_sleep - last_sleep_at;
+        let check = (clock.now(), clock.system_time());
+        let time_jumped = is_time_jump(last_check, check);
+        last_check = check;
 
-        if total_sleep_dur < config.interval {
-            next_sleep = config.interval - total_sleep_dur;
+        if triggered {
+            info!("Updating now as requested");
+        } else if time_jumped {
+            info!("Detected a time jump, e.g. after resuming from suspend: Updating now");
+        } else if check.0 - last_run_at < interval {
             continue;
         }
 
-        last_sleep_at = after_sleep;
-        next_sleep = config.interval;
+        last_run_at = check.0;
+
+        if job().is_break() {
+            return;
+        }
+    }
+}
+
+fn is_time_jump(
+    (previous_instant, previous_time): (Instant, SystemTime),
+    (instant, time): (Instant, SystemTime),
+) -> bool {
+    let monotonic = instant - previous_instant;
+
+    match time.duration_since(previous_time) {
+        Ok(wall) => wall.abs_diff(monotonic) > TIME_JUMP_THRESHOLD,
+        Err(err) => err.duration() + monotonic > TIME_JUMP_THRESHOLD,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::{Cell, RefCell};
+
+    const INTERVAL: Duration = Duration::from_secs(300);
+
+    // Monotonic and wall clock time advance together while sleeping, unless a suspend is pending
+    struct VirtualTime {
+        start: Instant,
+        elapsed: Cell<Duration>,
+        time: Cell<SystemTime>,
+        sleep_factor: f64,
+        oversleep: Duration,
+        suspends: RefCell<Vec<(Duration, Duration)>>,
+    }
+
+    impl VirtualTime {
+        fn new() -> VirtualTime {
+            VirtualTime {
+                start: Instant::now(),
+                elapsed: Cell::new(Duration::ZERO),
+                time: Cell::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+                sleep_factor: 1.0,
+                oversleep: Duration::ZERO,
+                suspends: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn advance(&self, duration: Duration) {
+            self.elapsed.set(self.elapsed.get() + duration);
+            self.time.set(self.time.get() + duration);
+        }
+
+        fn run_times(&self, runs: usize, job_duration: Duration) -> Vec<u64> {
+            let mut run_times = Vec::new();
+
+            schedule(self, self, INTERVAL, None, || {
+                run_times.push(self.elapsed.get().as_secs());
+                self.advance(job_duration);
+
+                if run_times.len() < runs {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            });
+
+            run_times
+        }
+    }
+
+    impl Clock for VirtualTime {
+        fn now(&self) -> Instant {
+            self.start + self.elapsed.get()
+        }
+
+        fn system_time(&self) -> SystemTime {
+            self.time.get()
+        }
+    }
+
+    impl Sleeper for VirtualTime {
+        fn sleep(&self, duration: Duration) -> bool {
+            self.advance(duration.mul_f64(self.sleep_factor) + self.oversleep);
+
+            let elapsed = self.elapsed.get();
+            self.suspends.borrow_mut().retain(|(at, suspend)| {
+                if *at <= elapsed {
+                    self.time.set(self.time.get() + *suspend);
+                    false
+                } else {
+                    true
+                }
+            });
+
+            false
+        }
+    }
+
+    #[test]
+    fn test_interval_without_drift() {
+        let time = VirtualTime::new();
+
+        assert_eq!(
+            vec![0, 300, 600, 900],
+            time.run_times(4, Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn test_oversleep_correction() {
+        let time = VirtualTime {
+            oversleep: Duration::from_secs(2),
+            ..VirtualTime::new()
+        };
+
+        let run_times = time.run_times(4, Duration::ZERO);
+        for gap in run_times.windows(2).map(|runs| runs[1] - runs[0]) {
+            assert!(gap >= INTERVAL.as_secs());
+            assert!(gap < INTERVAL.as_secs() + 5);
+        }
+    }
+
+    #[test]
+    fn test_wakeup() {
+        let wakeup = Wakeup::default();
+        assert!(!wakeup.sleep(Duration::from_millis(10)));
+
+        wakeup.wake();
+        assert!(wakeup.sleep(Duration::from_secs(60)));
+        assert!(!wakeup.sleep(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_early_wakeup() {
+        let time = VirtualTime {
+            sleep_factor: 0.5,
+            ..VirtualTime::new()
+        };
+
+        let run_times = time.run_times(3, Duration::ZERO);
+        for gap in run_times.windows(2).map(|runs| runs[1] - runs[0]) {
+            assert!(gap >= INTERVAL.as_secs());
+            assert!(gap < INTERVAL.as_secs() + 5);
+        }
+    }
+
+    #[test]
+    fn test_time_jump() {
+        let time = VirtualTime::new();
+        time.suspends.borrow_mut().extend([
+            // suspended for three hours after 100 seconds
+            (Duration::from_secs(100), Duration::from_secs(3 * 3600)),
+            // a small clock adjustment is ignored
+            (Duration::from_secs(200), Duration::from_secs(5)),
+        ]);
+
+        assert_eq!(vec![0, 120, 420], time.run_times(3, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_initial_delay() {
+        let time = VirtualTime::new();
+        let mut run_times = Vec::new();
+
+        schedule(
+            &time,
+            &time,
+            INTERVAL,
+            Some(Duration::from_secs(42)),
+            || {
+                run_times.push(time.elapsed.get().as_secs());
+                ControlFlow::Break(())
+            },
+        );
 
-        job();
+        assert_eq!(vec![42], run_times);
     }
 }
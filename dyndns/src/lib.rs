@@ -32,14 +32,58 @@
  */
 
 pub use crate::dyndns::*;
-pub use anyhow;
-pub use pico_args;
+pub use crate::error::{describe, Error, ProviderErrorKind, Result, Source};
+#[doc(hidden)]
+pub use log;
+#[doc(hidden)]
+pub use serde_yaml;
+#[doc(hidden)]
+#[cfg(feature = "blocking")]
 pub use ureq;
 
+mod admin;
+pub mod api;
+pub mod approval;
 pub mod config;
+mod convert;
+pub mod credentials;
+mod daemon;
+mod diagnose;
+mod diff;
+mod dnssec;
+pub mod drift;
 mod dyndns;
+pub mod environment;
+mod error;
+mod eventlog;
+mod export;
 pub mod ez;
-mod ip;
+pub mod failover;
+pub mod filter;
+pub mod flap;
+mod guard;
+pub mod healthcheck;
+mod host;
+#[doc(hidden)]
+pub mod http;
+mod idn;
+mod import;
+pub mod ip;
 mod job;
+mod lint;
+pub mod logging;
+pub mod name;
+pub mod prelude;
 pub mod provider;
-pub mod result;
+mod resolver;
+mod result;
+mod rollback;
+mod secrets;
+mod service;
+mod state;
+mod summary;
+pub mod telemetry;
+mod template;
+pub mod tls;
+pub mod ttl;
+pub mod upnp;
@@ -0,0 +1,139 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+use std::collections::BTreeMap;
+
+use crate::config::{Config, DomainRecord};
+use crate::name::DnsName;
+use crate::ttl::Ttl;
+
+// Above this TTL resolvers keep serving a stale address for too long after a change
+const MAX_DYNAMIC_TTL: Ttl = Ttl::from_secs(3600);
+
+// Best-practice warnings for a valid configuration, reported by `validate`
+pub(crate) fn lint_config(config: &Config) -> Vec<String> {
+    let zones = config.zones.iter().collect::<BTreeMap<_, _>>();
+
+    let mut lints = Vec::new();
+    for (zone, zone_config) in zones {
+        let dynamic_records = zone_config
+            .records
+            .iter()
+            .filter(|record| is_dynamic(record))
+            .collect::<Vec<_>>();
+
+        for record in &dynamic_records {
+            let names = record.names().collect::<Vec<_>>().join(", ");
+            let ttl = record.ttl();
+
+            if ttl > MAX_DYNAMIC_TTL {
+                lints.push(format!(
+                    "record {} has a TTL of {} seconds, resolvers may serve its old address for that long \
+                     after a change (at most {} seconds recommended)",
+                    names, ttl, MAX_DYNAMIC_TTL
+                ));
+            } else if u64::from(ttl.get()) > config.interval.as_secs() {
+                lints.push(format!(
+                    "record {} has a TTL of {} seconds, longer than the interval of {} seconds",
+                    names,
+                    ttl,
+                    config.interval.as_secs()
+                ));
+            }
+        }
+
+        let apex = DnsName::from(zone);
+        let wildcard = DnsName::from(format!("*.{}", zone).as_str());
+        let has_name = |name: &DnsName| {
+            dynamic_records
+                .iter()
+                .any(|record| record.names().any(|own| DnsName::from(own) == *name))
+        };
+
+        if has_name(&apex) && has_name(&wildcard) {
+            lints.push(format!(
+                "zone {} has both a wildcard and an apex record, consider a CNAME from {} to {} instead",
+                zone, wildcard, apex
+            ));
+        }
+    }
+
+    lints
+}
+
+fn is_dynamic(record: &DomainRecord) -> bool {
+    record.a.is_some() || record.aaaa.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::load_config;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_lint_config() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            r#"---
+interval: 600
+zones:
+  example.com:
+    - a: example.com
+    - a: '*.example.com'
+    - a: home.example.com
+      ttl: 7200
+    - aaaa: nas.example.com
+      ttl: 900
+    - cname: www.example.com -> home.example.com
+      ttl: 86400
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let config = load_config(file).unwrap();
+
+        assert_eq!(
+            vec![
+                "record home.example.com has a TTL of 7200 seconds, resolvers may serve its old address for that \
+                 long after a change (at most 3600 seconds recommended)",
+                "record nas.example.com has a TTL of 900 seconds, longer than the interval of 600 seconds",
+                "zone example.com has both a wildcard and an apex record, consider a CNAME from *.example.com to \
+                 example.com instead",
+            ],
+            lint_config(&config)
+        );
+    }
+}
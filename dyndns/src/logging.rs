@@ -0,0 +1,188 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+use anyhow::Error;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use syslog::{BasicLogger, Facility, Formatter3164};
+
+use crate::eventlog::event_logger;
+use crate::host::host;
+use crate::result::DynResult;
+
+const DEFAULT_FACILITY: &str = "daemon";
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub backend: LogBackend,
+    #[serde(default = "default_facility")]
+    pub facility: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+fn default_facility() -> String {
+    DEFAULT_FACILITY.into()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogBackend {
+    #[default]
+    Console,
+    Syslog,
+    EventLog,
+}
+
+struct Logger {
+    backend: RwLock<Option<Box<dyn Log>>>,
+}
+
+static LOGGER: Logger = Logger {
+    backend: RwLock::new(None),
+};
+
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        match self.backend.read().unwrap().as_ref() {
+            Some(backend) => backend.log(record),
+            None => match record.level() {
+                Level::Error | Level::Warn => eprintln!("{}", record.args()),
+                _ => println!("{}", record.args()),
+            },
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(backend) = self.backend.read().unwrap().as_ref() {
+            backend.flush()
+        }
+    }
+}
+
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+// UTC, so the logs of machines in different time zones line up
+pub(crate) fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+        && env::var_os("NO_COLOR").is_none()
+        && LOGGER.backend.read().unwrap().is_none()
+        && io::stdout().is_terminal()
+}
+
+pub fn configure(config: &LoggingConfig, process: &str) -> crate::Result<()> {
+    let backend: Option<Box<dyn Log>> = match config.backend {
+        LogBackend::Console => None,
+        LogBackend::Syslog => Some(Box::new(
+            syslog_logger(config, process).map_err(|err| crate::Error::Config(err.into()))?,
+        )),
+        LogBackend::EventLog => Some(
+            event_logger(config.source.as_deref().unwrap_or(process))
+                .map_err(|err| crate::Error::Config(err.into()))?,
+        ),
+    };
+
+    *LOGGER.backend.write().unwrap() = backend;
+
+    Ok(())
+}
+
+fn syslog_logger(config: &LoggingConfig, process: &str) -> DynResult<BasicLogger> {
+    let facility = Facility::from_str(&config.facility)
+        .map_err(|_| Error::msg(format!("unknown syslog facility {}", config.facility)))?;
+
+    let formatter = Formatter3164 {
+        facility,
+        hostname: None,
+        process: process.into(),
+        pid: std::process::id(),
+    };
+
+    let logger = match config.server.as_deref() {
+        None => syslog::unix(formatter),
+        Some(server) => {
+            let formatter = Formatter3164 {
+                hostname: Some(host()),
+                ..formatter
+            };
+
+            match server.split_once("://") {
+                Some(("tcp", address)) => syslog::tcp(formatter, address),
+                Some(("udp", address)) => syslog::udp(formatter, "0.0.0.0:0", address),
+                None => syslog::udp(formatter, "0.0.0.0:0", server),
+                Some((protocol, _)) => {
+                    return Err(Error::msg(format!(
+                        "unsupported syslog protocol {}",
+                        protocol
+                    )))
+                }
+            }
+        }
+    };
+
+    logger
+        .map(BasicLogger::new)
+        .map_err(|err| Error::msg(format!("failed to connect to syslog: {}", err)))
+}
@@ -0,0 +1,141 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::fmt::{Display, Formatter};
+
+use crate::idn::normalize;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DnsName(String);
+
+impl DnsName {
+    pub fn new(name: &str) -> DnsName {
+        DnsName(normalize(name.trim_end_matches('.')))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_in_zone(&self, zone: &DnsName) -> bool {
+        self == zone || self.0.ends_with(&format!(".{}", zone.0))
+    }
+
+    pub fn strip_zone(&self, zone: &DnsName) -> Option<&str> {
+        if self == zone {
+            Some("")
+        } else {
+            self.0.strip_suffix(&format!(".{}", zone.0))
+        }
+    }
+}
+
+impl From<&str> for DnsName {
+    fn from(name: &str) -> Self {
+        DnsName::new(name)
+    }
+}
+
+impl From<&String> for DnsName {
+    fn from(name: &String) -> Self {
+        DnsName::new(name)
+    }
+}
+
+impl Display for DnsName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    const DOMAIN: &str = "[a-z0-9]([a-z0-9-]{0,8}[a-z0-9])?(\\.[a-z0-9]{1,10}){0,3}";
+
+    #[test]
+    fn test_dns_name() {
+        assert_eq!(
+            DnsName::from("HOME.Example.COM."),
+            "home.example.com".into()
+        );
+        assert!(DnsName::from("home.example.com").is_in_zone(&"Example.com.".into()));
+        assert!(!DnsName::from("home.example.com").is_in_zone(&"ample.com".into()));
+        assert_eq!(
+            Some("home"),
+            DnsName::from("HOME.example.com.").strip_zone(&"example.com".into())
+        );
+        assert_eq!(
+            Some(""),
+            DnsName::from("example.com").strip_zone(&"example.com".into())
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_ignores_case_and_trailing_dot(name in DOMAIN) {
+            prop_assert_eq!(
+                DnsName::from(format!("{}.", name.to_uppercase()).as_str()),
+                DnsName::from(name.as_str())
+            );
+        }
+
+        #[test]
+        fn prop_subdomain_is_in_zone(label in "[a-z0-9]{1,10}", zone in DOMAIN) {
+            let zone = DnsName::from(zone.as_str());
+            let name = DnsName::from(format!("{}.{}", label, zone).as_str());
+            let wildcard = DnsName::from(format!("*.{}", zone).as_str());
+
+            prop_assert!(name.is_in_zone(&zone));
+            prop_assert!(wildcard.is_in_zone(&zone));
+            prop_assert!(!zone.is_in_zone(&name));
+            prop_assert_eq!(Some(label.as_str()), name.strip_zone(&zone));
+        }
+
+        #[test]
+        fn prop_suffix_is_not_a_zone(prefix in "[a-z0-9]{1,10}", zone in DOMAIN) {
+            let name = DnsName::from(format!("{}{}", prefix, zone).as_str());
+
+            prop_assert!(!name.is_in_zone(&DnsName::from(zone.as_str())));
+        }
+    }
+
+    #[test]
+    fn test_empty_name() {
+        assert_eq!(DnsName::from(""), DnsName::from("."));
+        assert!(!DnsName::from("example.com").is_in_zone(&"".into()));
+    }
+}
@@ -31,71 +31,448 @@
  *
  */
 
-use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-use crate::config::Config;
-use crate::result::DynResult;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Credentials, ZoneConfig, REDACTED};
+use crate::error::{Error, ProviderErrorKind, Result};
+use crate::name::DnsName;
+use crate::ttl::Ttl;
 
 pub type DnsZones = HashMap<Zone, DnsRecords>;
 
+pub type ZoneResults = HashMap<Zone, Result<DnsRecords>>;
+
 pub type DnsRecords = Vec<Record>;
 
 pub trait DnsProvider {
-    fn current(&self, config: &Config) -> DynResult<DnsZones>;
+    fn name(&self) -> &str;
+
+    fn current(&self, config: &Config) -> Result<ZoneResults>;
+
+    fn update(&self, zone: &Zone, record: Record) -> Result<()>;
+
+    fn delete(&self, _zone: &Zone, record: Record) -> Result<()> {
+        Err(Error::provider(
+            ProviderErrorKind::Unsupported,
+            format!(
+                "{} does not support deleting records ({})",
+                self.name(),
+                record
+            ),
+        ))
+    }
+
+    // Providers whose API distinguishes creating from updating records can override this
+    fn apply(&self, zone: &Zone, action: Action) -> Result<()> {
+        match action {
+            Action::Create(record)
+            | Action::Update {
+                desired: record, ..
+            } => self.update(zone, record),
+            Action::Delete(record) => self.delete(zone, record),
+            Action::Noop(_) => Ok(()),
+        }
+    }
+
+    // Describes what `apply_options` would change, it's only called if there is anything
+    fn option_changes(&self, _zone: &Zone, _zone_config: &ZoneConfig) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn apply_options(&self, _zone: &Zone, _zone_config: &ZoneConfig) -> Result<()> {
+        Ok(())
+    }
+
+    // TTLs accepted by the provider, configured records outside of it are rejected on start
+    fn ttl_range(&self) -> RangeInclusive<Ttl> {
+        Ttl::MIN..=Ttl::MAX
+    }
 
-    fn update(&self, zone: &Zone, record: Record) -> DynResult<()>;
+    fn snapshot(&self, zone: &Zone) -> Result<String> {
+        Err(Error::provider(
+            ProviderErrorKind::Unsupported,
+            format!("{} does not support snapshots ({})", self.name(), zone.name),
+        ))
+    }
+
+    fn rollback(&self, zone: &Zone, snapshot: &str) -> Result<()> {
+        Err(Error::provider(
+            ProviderErrorKind::Unsupported,
+            format!(
+                "{} does not support restoring snapshots ({} of {})",
+                self.name(),
+                snapshot,
+                zone.name
+            ),
+        ))
+    }
 }
 
-#[derive(Clone, Debug, Eq)]
+#[derive(Clone, Eq)]
 pub struct Zone {
     pub name: String,
     pub id: Option<String>,
+    pub credentials: Credentials,
 }
 
 impl Zone {
     pub fn new(name: String) -> Zone {
-        Zone { name, id: None }
+        Zone {
+            name,
+            id: None,
+            credentials: Credentials::new(),
+        }
     }
 
     pub fn with_id(name: String, id: String) -> Zone {
-        Zone { name, id: Some(id) }
+        Zone {
+            name,
+            id: Some(id),
+            credentials: Credentials::new(),
+        }
+    }
+}
+
+// Zones end up in logs and error messages, which must not contain the credentials
+impl Debug for Zone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let credentials = self
+            .credentials
+            .keys()
+            .map(|key| (key, REDACTED))
+            .collect::<BTreeMap<_, _>>();
+
+        f.debug_struct("Zone")
+            .field("name", &self.name)
+            .field("id", &self.id)
+            .field("credentials", &credentials)
+            .finish()
     }
 }
 
 impl Hash for Zone {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.name.hash(state)
+        DnsName::from(&self.name).hash(state)
     }
 }
 
 impl PartialEq for Zone {
     fn eq(&self, other: &Self) -> bool {
-        other.name == self.name
+        DnsName::from(&other.name) == DnsName::from(&self.name)
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type")]
 pub enum Record {
     A {
         name: String,
-        value: Ipv4Addr,
+        values: Vec<Ipv4Addr>,
         ttl: u32,
     },
     AAAA {
         name: String,
-        value: Ipv6Addr,
+        values: Vec<Ipv6Addr>,
+        ttl: u32,
+    },
+    PTR {
+        name: String,
+        target: String,
+        ttl: u32,
+    },
+    CNAME {
+        name: String,
+        target: String,
+        ttl: u32,
+    },
+    SRV {
+        name: String,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+        ttl: u32,
+    },
+    HTTPS {
+        name: String,
+        priority: u16,
+        target: String,
+        params: Vec<String>,
         ttl: u32,
     },
 }
 
+impl Record {
+    pub fn name(&self) -> &str {
+        match self {
+            Record::A { name, .. }
+            | Record::AAAA { name, .. }
+            | Record::PTR { name, .. }
+            | Record::CNAME { name, .. }
+            | Record::SRV { name, .. }
+            | Record::HTTPS { name, .. } => name,
+        }
+    }
+
+    pub fn record_type(&self) -> &'static str {
+        match self {
+            Record::A { .. } => "A",
+            Record::AAAA { .. } => "AAAA",
+            Record::PTR { .. } => "PTR",
+            Record::CNAME { .. } => "CNAME",
+            Record::SRV { .. } => "SRV",
+            Record::HTTPS { .. } => "HTTPS",
+        }
+    }
+
+    pub fn ttl(&self) -> u32 {
+        match self {
+            Record::A { ttl, .. }
+            | Record::AAAA { ttl, .. }
+            | Record::PTR { ttl, .. }
+            | Record::CNAME { ttl, .. }
+            | Record::SRV { ttl, .. }
+            | Record::HTTPS { ttl, .. } => *ttl,
+        }
+    }
+
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            Record::A { values, .. } => values.iter().map(|value| value.to_string()).collect(),
+            Record::AAAA { values, .. } => values.iter().map(|value| value.to_string()).collect(),
+            Record::PTR { target, .. } | Record::CNAME { target, .. } => vec![target.clone()],
+            Record::SRV {
+                priority,
+                weight,
+                port,
+                target,
+                ..
+            } => vec![format!(
+                "{} {} {} {}",
+                priority,
+                weight,
+                port,
+                absolute(target)
+            )],
+            Record::HTTPS {
+                priority,
+                target,
+                params,
+                ..
+            } => vec![[priority.to_string(), absolute(target)]
+                .into_iter()
+                .chain(params.iter().cloned())
+                .collect::<Vec<_>>()
+                .join(" ")],
+        }
+    }
+
+    // Parses a record from its type and values as returned by `values`, e.g. by plugins and services
+    pub fn parse(record_type: &str, name: &str, values: &[String], ttl: u32) -> Option<Record> {
+        match record_type {
+            "A" => parse_values(values).map(|values| Record::A {
+                name: name.into(),
+                values,
+                ttl,
+            }),
+            "AAAA" => parse_values(values).map(|values| Record::AAAA {
+                name: name.into(),
+                values,
+                ttl,
+            }),
+            "CNAME" => values.first().map(|target| Record::CNAME {
+                name: name.into(),
+                target: target.trim_end_matches('.').into(),
+                ttl,
+            }),
+            "PTR" => values.first().map(|target| Record::PTR {
+                name: name.into(),
+                target: target.trim_end_matches('.').into(),
+                ttl,
+            }),
+            "SRV" | "HTTPS" => values
+                .first()
+                .and_then(|value| Record::parse_service(record_type, name, value, ttl)),
+            _ => None,
+        }
+    }
+
+    // Parses the presentation format of SRV and HTTPS values, as listed by most providers
+    pub fn parse_service(record_type: &str, name: &str, value: &str, ttl: u32) -> Option<Record> {
+        let mut fields = value.split_whitespace();
+
+        match record_type {
+            "SRV" => {
+                let record = Record::SRV {
+                    name: name.into(),
+                    priority: fields.next()?.parse().ok()?,
+                    weight: fields.next()?.parse().ok()?,
+                    port: fields.next()?.parse().ok()?,
+                    target: relative(fields.next()?),
+                    ttl,
+                };
+
+                fields.next().is_none().then_some(record)
+            }
+            "HTTPS" => Some(Record::HTTPS {
+                name: name.into(),
+                priority: fields.next()?.parse().ok()?,
+                target: relative(fields.next()?),
+                params: fields.map(|param| param.replace('"', "")).collect(),
+                ttl,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Zones left out of a response are reported as not found, their records would all look missing otherwise
+pub fn add_missing_zones(results: &mut ZoneResults, zones: Vec<Zone>, source: &str) {
+    for zone in zones {
+        let message = format!("zone {} missing from the {}'s response", zone.name, source);
+
+        results
+            .entry(zone)
+            .or_insert_with(|| Err(Error::provider(ProviderErrorKind::NotFound, message)));
+    }
+}
+
+fn parse_values<T: FromStr>(values: &[String]) -> Option<Vec<T>> {
+    values
+        .iter()
+        .map(|value| T::from_str(value).ok())
+        .collect::<Option<Vec<_>>>()
+        .filter(|values| !values.is_empty())
+}
+
+// `.` is the owner name itself in SRV and HTTPS targets
+fn absolute(target: &str) -> String {
+    match target {
+        "." => target.into(),
+        target => format!("{}.", target.trim_end_matches('.')),
+    }
+}
+
+fn relative(target: &str) -> String {
+    match target {
+        "." => target.into(),
+        target => target.trim_end_matches('.').into(),
+    }
+}
+
 impl Display for Record {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Record::A { name, value, .. } => write!(f, "(A {}): {}", name, value),
-            Record::AAAA { name, value, .. } => write!(f, "(AAAA {}): {}", name, value),
+            Record::A { name, values, .. } => write!(f, "(A {}): {}", name, join_values(values)),
+            Record::AAAA { name, values, .. } => {
+                write!(f, "(AAAA {}): {}", name, join_values(values))
+            }
+            Record::PTR { name, target, .. } => write!(f, "(PTR {}): {}", name, target),
+            Record::CNAME { name, target, .. } => write!(f, "(CNAME {}): {}", name, target),
+            Record::SRV { name, .. } | Record::HTTPS { name, .. } => write!(
+                f,
+                "({} {}): {}",
+                self.record_type(),
+                name,
+                self.values().join(", ")
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Create(Record),
+    Update { current: Record, desired: Record },
+    Delete(Record),
+    Noop(Record),
+}
+
+impl Action {
+    pub fn record(&self) -> &Record {
+        match self {
+            Action::Create(record)
+            | Action::Update {
+                desired: record, ..
+            }
+            | Action::Delete(record)
+            | Action::Noop(record) => record,
         }
     }
 }
+
+pub(crate) fn join_values<T: Display>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone_debug() {
+        let zone = Zone {
+            credentials: Credentials::from([("api_key".into(), "secret".into())]),
+            ..Zone::new("example.com".into())
+        };
+
+        assert_eq!(
+            "Zone { name: \"example.com\", id: None, credentials: {\"api_key\": \"<redacted>\"} }",
+            format!("{:?}", zone)
+        );
+    }
+
+    #[test]
+    fn test_parse_record() {
+        let records = [
+            Record::A {
+                name: "home.example.com".into(),
+                values: vec![Ipv4Addr::new(192, 0, 2, 1)],
+                ttl: 300,
+            },
+            Record::CNAME {
+                name: "www.example.com".into(),
+                target: "home.example.com".into(),
+                ttl: 300,
+            },
+            Record::SRV {
+                name: "_sip._tcp.example.com".into(),
+                priority: 10,
+                weight: 5,
+                port: 5060,
+                target: "home.example.com".into(),
+                ttl: 300,
+            },
+        ];
+
+        for record in records {
+            assert_eq!(
+                Some(&record),
+                Record::parse(
+                    record.record_type(),
+                    record.name(),
+                    &record.values(),
+                    record.ttl()
+                )
+                .as_ref()
+            );
+        }
+
+        assert_eq!(None, Record::parse("A", "home.example.com", &[], 300));
+        assert_eq!(
+            None,
+            Record::parse("MX", "example.com", &["10 mail".into()], 300)
+        );
+    }
+}
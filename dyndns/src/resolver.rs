@@ -0,0 +1,278 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use simple_dns::rdata::{RData, OPT};
+use simple_dns::{Name, Packet, PacketFlag, Question, CLASS, RCODE, TYPE};
+
+use crate::name::DnsName;
+use crate::result::DynResult;
+
+const UDP_PAYLOAD_SIZE: u16 = 4096;
+
+pub(crate) const PUBLIC_RESOLVERS: [IpAddr; 2] = [
+    IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+    IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+];
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Answer {
+    pub addresses: Vec<IpAddr>,
+    pub ttl: Option<u32>,
+    pub nxdomain: bool,
+    pub server_failure: bool,
+    pub authenticated: bool,
+    pub signed: bool,
+}
+
+pub(crate) fn query(
+    resolver: IpAddr,
+    name: &str,
+    ipv6: bool,
+    dnssec: bool,
+    timeout: Duration,
+) -> DynResult<Answer> {
+    let record_type = if ipv6 { TYPE::AAAA } else { TYPE::A };
+
+    resolve(resolver, name, record_type, dnssec, timeout, |packet| {
+        answer(packet, name, record_type)
+    })
+}
+
+pub(crate) fn mail_exchanges(
+    resolver: IpAddr,
+    name: &str,
+    timeout: Duration,
+) -> DynResult<Vec<String>> {
+    resolve(resolver, name, TYPE::MX, false, timeout, |packet| {
+        exchanges(packet, name)
+    })
+}
+
+fn resolve<T>(
+    resolver: IpAddr,
+    name: &str,
+    record_type: TYPE,
+    dnssec: bool,
+    timeout: Duration,
+    parse: impl FnOnce(&Packet) -> T,
+) -> DynResult<T> {
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.subsec_nanos() as u16);
+
+    let request = request(id, name, record_type, dnssec)?;
+
+    let local_address: IpAddr = if resolver.is_ipv6() {
+        Ipv6Addr::UNSPECIFIED.into()
+    } else {
+        Ipv4Addr::UNSPECIFIED.into()
+    };
+
+    let socket = UdpSocket::bind(SocketAddr::new(local_address, 0))?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect(SocketAddr::new(resolver, 53))?;
+    socket.send(&request)?;
+
+    let mut response = vec![0; UDP_PAYLOAD_SIZE as usize];
+    let length = socket
+        .recv(&mut response)
+        .with_context(|| format!("no response from resolver {}", resolver))?;
+
+    let packet = Packet::parse(&response[..length]).context("invalid DNS response")?;
+    if packet.id() != id {
+        bail!("unexpected DNS response from resolver {}", resolver);
+    }
+
+    Ok(parse(&packet))
+}
+
+fn request(id: u16, name: &str, record_type: TYPE, dnssec: bool) -> DynResult<Vec<u8>> {
+    let mut packet = Packet::new_query(id);
+    packet.set_flags(PacketFlag::RECURSION_DESIRED);
+    packet.questions.push(Question::new(
+        Name::new(name.trim_end_matches('.')).context("invalid name")?,
+        record_type.into(),
+        CLASS::IN.into(),
+        false,
+    ));
+    *packet.opt_mut() = Some(OPT {
+        opt_codes: Vec::new(),
+        udp_packet_size: UDP_PAYLOAD_SIZE,
+        version: 0,
+    });
+
+    let mut request = packet
+        .build_bytes_vec()
+        .context("failed to build DNS query")?;
+    if dnssec {
+        // simple-dns can't set the DO flag, it's the highest bit of the OPT record's TTL flags, followed by the empty RDATA length
+        let flags = request.len() - 4;
+        request[flags] |= 0x80;
+    }
+
+    Ok(request)
+}
+
+fn answer(packet: &Packet, name: &str, record_type: TYPE) -> Answer {
+    let name = DnsName::from(name);
+
+    let records = packet
+        .answers
+        .iter()
+        .filter(|record| DnsName::from(record.name.to_string().as_str()) == name)
+        .collect::<Vec<_>>();
+
+    Answer {
+        addresses: records
+            .iter()
+            .filter_map(|record| match &record.rdata {
+                RData::A(a) => Some(IpAddr::V4(Ipv4Addr::from(a.address))),
+                RData::AAAA(aaaa) => Some(IpAddr::V6(Ipv6Addr::from(aaaa.address))),
+                _ => None,
+            })
+            .collect(),
+        ttl: records
+            .iter()
+            .filter(|record| record.rdata.type_code() == record_type)
+            .map(|record| record.ttl)
+            .min(),
+        nxdomain: packet.rcode() == RCODE::NameError,
+        server_failure: packet.rcode() == RCODE::ServerFailure,
+        authenticated: packet.has_flags(PacketFlag::AUTHENTIC_DATA),
+        signed: records.iter().any(|record| match &record.rdata {
+            RData::RRSIG(rrsig) => rrsig.type_covered == u16::from(record_type),
+            _ => false,
+        }),
+    }
+}
+
+fn exchanges(packet: &Packet, name: &str) -> Vec<String> {
+    let name = DnsName::from(name);
+
+    packet
+        .answers
+        .iter()
+        .filter(|record| DnsName::from(record.name.to_string().as_str()) == name)
+        .filter_map(|record| match &record.rdata {
+            RData::MX(mx) => Some(mx.exchange.to_string().trim_end_matches('.').to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use simple_dns::rdata::{A, MX};
+    use simple_dns::ResourceRecord;
+
+    #[test]
+    fn test_request() {
+        let request = request(1, "home.example.com.", TYPE::A, true).unwrap();
+
+        // root name, type OPT, payload size, extended RCODE and version, DO flag, no data
+        assert_eq!(
+            [0, 0, 41, 16, 0, 0, 0, 0x80, 0, 0, 0],
+            request[request.len() - 11..]
+        );
+        assert_eq!(1, Packet::parse(&request).unwrap().questions.len());
+    }
+
+    #[test]
+    fn test_answer() {
+        let mut packet = Packet::new_reply(1);
+        packet.set_flags(PacketFlag::AUTHENTIC_DATA);
+        packet.answers.push(ResourceRecord::new(
+            Name::new_unchecked("home.example.com"),
+            CLASS::IN,
+            300,
+            RData::A(A {
+                address: u32::from(Ipv4Addr::new(203, 0, 113, 10)),
+            }),
+        ));
+        packet.answers.push(ResourceRecord::new(
+            Name::new_unchecked("other.example.com"),
+            CLASS::IN,
+            300,
+            RData::A(A {
+                address: u32::from(Ipv4Addr::new(203, 0, 113, 20)),
+            }),
+        ));
+
+        let bytes = packet.build_bytes_vec().unwrap();
+        let packet = Packet::parse(&bytes).unwrap();
+
+        assert_eq!(
+            Answer {
+                addresses: vec![IpAddr::V4(Ipv4Addr::new(203, 0, 113, 10))],
+                ttl: Some(300),
+                authenticated: true,
+                ..Default::default()
+            },
+            answer(&packet, "Home.Example.com.", TYPE::A)
+        );
+    }
+
+    #[test]
+    fn test_exchanges() {
+        let mut packet = Packet::new_reply(1);
+        packet.answers.push(ResourceRecord::new(
+            Name::new_unchecked("example.com"),
+            CLASS::IN,
+            300,
+            RData::MX(MX {
+                preference: 10,
+                exchange: Name::new_unchecked("mail.example.com"),
+            }),
+        ));
+        packet.answers.push(ResourceRecord::new(
+            Name::new_unchecked("other.example.com"),
+            CLASS::IN,
+            300,
+            RData::MX(MX {
+                preference: 10,
+                exchange: Name::new_unchecked("mail.other.example.com"),
+            }),
+        ));
+
+        let bytes = packet.build_bytes_vec().unwrap();
+        let packet = Packet::parse(&bytes).unwrap();
+
+        assert_eq!(vec!["mail.example.com"], exchanges(&packet, "Example.com."));
+    }
+}
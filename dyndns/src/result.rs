@@ -31,4 +31,4 @@
  *
  */
 
-pub type DynResult<T> = anyhow::Result<T>;
+pub(crate) type DynResult<T> = anyhow::Result<T>;
@@ -0,0 +1,183 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::fmt::Write;
+
+use anyhow::{bail, Context, Error};
+use log::{error, info};
+
+use crate::config::Config;
+use crate::dyndns::state_key;
+use crate::name::DnsName;
+use crate::provider::{Action, DnsProvider, Zone, ZoneResults};
+use crate::result::DynResult;
+use crate::state::{RunLog, State};
+
+pub(crate) fn load_runs(config: &Config) -> DynResult<Vec<RunLog>> {
+    let state_file = match &config.state_file {
+        Some(state_file) => state_file,
+        None => bail!(
+            "the changes of each run are only recorded in the state_file, which isn't configured"
+        ),
+    };
+
+    Ok(State::load(state_file, &state_key(config))?.runs)
+}
+
+pub(crate) fn list_runs(runs: &[RunLog]) -> String {
+    if runs.is_empty() {
+        return "No runs recorded\n".into();
+    }
+
+    let mut listed = String::new();
+    for run in runs {
+        write!(
+            listed,
+            "{}  #{}  {} changes",
+            run.id,
+            run.sequence,
+            run.changes.len()
+        )
+        .unwrap();
+
+        if let Some(host) = &run.host {
+            write!(listed, " by {}", host).unwrap();
+        }
+
+        if !run.snapshots.is_empty() {
+            write!(
+                listed,
+                ", snapshots of {}",
+                run.snapshots.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+            .unwrap();
+        }
+
+        listed.push('\n');
+    }
+
+    listed
+}
+
+pub(crate) fn rollback<D: DnsProvider>(
+    config: &Config,
+    provider: &D,
+    run: &RunLog,
+    from_snapshots: bool,
+) -> DynResult<()> {
+    let current_zones = provider
+        .current(config)
+        .map_err(Error::from)
+        .context("failed to retrieve current DNS data")?;
+
+    let mut failures = 0;
+
+    let restored_zones = if from_snapshots {
+        for (zone, snapshot) in &run.snapshots {
+            match provider.rollback(&find_zone(config, &current_zones, zone), snapshot) {
+                Ok(()) => info!("Restored snapshot {} of zone {}", snapshot, zone),
+                Err(err) => {
+                    error!(
+                        "{:?}",
+                        Error::from(err).context(format!(
+                            "failed to restore snapshot {} of zone {}",
+                            snapshot, zone
+                        ))
+                    );
+                    failures += 1;
+                }
+            }
+        }
+
+        run.snapshots.keys().collect()
+    } else {
+        Vec::new()
+    };
+
+    for change in run
+        .changes
+        .iter()
+        .rev()
+        .filter(|change| !restored_zones.contains(&&change.zone))
+    {
+        let zone = find_zone(config, &current_zones, &change.zone);
+
+        let action = match (&change.previous, &change.current) {
+            (Some(previous), Some(current)) => Action::Update {
+                current: current.clone(),
+                desired: previous.clone(),
+            },
+            (Some(previous), None) => Action::Create(previous.clone()),
+            (None, Some(current)) => Action::Delete(current.clone()),
+            (None, None) => continue,
+        };
+
+        let message = match &action {
+            Action::Delete(current) => format!("Deleted {}", current),
+            action => format!("Restored {}", action.record()),
+        };
+        let result = provider.apply(&zone, action).map(|_| message);
+
+        match result {
+            Ok(message) => info!("{}", message),
+            Err(err) => {
+                error!("{:?}", Error::from(err).context("failed to revert change"));
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("failed to revert {} changes of run {}", failures, run.id);
+    }
+
+    Ok(())
+}
+
+pub(crate) fn find_zone(config: &Config, current_zones: &ZoneResults, name: &str) -> Zone {
+    let mut zone = current_zones
+        .keys()
+        .find(|zone| DnsName::from(&zone.name) == DnsName::from(name))
+        .cloned()
+        .unwrap_or_else(|| Zone::new(name.into()));
+
+    if let Some(zone_config) = config.zones.get(name) {
+        if zone.id.is_none() {
+            zone.id = zone_config.id.clone();
+        }
+
+        zone.credentials = zone_config.credentials.clone();
+    }
+
+    zone
+}
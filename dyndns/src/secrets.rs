@@ -0,0 +1,286 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{bail, Context, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::environment;
+use crate::result::DynResult;
+
+const SECRETS_KEY_VAR: &str = "SECRETS_KEY";
+
+const DEFAULT_SECRETS_FILE: &str = "secrets.yml";
+const CIPHER: &str = "aes-256-gcm";
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct EncryptedSecrets {
+    cipher: String,
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SecretsFile {
+    Encrypted(EncryptedSecrets),
+    Plain(BTreeMap<String, String>),
+}
+
+// Replaces `!secret <name>` with the value from the secrets file before the configuration is parsed,
+// the substituted values are returned as well so they can be redacted
+pub(crate) fn resolve_secrets(
+    content: &str,
+    config_path: &Path,
+) -> DynResult<(String, Vec<String>)> {
+    if !has_secrets(content) {
+        return Ok((content.to_string(), Vec::new()));
+    }
+
+    // the secrets file may itself be configured, so it is looked up with the references blanked out
+    let blanked = substitute(content, |_| Ok("\"\"".into()))?;
+    let secrets_file = serde_yaml::from_str::<serde_yaml::Value>(&blanked)
+        .ok()
+        .and_then(|config| config.get("secrets_file")?.as_str().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SECRETS_FILE));
+
+    let secrets_path = match config_path.parent() {
+        Some(dir) if config_path != Path::new("-") => dir.join(secrets_file),
+        _ => secrets_file,
+    };
+
+    let secrets = load_secrets(&secrets_path)?;
+
+    let mut resolved = Vec::new();
+    let substituted = substitute(content, |name| match secrets.get(name) {
+        Some(secret) => {
+            resolved.push(secret.clone());
+            Ok(serde_json::to_string(secret)?)
+        }
+        None => bail!("secret {} not found in {}", name, secrets_path.display()),
+    })?;
+
+    Ok((substituted, resolved))
+}
+
+pub(crate) fn has_secrets(content: &str) -> bool {
+    content
+        .lines()
+        .any(|line| secret_pattern().is_match(uncommented(line)))
+}
+
+pub(crate) fn encrypt_secrets(content: &str, passphrase: &str) -> DynResult<String> {
+    serde_yaml::from_str::<BTreeMap<String, String>>(content)
+        .context("secrets file must map names to strings")?;
+
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let data = cipher(passphrase, &salt)
+        .encrypt(&nonce, content.as_bytes())
+        .map_err(|_| Error::msg("failed to encrypt secrets"))?;
+
+    Ok(serde_yaml::to_string(&EncryptedSecrets {
+        cipher: CIPHER.into(),
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        data: STANDARD.encode(data),
+    })?)
+}
+
+pub(crate) fn decrypt_secrets(content: &str, passphrase: &str) -> DynResult<String> {
+    match serde_yaml::from_str(content).context("failed to parse secrets file")? {
+        SecretsFile::Encrypted(encrypted) => decrypt(&encrypted, passphrase),
+        SecretsFile::Plain(_) => bail!("secrets file isn't encrypted"),
+    }
+}
+
+pub(crate) fn secrets_key() -> DynResult<String> {
+    environment::var(SECRETS_KEY_VAR)
+        .filter(|key| !key.is_empty())
+        .with_context(|| {
+            format!(
+                "the passphrase of the secrets file must be set in {}",
+                environment::name(SECRETS_KEY_VAR)
+            )
+        })
+}
+
+fn load_secrets(path: &Path) -> DynResult<BTreeMap<String, String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read secrets file {}", path.display()))?;
+
+    let secrets = serde_yaml::from_str(&content)
+        .with_context(|| format!("failed to parse secrets file {}", path.display()))?;
+
+    match secrets {
+        SecretsFile::Plain(secrets) => Ok(secrets),
+        SecretsFile::Encrypted(encrypted) => {
+            let decrypted = decrypt(&encrypted, &secrets_key()?)
+                .with_context(|| format!("failed to decrypt secrets file {}", path.display()))?;
+
+            Ok(serde_yaml::from_str(&decrypted)?)
+        }
+    }
+}
+
+fn decrypt(encrypted: &EncryptedSecrets, passphrase: &str) -> DynResult<String> {
+    if encrypted.cipher != CIPHER {
+        bail!("unsupported cipher {}", encrypted.cipher);
+    }
+
+    let salt = STANDARD.decode(&encrypted.salt).context("invalid salt")?;
+    let nonce = STANDARD.decode(&encrypted.nonce).context("invalid nonce")?;
+    let data = STANDARD.decode(&encrypted.data).context("invalid data")?;
+
+    if nonce.len() != 12 {
+        bail!("invalid nonce");
+    }
+
+    let decrypted = cipher(passphrase, &salt)
+        .decrypt(Nonce::from_slice(&nonce), data.as_slice())
+        .map_err(|_| Error::msg("wrong passphrase or corrupted data"))?;
+
+    String::from_utf8(decrypted).context("decrypted secrets aren't valid UTF-8")
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Aes256Gcm {
+    let mut key = Key::<Aes256Gcm>::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+
+    Aes256Gcm::new(&key)
+}
+
+fn substitute<F>(content: &str, mut secret: F) -> DynResult<String>
+where
+    F: FnMut(&str) -> DynResult<String>,
+{
+    let mut substituted = String::new();
+
+    for line in content.split_inclusive('\n') {
+        let code = uncommented(line);
+
+        let mut result = Ok(());
+        let replaced = secret_pattern().replace_all(code, |captures: &Captures| {
+            secret(&captures[1]).unwrap_or_else(|err| {
+                result = Err(err);
+                String::new()
+            })
+        });
+        result?;
+
+        substituted.push_str(&replaced);
+        substituted.push_str(&line[code.len()..]);
+    }
+
+    Ok(substituted)
+}
+
+// Comments start with a # at the beginning of the line or after whitespace
+fn uncommented(line: &str) -> &str {
+    let comment = line
+        .char_indices()
+        .find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with(char::is_whitespace)))
+        .map(|(i, _)| i);
+
+    match comment {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+fn secret_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"!secret[ \t]+([A-Za-z0-9_.\-/]+)").unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_secrets() {
+        let dir = TempDir::new().unwrap();
+        let config_path = dir.path().join("dyndns.yml");
+
+        fs::File::create(dir.path().join("secrets.yml"))
+            .unwrap()
+            .write_all(b"gandi_api_key: \"abc: 'def'\"\n")
+            .unwrap();
+
+        let config = "zones:\n  example.com:\n    credentials:\n      api_key: !secret gandi_api_key # !secret unused\n";
+
+        assert_eq!(
+            (
+                "zones:\n  example.com:\n    credentials:\n      api_key: \"abc: 'def'\" # !secret unused\n".to_string(),
+                vec!["abc: 'def'".to_string()]
+            ),
+            resolve_secrets(config, &config_path).unwrap()
+        );
+
+        assert!(resolve_secrets("api_key: !secret missing\n", &config_path).is_err());
+        assert_eq!(
+            ("interval: 60\n".to_string(), Vec::new()),
+            resolve_secrets("interval: 60\n", Path::new("/nonexistent/dyndns.yml")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encrypt_secrets() {
+        let secrets = "gandi_api_key: abc\n";
+
+        let encrypted = encrypt_secrets(secrets, "passphrase").unwrap();
+        assert!(!encrypted.contains("abc"));
+
+        assert_eq!(secrets, decrypt_secrets(&encrypted, "passphrase").unwrap());
+        assert!(decrypt_secrets(&encrypted, "wrong").is_err());
+    }
+}
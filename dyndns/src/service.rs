@@ -0,0 +1,210 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+
+use crate::result::DynResult;
+use crate::template::render;
+
+const SYSTEMD_UNIT: &str = r#"[Unit]
+Description=${name} dynamic DNS updater
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=simple
+User=${user}
+ExecStart=${exe} run --config ${config}
+Restart=on-failure
+RestartSec=30
+
+[Install]
+WantedBy=multi-user.target
+"#;
+
+const OPENRC_SCRIPT: &str = r#"#!/sbin/openrc-run
+
+description="${name} dynamic DNS updater"
+command="${exe}"
+command_args="run --config ${config}"
+command_user="${user}"
+command_background=true
+pidfile="/run/${name}.pid"
+
+depend() {
+    need net
+}
+"#;
+
+#[derive(Clone, Copy)]
+pub(crate) enum ServiceManager {
+    Systemd,
+    Openrc,
+}
+
+impl ServiceManager {
+    pub(crate) fn path(self, name: &str) -> PathBuf {
+        match self {
+            ServiceManager::Systemd => {
+                PathBuf::from(format!("/etc/systemd/system/{}.service", name))
+            }
+            ServiceManager::Openrc => PathBuf::from(format!("/etc/init.d/{}", name)),
+        }
+    }
+
+    pub(crate) fn enable_command(self, name: &str) -> String {
+        match self {
+            ServiceManager::Systemd => format!("systemctl enable --now {}", name),
+            ServiceManager::Openrc => {
+                format!(
+                    "rc-update add {} default && rc-service {} start",
+                    name, name
+                )
+            }
+        }
+    }
+}
+
+pub(crate) struct Service<'a> {
+    pub name: &'a str,
+    pub exe: &'a Path,
+    pub config: &'a Path,
+    pub user: &'a str,
+}
+
+impl Service<'_> {
+    pub(crate) fn render(&self, manager: ServiceManager) -> DynResult<String> {
+        let exe = quotable(self.exe)?;
+        let config = quotable(self.config)?;
+
+        let template = match manager {
+            ServiceManager::Systemd => SYSTEMD_UNIT,
+            ServiceManager::Openrc => OPENRC_SCRIPT,
+        };
+
+        render(template, |name| match name {
+            "name" => Some(self.name),
+            "exe" => Some(exe),
+            "config" => Some(config),
+            "user" => Some(self.user),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn install(&self, manager: ServiceManager) -> DynResult<PathBuf> {
+        if !is_root() {
+            bail!(
+                "installing the service requires root privileges, run it with sudo or pass --print"
+            );
+        }
+
+        let path = manager.path(self.name);
+        fs::write(&path, self.render(manager)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+
+        #[cfg(unix)]
+        if let ServiceManager::Openrc = manager {
+            use std::os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                .with_context(|| format!("failed to make {} executable", path.display()))?;
+        }
+
+        Ok(path)
+    }
+}
+
+// The user invoking sudo, not root, is the one the service should run as
+pub(crate) fn current_user() -> Option<String> {
+    env::var("SUDO_USER")
+        .or_else(|_| env::var("USER"))
+        .ok()
+        .filter(|user| !user.is_empty())
+}
+
+// Neither unit files nor OpenRC scripts handle paths that would need quoting consistently
+fn quotable(path: &Path) -> DynResult<&str> {
+    match path.to_str() {
+        Some(path) if !path.contains(|c: char| c.is_whitespace() || "\"'\\$`".contains(c)) => {
+            Ok(path)
+        }
+        _ => bail!(
+            "{} contains characters not supported in service files",
+            path.display()
+        ),
+    }
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_service() {
+        let service = Service {
+            name: "dyndns-gandi-livedns",
+            exe: Path::new("/usr/local/bin/dyndns-gandi-livedns"),
+            config: Path::new("/etc/dyndns.yml"),
+            user: "dyndns",
+        };
+
+        let unit = service.render(ServiceManager::Systemd).unwrap();
+        assert!(unit.contains("\nUser=dyndns\n"));
+        assert!(unit.contains(
+            "\nExecStart=/usr/local/bin/dyndns-gandi-livedns run --config /etc/dyndns.yml\n"
+        ));
+
+        let script = service.render(ServiceManager::Openrc).unwrap();
+        assert!(script.starts_with("#!/sbin/openrc-run\n"));
+        assert!(script.contains("\npidfile=\"/run/dyndns-gandi-livedns.pid\"\n"));
+
+        let service = Service {
+            config: Path::new("/etc/dyn dns.yml"),
+            ..service
+        };
+        assert!(service.render(ServiceManager::Systemd).is_err());
+    }
+}
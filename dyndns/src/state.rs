@@ -0,0 +1,338 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::flap::RunReport;
+use crate::provider::Record;
+use crate::result::DynResult;
+
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct State {
+    // The machine that wrote the state, to notice state files shared between machines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_forced_update: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reports: Vec<RunReport>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runs: Vec<RunLog>,
+    // Counts the recorded runs, orders them even if the clock jumped between them
+    #[serde(default)]
+    pub sequence: u64,
+    // The records as last written by this machine, to detect when others overwrite them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub written: Vec<Record>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RunLog {
+    pub id: String,
+    #[serde(default)]
+    pub sequence: u64,
+    pub time: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub changes: Vec<LoggedChange>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub snapshots: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LoggedChange {
+    pub zone: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<Record>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<Record>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SignedState {
+    #[serde(flatten)]
+    state: State,
+    #[serde(default)]
+    signature: String,
+}
+
+pub struct StateKey(Vec<u8>);
+
+impl StateKey {
+    pub fn new(secret: Option<&str>) -> StateKey {
+        let secret = secret
+            .map(String::from)
+            .or_else(machine_id)
+            .or_else(|| hostname::get().ok()?.into_string().ok())
+            .unwrap_or_default();
+
+        StateKey(
+            Sha256::new()
+                .chain_update("ez-dyndns state\0")
+                .chain_update(secret)
+                .finalize()
+                .to_vec(),
+        )
+    }
+
+    fn mac(&self, content: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).unwrap();
+        mac.update(content.as_bytes());
+        mac
+    }
+}
+
+fn machine_id() -> Option<String> {
+    MACHINE_ID_PATHS
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|machine_id| machine_id.trim().to_string())
+        .find(|machine_id| !machine_id.is_empty())
+}
+
+impl State {
+    pub fn load<P: AsRef<Path>>(path: P, key: &StateKey) -> crate::Result<State> {
+        State::read(path.as_ref(), key).map_err(state_error)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, key: &StateKey) -> crate::Result<()> {
+        self.write(path.as_ref(), key).map_err(state_error)
+    }
+
+    fn read(path: &Path, key: &StateKey) -> DynResult<State> {
+        let signed_state: SignedState = match fs::read_to_string(path) {
+            Ok(content) => serde_yaml::from_str(&content)
+                .with_context(|| format!("failed to parse state file {}", path.display()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read state file {}", path.display()))
+            }
+        };
+
+        let content =
+            serde_yaml::to_string(&signed_state.state).context("failed to serialize state")?;
+
+        let is_valid = STANDARD
+            .decode(&signed_state.signature)
+            .is_ok_and(|signature| key.mac(&content).verify_slice(&signature).is_ok());
+
+        if is_valid {
+            Ok(signed_state.state)
+        } else {
+            Err(Error::msg(format!(
+                "state file {} has an invalid signature, ignoring it",
+                path.display()
+            )))
+        }
+    }
+
+    fn write(&self, path: &Path, key: &StateKey) -> DynResult<()> {
+        let temp_path = path.with_extension("tmp");
+
+        let content = serde_yaml::to_string(self).context("failed to serialize state")?;
+        let signature = STANDARD.encode(key.mac(&content).finalize().into_bytes());
+
+        let content = serde_yaml::to_string(&SignedState {
+            state: self.clone(),
+            signature,
+        })
+        .context("failed to serialize state")?;
+
+        fs::write(&temp_path, content)
+            .and_then(|_| fs::rename(&temp_path, path))
+            .with_context(|| format!("failed to write state file {}", path.display()))
+    }
+
+    pub fn remaining_interval(&self, interval: Duration, now: DateTime<Utc>) -> Option<Duration> {
+        let elapsed = (now - self.last_success?).to_std().ok()?;
+
+        interval
+            .checked_sub(elapsed)
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    // A time in the future means the clock was set back since, waiting for it to catch up could take years
+    pub fn is_force_due(&self, force_update_interval: Duration, now: DateTime<Utc>) -> bool {
+        match self.last_forced_update {
+            Some(last_forced_update) => (now - last_forced_update)
+                .to_std()
+                .map_or(true, |elapsed| elapsed >= force_update_interval),
+            None => true,
+        }
+    }
+}
+
+fn state_error(err: Error) -> crate::Error {
+    crate::Error::Io(io::Error::other(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_remaining_interval() {
+        let interval = Duration::from_secs(1800);
+        let now = Utc.with_ymd_and_hms(2021, 11, 20, 12, 0, 0).unwrap();
+
+        assert_eq!(State::default().remaining_interval(interval, now), None);
+
+        let state = State {
+            last_success: Some(Utc.with_ymd_and_hms(2021, 11, 20, 11, 50, 0).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            state.remaining_interval(interval, now),
+            Some(Duration::from_secs(1200))
+        );
+
+        let state = State {
+            last_success: Some(Utc.with_ymd_and_hms(2021, 11, 20, 11, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(state.remaining_interval(interval, now), None);
+
+        let state = State {
+            last_success: Some(Utc.with_ymd_and_hms(2021, 11, 20, 12, 10, 0).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(state.remaining_interval(interval, now), None);
+    }
+
+    #[test]
+    fn test_is_force_due() {
+        let interval = Duration::from_secs(30 * 86400);
+        let now = Utc.with_ymd_and_hms(2021, 11, 20, 12, 0, 0).unwrap();
+
+        assert!(State::default().is_force_due(interval, now));
+
+        let state = State {
+            last_forced_update: Some(Utc.with_ymd_and_hms(2021, 11, 1, 12, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        assert!(!state.is_force_due(interval, now));
+
+        let state = State {
+            last_forced_update: Some(Utc.with_ymd_and_hms(2021, 10, 21, 12, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        assert!(state.is_force_due(interval, now));
+
+        let state = State {
+            last_forced_update: Some(Utc.with_ymd_and_hms(2031, 11, 20, 12, 0, 0).unwrap()),
+            ..Default::default()
+        };
+        assert!(state.is_force_due(interval, now));
+    }
+
+    #[test]
+    fn test_signed_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.yml");
+
+        let key = StateKey::new(Some("secret"));
+        let state = State {
+            last_success: Some(Utc.with_ymd_and_hms(2021, 11, 20, 11, 50, 0).unwrap()),
+            ..Default::default()
+        };
+
+        state.save(&path, &key).unwrap();
+        assert_eq!(state, State::load(&path, &key).unwrap());
+
+        assert!(State::load(&path, &StateKey::new(Some("other secret"))).is_err());
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, content.replace("2021-11-20", "2031-11-20")).unwrap();
+        assert!(State::load(&path, &key).is_err());
+
+        fs::write(&path, "last_success: 2031-11-20T11:50:00Z\n").unwrap();
+        assert!(State::load(&path, &key).is_err());
+    }
+
+    #[test]
+    fn test_run_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.yml");
+
+        let key = StateKey::new(Some("secret"));
+        let state = State {
+            runs: vec![RunLog {
+                id: "20211120T115000Z".into(),
+                sequence: 1,
+                time: Utc.with_ymd_and_hms(2021, 11, 20, 11, 50, 0).unwrap(),
+                host: Some("nas".into()),
+                changes: vec![LoggedChange {
+                    zone: "example.com".into(),
+                    previous: Some(Record::A {
+                        name: "home.example.com".into(),
+                        values: vec!["198.51.100.1".parse().unwrap()],
+                        ttl: 300,
+                    }),
+                    current: Some(Record::A {
+                        name: "home.example.com".into(),
+                        values: vec!["203.0.113.10".parse().unwrap()],
+                        ttl: 300,
+                    }),
+                }],
+                snapshots: BTreeMap::from([
+                    ("example.com".into(), "snapshot".into()),
+                    ("example.net".into(), "other snapshot".into()),
+                ]),
+            }],
+            sequence: 1,
+            ..Default::default()
+        };
+
+        state.save(&path, &key).unwrap();
+        assert_eq!(state, State::load(&path, &key).unwrap());
+    }
+}
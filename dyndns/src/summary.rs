@@ -0,0 +1,414 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, Write};
+
+use log::info;
+
+use crate::approval::Approver;
+use crate::logging::color_enabled;
+use crate::name::DnsName;
+use crate::provider::{Action, Record, Zone};
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone)]
+pub(crate) enum Change {
+    Create,
+    Update(String),
+    Refresh,
+    Delete,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Outcome {
+    Applied,
+    Failed,
+    Skipped,
+}
+
+struct Entry {
+    zone: String,
+    change: Change,
+    record: Record,
+    outcome: Outcome,
+}
+
+struct OptionEntry {
+    change: String,
+    outcome: Outcome,
+}
+
+pub(crate) enum Proposal {
+    Action(Zone, Action, Change),
+    Options(Zone, Vec<String>),
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Confirmation {
+    #[default]
+    Disabled,
+    Ask,
+    All,
+    None,
+}
+
+#[derive(Default)]
+pub(crate) struct Summary {
+    entries: RefCell<Vec<Entry>>,
+    options: RefCell<Vec<OptionEntry>>,
+    unchanged: Cell<usize>,
+    confirmation: Cell<Confirmation>,
+    approver: Option<Approver>,
+    proposed: RefCell<Vec<Proposal>>,
+}
+
+impl Summary {
+    pub(crate) fn interactive() -> Summary {
+        Summary {
+            confirmation: Cell::new(Confirmation::Ask),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn approval(approver: Approver) -> Summary {
+        Summary {
+            approver: Some(approver),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn needs_approval(&self) -> bool {
+        self.approver.is_some()
+    }
+
+    // Changes needing approval are collected and proposed together once the run knows all of them
+    pub(crate) fn propose(&self, proposal: Proposal) {
+        self.proposed.borrow_mut().push(proposal);
+    }
+
+    // Blocks until the proposed changes are decided, returns them if they were approved
+    pub(crate) fn approved(&self) -> Vec<Proposal> {
+        let proposed = self.proposed.take();
+
+        let approver = match &self.approver {
+            Some(approver) if !proposed.is_empty() => approver,
+            _ => return proposed,
+        };
+
+        let changes = proposed
+            .iter()
+            .flat_map(|proposal| match proposal {
+                Proposal::Action(zone, action, change) => {
+                    vec![(zone.name.clone(), line(change, action.record(), false))]
+                }
+                Proposal::Options(zone, changes) => changes
+                    .iter()
+                    .map(|change| (zone.name.clone(), option_line(change, false)))
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        if approver.approve(&changes) {
+            return proposed;
+        }
+
+        for proposal in proposed {
+            match proposal {
+                Proposal::Action(zone, action, change) => {
+                    self.add(&zone, change, action.record().clone(), Outcome::Skipped)
+                }
+                Proposal::Options(_, changes) => self.add_options(changes, Outcome::Skipped),
+            }
+        }
+
+        Vec::new()
+    }
+
+    pub(crate) fn confirm(&self, change: &Change, record: &Record) -> bool {
+        self.ask(&line(change, record, color_enabled()))
+    }
+
+    pub(crate) fn confirm_options(&self, changes: &[String]) -> bool {
+        let lines = changes
+            .iter()
+            .map(|change| option_line(change, color_enabled()))
+            .collect::<Vec<_>>();
+
+        self.ask(&lines.join("\n"))
+    }
+
+    fn ask(&self, line: &str) -> bool {
+        match self.confirmation.get() {
+            Confirmation::Disabled | Confirmation::All => return true,
+            Confirmation::None => return false,
+            Confirmation::Ask => {}
+        }
+
+        let stdin = io::stdin();
+
+        loop {
+            print!("{} Apply? [y/n/a] ", line);
+            io::stdout().flush().ok();
+
+            let mut answer = String::new();
+            if stdin.lock().read_line(&mut answer).unwrap_or(0) == 0 {
+                println!();
+                self.confirmation.set(Confirmation::None);
+                return false;
+            }
+
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return true,
+                "n" | "no" => return false,
+                "a" | "all" => {
+                    self.confirmation.set(Confirmation::All);
+                    return true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn add(&self, zone: &Zone, change: Change, record: Record, outcome: Outcome) {
+        self.entries.borrow_mut().push(Entry {
+            zone: zone.name.clone(),
+            change,
+            record,
+            outcome,
+        });
+    }
+
+    pub(crate) fn add_options(&self, changes: Vec<String>, outcome: Outcome) {
+        self.options.borrow_mut().extend(
+            changes
+                .into_iter()
+                .map(|change| OptionEntry { change, outcome }),
+        );
+    }
+
+    pub(crate) fn unchanged(&self) {
+        self.unchanged.set(self.unchanged.get() + 1);
+    }
+
+    pub(crate) fn has_failures(&self) -> bool {
+        self.entries
+            .borrow()
+            .iter()
+            .any(|entry| entry.outcome == Outcome::Failed)
+            || self
+                .options
+                .borrow()
+                .iter()
+                .any(|entry| entry.outcome == Outcome::Failed)
+    }
+
+    pub(crate) fn has_failed(&self, zone: &Zone, name: &str) -> bool {
+        self.entries.borrow().iter().any(|entry| {
+            entry.outcome == Outcome::Failed
+                && entry.zone == zone.name
+                && DnsName::from(entry.record.name()) == DnsName::from(name)
+        })
+    }
+
+    pub(crate) fn applied(&self) -> Vec<(String, Change, Record)> {
+        self.entries
+            .borrow()
+            .iter()
+            .filter(|entry| entry.outcome == Outcome::Applied)
+            .map(|entry| {
+                (
+                    entry.zone.clone(),
+                    entry.change.clone(),
+                    entry.record.clone(),
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn log(&self) {
+        for line in self.lines(color_enabled()) {
+            info!("{}", line);
+        }
+    }
+
+    fn lines(&self, color: bool) -> Vec<String> {
+        let entries = self.entries.borrow();
+        let options = self.options.borrow();
+
+        let mut lines = entries
+            .iter()
+            .map(|entry| {
+                outcome_line(entry.outcome, color, |color| {
+                    line(&entry.change, &entry.record, color)
+                })
+            })
+            .chain(options.iter().map(|entry| {
+                outcome_line(entry.outcome, color, |color| {
+                    option_line(&entry.change, color)
+                })
+            }))
+            .collect::<Vec<_>>();
+
+        let unchanged = self.unchanged.get();
+        match (entries.len() + options.len(), unchanged) {
+            (0, _) => lines.push("No changes".into()),
+            (_, 0) => {}
+            (_, 1) => lines.push("1 record unchanged".into()),
+            (_, unchanged) => lines.push(format!("{} records unchanged", unchanged)),
+        }
+
+        lines
+    }
+}
+
+fn outcome_line(outcome: Outcome, color: bool, line: impl Fn(bool) -> String) -> String {
+    match outcome {
+        Outcome::Applied => line(color),
+        Outcome::Failed if color => format!("{}{} (failed){}", RED, line(false), RESET),
+        Outcome::Failed => format!("{} (failed)", line(false)),
+        Outcome::Skipped => format!("{} (skipped)", line(false)),
+    }
+}
+
+fn option_line(change: &str, color: bool) -> String {
+    if color {
+        format!("{}~ {}{}", YELLOW, change, RESET)
+    } else {
+        format!("~ {}", change)
+    }
+}
+
+fn line(change: &Change, record: &Record, color: bool) -> String {
+    let value = record.values().join(", ");
+
+    let (sign, color_code, value) = match change {
+        Change::Create => ('+', GREEN, value),
+        Change::Update(previous) => ('~', YELLOW, format!("{} -> {}", previous, value)),
+        Change::Refresh => ('=', YELLOW, value),
+        Change::Delete => ('-', RED, value),
+    };
+
+    let line = format!(
+        "{} {} {} {}",
+        sign,
+        record.name(),
+        record.record_type(),
+        value
+    );
+
+    if color {
+        format!("{}{}{}", color_code, line, RESET)
+    } else {
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_lines() {
+        let summary = Summary::default();
+        assert_eq!(vec!["No changes"], summary.lines(false));
+
+        let zone = Zone::new("example.com".into());
+
+        summary.add(
+            &zone,
+            Change::Update("1.2.3.4".into()),
+            Record::A {
+                name: "home.example.com".into(),
+                values: vec!["5.6.7.8".parse().unwrap()],
+                ttl: 300,
+            },
+            Outcome::Applied,
+        );
+        summary.add(
+            &zone,
+            Change::Create,
+            Record::AAAA {
+                name: "nas.example.com".into(),
+                values: vec!["2001:db8::1".parse().unwrap()],
+                ttl: 300,
+            },
+            Outcome::Failed,
+        );
+        summary.add(
+            &zone,
+            Change::Delete,
+            Record::A {
+                name: "old.example.com".into(),
+                values: vec!["1.2.3.4".parse().unwrap()],
+                ttl: 300,
+            },
+            Outcome::Skipped,
+        );
+        summary.unchanged();
+
+        assert_eq!(
+            vec![
+                "~ home.example.com A 1.2.3.4 -> 5.6.7.8",
+                "+ nas.example.com AAAA 2001:db8::1 (failed)",
+                "- old.example.com A 1.2.3.4 (skipped)",
+                "1 record unchanged"
+            ],
+            summary.lines(false)
+        );
+
+        assert_eq!(
+            "\x1b[33m~ home.example.com A 1.2.3.4 -> 5.6.7.8\x1b[0m",
+            summary.lines(true)[0]
+        );
+    }
+
+    #[test]
+    fn test_option_lines() {
+        let summary = Summary::default();
+
+        summary.add_options(
+            vec!["example.com A ALIAS home.example.com".into()],
+            Outcome::Skipped,
+        );
+
+        assert_eq!(
+            vec!["~ example.com A ALIAS home.example.com (skipped)"],
+            summary.lines(false)
+        );
+    }
+}
@@ -0,0 +1,157 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_ENDPOINT: &str = "http://localhost:4318/v1/traces";
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TelemetryConfig {
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+}
+
+fn default_endpoint() -> String {
+    DEFAULT_ENDPOINT.into()
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::fmt::Display;
+    use std::sync::Mutex;
+
+    use anyhow::{Context, Error};
+    use opentelemetry::trace::{Status, TraceContextExt, Tracer};
+    use opentelemetry::{global, KeyValue};
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+
+    use super::TelemetryConfig;
+
+    const TRACER_NAME: &str = "ez-dyndns";
+
+    static PROVIDER: Mutex<Option<SdkTracerProvider>> = Mutex::new(None);
+
+    pub fn init(config: &TelemetryConfig, service_name: &str) -> crate::Result<()> {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(&config.endpoint)
+            .build()
+            .context("failed to create OTLP exporter")
+            .map_err(|err| crate::Error::Config(err.into()))?;
+
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(service_name.to_string())
+                    .with_attribute(KeyValue::new("host.name", crate::host::host()))
+                    .build(),
+            )
+            .build();
+
+        global::set_tracer_provider(provider.clone());
+        *PROVIDER.lock().unwrap() = Some(provider);
+
+        Ok(())
+    }
+
+    pub fn shutdown() {
+        if let Some(provider) = PROVIDER.lock().unwrap().take() {
+            if let Err(err) = provider.shutdown() {
+                log::error!(
+                    "{:?}",
+                    Error::from(err).context("failed to export remaining spans")
+                );
+            }
+        }
+    }
+
+    pub fn span<T, E, F>(
+        name: &'static str,
+        attributes: &[(&'static str, String)],
+        f: F,
+    ) -> Result<T, E>
+    where
+        E: Display,
+        F: FnOnce() -> Result<T, E>,
+    {
+        let tracer = global::tracer(TRACER_NAME);
+        let span = tracer
+            .span_builder(name)
+            .with_attributes(
+                attributes
+                    .iter()
+                    .map(|(key, value)| KeyValue::new(*key, value.clone())),
+            )
+            .start(&tracer);
+        let cx = opentelemetry::Context::current_with_span(span);
+        let _guard = cx.clone().attach();
+
+        let result = f();
+        if let Err(err) = &result {
+            cx.span().set_status(Status::error(format!("{:#}", err)));
+        }
+
+        result
+    }
+}
+
+#[cfg(feature = "otel")]
+pub use otel::{init, shutdown};
+
+#[cfg(feature = "otel")]
+pub(crate) use otel::span;
+
+// The same configuration is shared by executables built with and without the feature
+#[cfg(not(feature = "otel"))]
+pub fn init(_config: &TelemetryConfig, _service_name: &str) -> crate::Result<()> {
+    log::warn!("Ignoring telemetry, this executable was built without OpenTelemetry support");
+    Ok(())
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn shutdown() {}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn span<T, E, F>(
+    _name: &'static str,
+    _attributes: &[(&'static str, String)],
+    f: F,
+) -> Result<T, E>
+where
+    F: FnOnce() -> Result<T, E>,
+{
+    f()
+}
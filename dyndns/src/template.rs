@@ -0,0 +1,144 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use anyhow::{bail, Error};
+
+use crate::result::DynResult;
+
+pub(crate) const BUILTIN_VARIABLES: [&str; 3] = ["ipv4", "ipv6", "ipv6_prefix"];
+
+pub(crate) fn render_ipv4(
+    template: &str,
+    vars: &BTreeMap<String, String>,
+    address: &Ipv4Addr,
+) -> DynResult<Ipv4Addr> {
+    let address = address.to_string();
+
+    let rendered = render(template, |name| match name {
+        "ipv4" => Some(address.as_str()),
+        _ => vars.get(name).map(String::as_str),
+    })?;
+
+    rendered
+        .parse()
+        .map_err(|_| Error::msg(format!("{} is not an IPv4 address", rendered)))
+}
+
+pub(crate) fn render_ipv6(
+    template: &str,
+    vars: &BTreeMap<String, String>,
+    address: &Ipv6Addr,
+) -> DynResult<Ipv6Addr> {
+    let prefix = ipv6_prefix(address);
+    let address = address.to_string();
+
+    let rendered = render(template, |name| match name {
+        "ipv6" => Some(address.as_str()),
+        "ipv6_prefix" => Some(prefix.as_str()),
+        _ => vars.get(name).map(String::as_str),
+    })?;
+
+    rendered
+        .parse()
+        .map_err(|_| Error::msg(format!("{} is not an IPv6 address", rendered)))
+}
+
+// The /64 network part as four uncompressed groups, so appending e.g. "::10" yields a valid address
+fn ipv6_prefix(address: &Ipv6Addr) -> String {
+    let segments = address.segments();
+
+    format!(
+        "{:x}:{:x}:{:x}:{:x}",
+        segments[0], segments[1], segments[2], segments[3]
+    )
+}
+
+pub(crate) fn render<'a, F>(template: &str, lookup: F) -> DynResult<String>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        rendered.push_str(&rest[..start]);
+
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => bail!("unterminated variable in {}", template),
+        };
+
+        let name = rest[start + 2..end].trim();
+        match lookup(name) {
+            Some(value) => rendered.push_str(value),
+            None => bail!("unknown variable {} in {}", name, template),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let vars = BTreeMap::from([("suffix".to_string(), "::10".to_string())]);
+
+        assert_eq!(
+            "2001:db8:0:5::10".parse::<Ipv6Addr>().unwrap(),
+            render_ipv6(
+                "${ipv6_prefix}${suffix}",
+                &vars,
+                &"2001:db8::5:1:2:3:4".parse().unwrap()
+            )
+            .unwrap()
+        );
+
+        assert_eq!(
+            "203.0.113.10".parse::<Ipv4Addr>().unwrap(),
+            render_ipv4("${ ipv4 }", &vars, &"203.0.113.10".parse().unwrap()).unwrap()
+        );
+
+        assert!(render_ipv4("${ipv6}", &vars, &Ipv4Addr::UNSPECIFIED).is_err());
+        assert!(render_ipv6("${ipv6_prefix", &vars, &Ipv6Addr::UNSPECIFIED).is_err());
+        assert!(render_ipv6("${ipv6_prefix}::1::2", &vars, &Ipv6Addr::UNSPECIFIED).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::fmt::{Display, Formatter};
+#[cfg(feature = "blocking")]
+use std::sync::Arc;
+#[cfg(feature = "blocking")]
+use std::time::SystemTime;
+
+use anyhow::Error;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rustls::Certificate;
+#[cfg(feature = "blocking")]
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+#[cfg(feature = "blocking")]
+use crate::http;
+
+const PIN_PREFIX: &str = "sha256/";
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SpkiPin([u8; 32]);
+
+impl TryFrom<String> for SpkiPin {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hash = value
+            .strip_prefix(PIN_PREFIX)
+            .and_then(|hash| STANDARD.decode(hash).ok())
+            .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+            .ok_or_else(|| {
+                Error::msg(format!(
+                    "invalid key pin {}, expected sha256/<base64 encoded SHA-256 hash>",
+                    value
+                ))
+            })?;
+
+        Ok(SpkiPin(hash))
+    }
+}
+
+impl From<SpkiPin> for String {
+    fn from(pin: SpkiPin) -> Self {
+        pin.to_string()
+    }
+}
+
+impl Display for SpkiPin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", PIN_PREFIX, STANDARD.encode(self.0))
+    }
+}
+
+impl SpkiPin {
+    #[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+    fn of_certificate(certificate: &Certificate) -> Option<SpkiPin> {
+        let (_, certificate) = X509Certificate::from_der(&certificate.0).ok()?;
+        let hash = Sha256::digest(certificate.public_key().raw);
+
+        Some(SpkiPin(hash.into()))
+    }
+}
+
+#[cfg(feature = "blocking")]
+struct PinnedVerifier {
+    verifier: WebPkiVerifier,
+    pins: Vec<SpkiPin>,
+}
+
+#[cfg(feature = "blocking")]
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.verifier.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+
+        let is_pinned = [end_entity]
+            .into_iter()
+            .chain(intermediates)
+            .filter_map(SpkiPin::of_certificate)
+            .any(|pin| self.pins.contains(&pin));
+
+        if is_pinned {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(format!(
+                "no certificate presented by {:?} matches the pinned keys",
+                server_name
+            )))
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn pinned_agent(
+    pins: &[SpkiPin],
+    proxy: Option<&str>,
+    user_agent: Option<&str>,
+) -> ureq::Agent {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+
+    let verifier = PinnedVerifier {
+        verifier: WebPkiVerifier::new(roots, None),
+        pins: pins.to_vec(),
+    };
+
+    let tls_config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    http::agent_builder(proxy, user_agent)
+        .tls_config(Arc::new(tls_config))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERTIFICATE: &str = "MIIBjDCCATGgAwIBAgIUWB+rHUVHOO0qlfJjC3sl6cUsT9kwCgYIKoZIzj0EAwIwGzEZMBcGA1UEAwwQZWNoby5leGFtcGxlLmNvbTAeFw0yNjEwMTYxMDI0MjdaFw0zNjEwMTMxMDI0MjdaMBsxGTAXBgNVBAMMEGVjaG8uZXhhbXBsZS5jb20wWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAAQ6aCZ5JBrPcCUXuBFg4noCEqGWYlGEGjV+qDV2CSPTAFQNBzzx5pLiPl/N1+uPhXSax8Kb5CfEydIVdaDTP+d7o1MwUTAdBgNVHQ4EFgQUi3pCYyvVGLQmMLmBNkrGPYuhkykwHwYDVR0jBBgwFoAUi3pCYyvVGLQmMLmBNkrGPYuhkykwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNJADBGAiEAqrrW82w0YJg5G17+yS1gIPrEqAGZbAZDO57qPh2Cn8gCIQDv0L3vx1D0Tk7IBxNTvzuMqcgEvQkIQZeMApsdN7x9vQ==";
+
+    #[test]
+    fn test_spki_pin() {
+        let pin =
+            SpkiPin::try_from("sha256/P/2G8VG7WpzZtg6J3pJavKYAkPWO6oRAfj+n/fKF4us=".to_string())
+                .unwrap();
+
+        let certificate = Certificate(STANDARD.decode(CERTIFICATE).unwrap());
+        assert_eq!(Some(pin.clone()), SpkiPin::of_certificate(&certificate));
+
+        assert_eq!(
+            "sha256/P/2G8VG7WpzZtg6J3pJavKYAkPWO6oRAfj+n/fKF4us=",
+            pin.to_string()
+        );
+
+        assert!(
+            SpkiPin::try_from("P/2G8VG7WpzZtg6J3pJavKYAkPWO6oRAfj+n/fKF4us=".to_string()).is_err()
+        );
+        assert!(SpkiPin::try_from("sha256/AAAA".to_string()).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+// TTL in seconds, limited to the range allowed by RFC 2181
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "u32", into = "u32")]
+pub struct Ttl(u32);
+
+impl Ttl {
+    pub const MIN: Ttl = Ttl(1);
+    pub const MAX: Ttl = Ttl(2_147_483_647);
+
+    // Panics if out of range, use `Ttl::try_from` for values not known at compile time
+    pub const fn from_secs(seconds: u32) -> Ttl {
+        assert!(seconds >= Ttl::MIN.0 && seconds <= Ttl::MAX.0);
+        Ttl(seconds)
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for Ttl {
+    type Error = String;
+
+    fn try_from(seconds: u32) -> Result<Self, Self::Error> {
+        if (Ttl::MIN.0..=Ttl::MAX.0).contains(&seconds) {
+            Ok(Ttl(seconds))
+        } else {
+            Err(format!(
+                "TTL of {} seconds is out of range ({} to {})",
+                seconds,
+                Ttl::MIN,
+                Ttl::MAX
+            ))
+        }
+    }
+}
+
+impl FromStr for Ttl {
+    type Err = String;
+
+    fn from_str(seconds: &str) -> Result<Self, Self::Err> {
+        seconds
+            .parse::<u32>()
+            .map_err(|_| format!("invalid TTL {}", seconds))
+            .and_then(Ttl::try_from)
+    }
+}
+
+impl From<Ttl> for u32 {
+    fn from(ttl: Ttl) -> Self {
+        ttl.0
+    }
+}
+
+impl Display for Ttl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_range() {
+        assert_eq!(Ok(Ttl::from_secs(300)), Ttl::try_from(300u32));
+        assert_eq!(Ok(Ttl::MAX), Ttl::try_from(2_147_483_647u32));
+        assert!(Ttl::try_from(0u32).is_err());
+        assert!(Ttl::try_from(2_147_483_648u32).is_err());
+        assert!("-1".parse::<Ttl>().is_err());
+
+        assert_eq!(
+            "TTL of 0 seconds is out of range (1 to 2147483647)",
+            serde_yaml::from_str::<Ttl>("0").unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use anyhow::Context;
+use igd_next::{search_gateway, Gateway, PortMappingProtocol, SearchOptions};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+use crate::result::DynResult;
+
+const DEFAULT_TIMEOUT: u64 = 5;
+const DEFAULT_LEASE: u64 = 3600;
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct UpnpConfig {
+    #[serde(default)]
+    pub check_cgnat: bool,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_timeout")]
+    pub timeout: Duration,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub port_mappings: Vec<PortMapping>,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PortMapping {
+    pub port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_port: Option<u16>,
+    #[serde(default)]
+    pub protocol: Protocol,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<IpAddr>,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    #[serde(default = "default_lease")]
+    pub lease: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl Display for PortMapping {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let protocol = match self.protocol {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        };
+
+        write!(f, "{}/{}", self.port, protocol)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl From<Protocol> for PortMappingProtocol {
+    fn from(protocol: Protocol) -> Self {
+        match protocol {
+            Protocol::Tcp => PortMappingProtocol::TCP,
+            Protocol::Udp => PortMappingProtocol::UDP,
+        }
+    }
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(DEFAULT_TIMEOUT)
+}
+
+fn default_lease() -> Duration {
+    Duration::from_secs(DEFAULT_LEASE)
+}
+
+fn find_gateway(config: &UpnpConfig) -> DynResult<Gateway> {
+    search_gateway(SearchOptions {
+        timeout: Some(config.timeout),
+        ..Default::default()
+    })
+    .context("failed to find UPnP gateway")
+}
+
+pub(crate) fn router_external_ip(config: &UpnpConfig) -> DynResult<IpAddr> {
+    find_gateway(config)?
+        .get_external_ip()
+        .context("failed to query external address of UPnP gateway")
+}
+
+// Renews every configured mapping, router leases expire silently otherwise
+pub(crate) fn refresh_port_mappings(config: &UpnpConfig) {
+    if config.port_mappings.is_empty() {
+        return;
+    }
+
+    let gateway = match find_gateway(config) {
+        Ok(gateway) => gateway,
+        Err(err) => {
+            error!("{:?}", err.context("failed to refresh port mappings"));
+            return;
+        }
+    };
+
+    for mapping in &config.port_mappings {
+        if let Err(err) = add_port_mapping(&gateway, mapping) {
+            error!(
+                "{:?}",
+                err.context(format!("failed to refresh port mapping {}", mapping))
+            );
+        }
+    }
+}
+
+fn add_port_mapping(gateway: &Gateway, mapping: &PortMapping) -> DynResult<()> {
+    let host = match mapping.host {
+        Some(host) => host,
+        None => local_address(gateway.addr)?,
+    };
+    let local_addr = SocketAddr::new(host, mapping.internal_port.unwrap_or(mapping.port));
+
+    gateway.add_port(
+        mapping.protocol.into(),
+        mapping.port,
+        local_addr,
+        // the lease is a 32 bit number of seconds, 0 would mean a permanent mapping
+        mapping.lease.as_secs().clamp(1, u32::MAX as u64) as u32,
+        mapping.description.as_deref().unwrap_or("ez-dyndns-rs"),
+    )?;
+
+    debug!("Refreshed port mapping {} to {}", mapping, local_addr);
+
+    Ok(())
+}
+
+// The address of the interface facing the gateway, connecting a UDP socket doesn't send anything
+fn local_address(gateway: SocketAddr) -> DynResult<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect(gateway)?;
+
+    Ok(socket
+        .local_addr()
+        .context("failed to determine local address facing the UPnP gateway")?
+        .ip())
+}
+
+pub(crate) fn check_cgnat(config: &UpnpConfig, external_ip: &Ipv4Addr) {
+    match router_external_ip(config) {
+        Ok(IpAddr::V4(router_ip)) if is_behind_cgnat(&router_ip, external_ip) => {
+            warn!("!!!");
+            error!(
+                "!!! The router reports WAN address {} but the external address is {}",
+                router_ip, external_ip
+            );
+            warn!("!!! This network is most likely behind carrier-grade NAT (CGNAT),");
+            warn!("!!! the updated DNS records won't make it reachable from the internet");
+            warn!("!!!");
+        }
+        Ok(_) => {}
+        Err(err) => error!("{:?}", err.context("failed to check for CGNAT")),
+    }
+}
+
+// Routers without a WAN connection report 0.0.0.0, which says nothing about the NAT in between
+fn is_behind_cgnat(router_ip: &Ipv4Addr, external_ip: &Ipv4Addr) -> bool {
+    !router_ip.is_unspecified() && router_ip != external_ip
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_behind_cgnat() {
+        let external_ip = Ipv4Addr::new(203, 0, 113, 10);
+
+        assert!(is_behind_cgnat(&Ipv4Addr::new(100, 64, 0, 1), &external_ip));
+        assert!(!is_behind_cgnat(&external_ip, &external_ip));
+        assert!(!is_behind_cgnat(&Ipv4Addr::UNSPECIFIED, &external_ip));
+    }
+
+    #[test]
+    fn test_port_mapping_defaults() {
+        let config: UpnpConfig = serde_yaml::from_str(
+            "{ port_mappings: [{ port: 443 }, { port: 51820, protocol: udp, lease: 600 }] }",
+        )
+        .unwrap();
+
+        assert_eq!(Duration::from_secs(DEFAULT_TIMEOUT), config.timeout);
+        assert_eq!(
+            vec![
+                PortMapping {
+                    port: 443,
+                    internal_port: None,
+                    protocol: Protocol::Tcp,
+                    host: None,
+                    lease: Duration::from_secs(DEFAULT_LEASE),
+                    description: None,
+                },
+                PortMapping {
+                    port: 51820,
+                    internal_port: None,
+                    protocol: Protocol::Udp,
+                    host: None,
+                    lease: Duration::from_secs(600),
+                    description: None,
+                }
+            ],
+            config.port_mappings
+        );
+        assert_eq!("51820/udp", config.port_mappings[1].to_string());
+    }
+}
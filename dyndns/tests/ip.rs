@@ -0,0 +1,111 @@
+/*
+ * BSD 3-Clause License
+ *
+ * Copyright (c) 2021, Alex Katlein
+ * All rights reserved.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice, this
+ *    list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+ * DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+ * FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+ * DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+ * SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+ * CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+ * OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+ * OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+ *
+ */
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use dyndns::ip::{HttpIpSource, IpSource, ResponseParser};
+use httpmock::prelude::*;
+
+#[test]
+fn test_http_ip_source() {
+    let server = MockServer::start();
+
+    let ipv4_mock = server.mock(|when, then| {
+        when.method(GET).path("/ipv4").header_exists("User-Agent");
+        then.status(200).body("203.0.113.10\n");
+    });
+
+    let ipv6_mock = server.mock(|when, then| {
+        when.method(GET).path("/ipv6");
+        then.status(200).body("2001:db8::10\n");
+    });
+
+    let ip_source = HttpIpSource::new(server.url("/ipv4"), server.url("/ipv6"));
+
+    assert_eq!(
+        Some(Ipv4Addr::new(203, 0, 113, 10)),
+        ip_source.ipv4().unwrap()
+    );
+    assert_eq!(
+        Some("2001:db8::10".parse::<Ipv6Addr>().unwrap()),
+        ip_source.ipv6().unwrap()
+    );
+
+    ipv4_mock.assert();
+    ipv6_mock.assert();
+}
+
+#[test]
+fn test_http_ip_source_invalid_response() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(GET).path("/ipv4");
+        then.status(200).body("<html>captive portal</html>");
+    });
+
+    let ip_source = HttpIpSource::new(server.url("/ipv4"), server.url("/ipv6"));
+
+    assert!(ip_source.ipv4().is_err());
+}
+
+#[test]
+fn test_http_ip_source_parsers() {
+    let server = MockServer::start();
+
+    server.mock(|when, then| {
+        when.method(GET).path("/ipv4");
+        then.status(200)
+            .body(r#"{"ip": "203.0.113.10", "country": "AT"}"#);
+    });
+
+    server.mock(|when, then| {
+        when.method(GET).path("/ipv6");
+        then.status(200)
+            .body("<tr><td>WAN IPv6</td><td>2001:db8::10</td></tr>");
+    });
+
+    let ip_source = HttpIpSource::new(server.url("/ipv4"), server.url("/ipv6")).with_parsers(
+        ResponseParser::Json("/ip".into()),
+        ResponseParser::Regex("WAN IPv6</td><td>([^<]+)".into()),
+    );
+
+    assert_eq!(
+        Some(Ipv4Addr::new(203, 0, 113, 10)),
+        ip_source.ipv4().unwrap()
+    );
+    assert_eq!(
+        Some("2001:db8::10".parse::<Ipv6Addr>().unwrap()),
+        ip_source.ipv6().unwrap()
+    );
+}